@@ -0,0 +1,275 @@
+//
+// file: ai.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module contains the computer player. It finds the best move by searching through every
+// possible continuation of the game using the minimax algorithm with alpha-beta pruning.
+// For more information, see: https://en.wikipedia.org/wiki/Alpha%E2%80%93beta_pruning
+
+use std::fmt;
+
+// `crate` refers to the root of this library (lib.rs), so this imports the types defined there
+use crate::{Game, Winner, MAX_COLS, MAX_ROWS};
+
+// A win is worth more than anything the heuristic below could ever return. We subtract the number
+// of moves it takes to reach the win so that faster wins (and slower losses) are preferred.
+pub const WIN_SCORE: i32 = 1_000_000;
+
+// A game can't last longer than there are tiles, so every win scores more than this. The heuristic
+// is kept at or below it, even on the largest boards where its lines could add up to more, so that
+// it is never mistaken for a win or a loss.
+const HEURISTIC_LIMIT: i32 = WIN_SCORE - (MAX_ROWS * MAX_COLS) as i32 - 1;
+
+// `analyze` looks as many moves ahead as it can without visiting more than roughly this many
+// positions. That is enough to solve the 3x3 board from the start.
+const ANALYZE_BUDGET: u64 = 5_000_000;
+
+// How good a move is for the piece making it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Evaluation {
+    /// The move wins in this many moves, counting the moves of both players. A move that wins
+    /// straight away wins in 1.
+    Win(usize),
+
+    /// Neither player can win if both play perfectly
+    Draw,
+
+    /// The move loses in this many moves, counting the moves of both players
+    Loss(usize),
+
+    /// The search stopped after `depth` moves without finding the result, so the move was scored
+    /// using the heuristic instead. Higher scores are better.
+    Heuristic { score: i32, depth: usize },
+}
+
+impl Evaluation {
+    // Turns a score from `search` into an evaluation. `exact` is true if the search reached the
+    // end of every game, so a score of 0 means that the game is a draw.
+    fn from_score(score: i32, depth: usize, exact: bool) -> Self {
+        // Scores close to WIN_SCORE are wins, and the distance from WIN_SCORE is the number of
+        // moves it takes
+        if score > HEURISTIC_LIMIT {
+            Evaluation::Win((WIN_SCORE - score) as usize)
+        }
+        else if score < -HEURISTIC_LIMIT {
+            Evaluation::Loss((WIN_SCORE + score) as usize)
+        }
+        else if exact {
+            Evaluation::Draw
+        }
+        else {
+            Evaluation::Heuristic {score, depth}
+        }
+    }
+}
+
+// This is how an evaluation reads after a move, e.g. "3B wins in 2"
+impl fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Evaluation::Win(moves) => write!(f, "wins in {}", moves),
+            Evaluation::Draw => write!(f, "draws"),
+            Evaluation::Loss(moves) => write!(f, "loses in {}", moves),
+            Evaluation::Heuristic {score, depth} => {
+                write!(f, "scores {:+} at depth {}", score, depth)
+            },
+        }
+    }
+}
+
+// Returns the best move for the current piece, or None if the game is already over.
+// This searches all the way to the end of the game, so it plays perfectly but is only practical on
+// small boards like the default 3x3 board. Use `best_move_with_depth` for larger boards.
+pub fn best_move(game: &Game) -> Option<(usize, usize)> {
+    // The game can't last longer than the number of tiles on the board, so this is deep enough to
+    // reach the end of every possible game
+    best_move_with_depth(game, game.rows() * game.cols())
+}
+
+// Returns the best move for the current piece while only looking `depth` moves ahead. Positions
+// that are not finished after `depth` moves are scored using a simple heuristic instead.
+pub fn best_move_with_depth(game: &Game, depth: usize) -> Option<(usize, usize)> {
+    search(game, depth).map(|(best, _)| best)
+}
+
+// Searches `depth` moves ahead and returns the best move along with its score. The score is from
+// the point of view of the current piece: positive is good for it and negative is bad for it.
+// A score of `WIN_SCORE - n` means that the current piece wins in n moves (counting both sides).
+pub fn search(game: &Game, depth: usize) -> Option<((usize, usize), i32)> {
+    // We search on a copy of the game so that the caller's game (and its undo history) is left
+    // untouched. Each move we try is undone again before trying the next one.
+    let mut game = game.clone();
+    // We always need to look at least one move ahead to have a move to return
+    let depth = depth.max(1);
+
+    let mut best: Option<((usize, usize), i32)> = None;
+    let mut alpha = -WIN_SCORE;
+    for (row, col) in ordered_moves(&game) {
+        game.make_move(row, col).expect("legal moves should always be valid");
+        let score = -negamax(&mut game, depth - 1, -WIN_SCORE, -alpha, 1);
+        game.undo();
+
+        // Only replace the best move if this one is strictly better, so that the first of several
+        // equally good moves is chosen. This keeps the computer's choices predictable.
+        let better = match best {
+            Some((_, best_score)) => score > best_score,
+            None => true,
+        };
+        if better {
+            best = Some(((row, col), score));
+        }
+        alpha = alpha.max(score);
+    }
+    best
+}
+
+// Scores every legal move for the current piece, best move first. On small boards, such as the 3x3
+// board, the search reaches the end of the game, so every move is a win, draw or loss. On larger
+// boards, the search looks as far ahead as it can in a reasonable amount of time, and any move
+// without a known result is scored by the heuristic along with how deep the search went.
+pub fn analyze(game: &Game) -> Vec<((usize, usize), Evaluation)> {
+    analyze_with_depth(game, analyze_depth(game))
+}
+
+// Scores every legal move for the current piece while only looking `depth` moves ahead (including
+// the move being scored), best move first
+pub fn analyze_with_depth(game: &Game, depth: usize) -> Vec<((usize, usize), Evaluation)> {
+    let depth = depth.max(1);
+    // If the search can see every remaining move, any position it doesn't find a win in is a draw
+    let empty = game.tiles().iter().flatten().filter(|tile| tile.is_none()).count();
+    let exact = depth >= empty;
+
+    let mut game = game.clone();
+    if game.is_finished() {
+        return Vec::new();
+    }
+    let mut scored: Vec<((usize, usize), i32)> = ordered_moves(&game).into_iter().map(|(row, col)| {
+        game.make_move(row, col).expect("legal moves should always be valid");
+        // Unlike `search`, each move is searched with the full window so that its exact score is
+        // found, rather than just whether it is better than the best move so far
+        let score = -negamax(&mut game, depth - 1, -WIN_SCORE, WIN_SCORE, 1);
+        game.undo();
+        ((row, col), score)
+    }).collect();
+
+    // Sorting by the negated score puts the best move first. The sort is stable, so moves with the
+    // same score stay in the center-first order.
+    scored.sort_by_key(|&(_, score)| -score);
+    scored.into_iter()
+        .map(|(mv, score)| (mv, Evaluation::from_score(score, depth, exact)))
+        .collect()
+}
+
+// Works out how deep `analyze` (and the computer player) can search. Without pruning, the number
+// of positions grows with every extra move: with n empty tiles there are n choices for the first
+// move, n - 1 for the second and so on. With gravity there are at most as many choices as there
+// are columns.
+pub(crate) fn analyze_depth(game: &Game) -> usize {
+    let empty = game.tiles().iter().flatten().filter(|tile| tile.is_none()).count();
+    let mut positions: u64 = 1;
+    let mut depth = 0;
+    while depth < empty {
+        let choices = if game.has_gravity() {
+            game.cols().min(empty - depth)
+        }
+        else {
+            empty - depth
+        };
+        positions = positions.saturating_mul(choices as u64);
+        if positions > ANALYZE_BUDGET {
+            break;
+        }
+        depth += 1;
+    }
+    depth
+}
+
+// This is the heart of the search. It returns the score of the game from the point of view of the
+// current piece. This variant of minimax is called "negamax" because instead of writing separate
+// code for the maximizing and minimizing player, we negate the score each time the turn changes.
+// alpha is the score the current piece is already guaranteed elsewhere and beta is the score the
+// opponent is already guaranteed elsewhere. Once a move scores at least beta, the opponent will
+// never allow this position, so we can stop searching it. That is what "pruning" means.
+fn negamax(game: &mut Game, depth: usize, mut alpha: i32, beta: i32, ply: i32) -> i32 {
+    if let Some(winner) = game.winner() {
+        return match winner {
+            Winner::Tie => 0,
+            // Only the piece that just moved can have won, and that is never the current piece
+            _ => -(WIN_SCORE - ply),
+        };
+    }
+    if depth == 0 {
+        return evaluate(game);
+    }
+
+    for (row, col) in ordered_moves(game) {
+        game.make_move(row, col).expect("legal moves should always be valid");
+        let score = -negamax(game, depth - 1, -beta, -alpha, ply + 1);
+        game.undo();
+
+        if score >= beta {
+            return beta;
+        }
+        alpha = alpha.max(score);
+    }
+    alpha
+}
+
+// Returns the legal moves with the ones closest to the center first. Central moves are usually
+// the strongest, and trying the strongest moves first lets alpha-beta prune much more of the tree.
+pub(crate) fn ordered_moves(game: &Game) -> Vec<(usize, usize)> {
+    let mut moves = game.legal_moves();
+    // Doubling the row and column lets us measure the distance to the center without fractions
+    let (rows, cols) = (game.rows() as isize, game.cols() as isize);
+    // `sort_by_key` is stable, so moves that are equally close stay in board order
+    moves.sort_by_key(|&(row, col)| {
+        (2 * row as isize - (rows - 1)).abs() + (2 * col as isize - (cols - 1)).abs()
+    });
+    moves
+}
+
+// Scores an unfinished position from the point of view of the current piece. Every possible line
+// of `win_length` tiles that only contains one player's pieces could still become a win for that
+// player, and lines that are closer to being complete are worth more.
+pub(crate) fn evaluate(game: &Game) -> i32 {
+    let tiles = game.tiles();
+    let (rows, cols) = (game.rows() as isize, game.cols() as isize);
+    let length = game.win_length() as isize;
+    let me = Some(game.current_piece());
+    let them = Some(game.current_piece().other());
+
+    let mut score = 0;
+    for &(d_row, d_col) in &[(0, 1), (1, 0), (1, 1), (1, -1)] {
+        for row in 0..rows {
+            for col in 0..cols {
+                // Skip lines that would run off the edge of the board
+                let (end_row, end_col) = (row + d_row * (length - 1), col + d_col * (length - 1));
+                if end_row < 0 || end_row >= rows || end_col < 0 || end_col >= cols {
+                    continue;
+                }
+
+                let (mut mine, mut theirs) = (0, 0);
+                for i in 0..length {
+                    let tile = tiles[(row + d_row * i) as usize][(col + d_col * i) as usize];
+                    if tile == me {
+                        mine += 1;
+                    }
+                    else if tile == them {
+                        theirs += 1;
+                    }
+                }
+
+                // A line that contains both pieces can never be completed by either player
+                if theirs == 0 {
+                    score += mine * mine;
+                }
+                else if mine == 0 {
+                    score -= theirs * theirs;
+                }
+            }
+        }
+    }
+    score.clamp(-HEURISTIC_LIMIT, HEURISTIC_LIMIT)
+}
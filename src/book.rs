@@ -0,0 +1,303 @@
+//
+// file: book.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module contains opening books. An opening book is a list of good moves for the positions at
+// the start of a game, so that a computer player doesn't have to search the same positions again in
+// every game. Each position can have several moves with a weight, and a move is picked at random in
+// proportion to its weight, which keeps games between bots from always starting the same way.
+//
+// Books are saved as text, one position per line, in the same style as game records (see the record
+// module):
+//
+//     [Variant "3x3k3"]
+//
+//     3/3/3 x 3x3k3: 2B 4, 1A 1
+//     1x1/3/3 o 3x3k3: 2B 1
+//
+// Each line is a position string, a colon and then the moves with their weights. Lines starting
+// with `#` are comments. Positions that are the same after turning or flipping the board (see the
+// symmetry module) share one line, so a book only has to list one of them.
+//
+// A book can be built in two ways: from the results of a search (`from_analysis`), which only adds
+// the moves that are as good as the best one, or from finished games (`add_game`), which adds the
+// moves played by the side that didn't lose.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::ai::Evaluation;
+use crate::symmetry::{self, Symmetry};
+use crate::{format_move, parse_move, Game, Piece, PositionError, Winner};
+
+// The header that names the rules of the book, just like in a game record
+const VARIANT: &str = "Variant";
+
+// This type represents the possible errors that can occur when loading an opening book
+#[derive(Debug)]
+pub enum BookError {
+    /// The file could not be read or written
+    Io(io::Error),
+
+    /// A line of the book could not be understood. `line` starts at 1.
+    Syntax { line: usize, message: String },
+
+    /// A position in the book isn't a position that can come up in a game. `line` starts at 1.
+    InvalidPosition { line: usize, error: PositionError },
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BookError::Io(error) => write!(f, "{}", error),
+            BookError::Syntax {line, message} => write!(f, "Line {}: {}", line, message),
+            BookError::InvalidPosition {line, error} => write!(f, "Line {}: invalid position: {}", line, error),
+        }
+    }
+}
+
+impl Error for BookError {}
+
+// `From` lets the `?` operator turn an io::Error into a BookError for us
+impl From<io::Error> for BookError {
+    fn from(error: io::Error) -> Self {
+        BookError::Io(error)
+    }
+}
+
+// The moves for one position. Everything is stored the way it looks in the canonical variant of
+// the position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    position: String,
+    moves: Vec<((usize, usize), u32)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpeningBook {
+    // The rules string of the game the book is for (see `Game::rules_string`)
+    rules: String,
+    // The entries by the hash key of the canonical variant of their position
+    entries: HashMap<u64, Entry>,
+}
+
+impl OpeningBook {
+    // Creates an empty book for games with the same rules as `game`
+    pub fn new(game: &Game) -> Self {
+        Self {rules: game.rules_string(), entries: HashMap::new()}
+    }
+
+    // Builds a book for every position up to `plies` moves after `game` (usually an empty board).
+    // `analyze` scores the moves of a position best first, like `ai::analyze`, and every move that
+    // is just as good as the best one goes in the book with a weight of 1. Both players' moves are
+    // covered, so the book still helps after an opponent plays something unexpected.
+    pub fn from_analysis<F>(game: &Game, plies: usize, mut analyze: F) -> Self
+    where F: FnMut(&Game) -> Vec<((usize, usize), Evaluation)>
+    {
+        let mut book = Self::new(game);
+        let mut seen = HashSet::new();
+        book.add_analysis(&mut game.clone(), plies, &mut analyze, &mut seen);
+        book
+    }
+
+    fn add_analysis<F>(&mut self, game: &mut Game, plies: usize, analyze: &mut F, seen: &mut HashSet<u64>)
+    where F: FnMut(&Game) -> Vec<((usize, usize), Evaluation)>
+    {
+        let (canonical, symmetry) = symmetry::canonical(game);
+        if plies == 0 || game.is_finished() || !seen.insert(canonical.hash_key()) {
+            return;
+        }
+
+        let moves = analyze(game);
+        if let Some(&(_, best)) = moves.first() {
+            for &(mv, evaluation) in &moves {
+                if evaluation == best {
+                    self.add(&canonical, symmetry, mv, 1);
+                }
+            }
+        }
+
+        for (row, col) in game.legal_moves() {
+            game.make_move(row, col).expect("legal moves should always be valid");
+            self.add_analysis(game, plies - 1, analyze, seen);
+            game.undo();
+        }
+    }
+
+    // Adds the first `plies` moves of a finished game, e.g. one played by two bots. The winner's
+    // moves are worth 2 and the moves of a tied game are worth 1. The loser's moves are left out,
+    // since at least one of them was a mistake. Games with other rules or that aren't finished
+    // are skipped.
+    pub fn add_game(&mut self, game: &Game, plies: usize) {
+        let winner = match game.winner() {
+            Some(winner) if game.rules_string() == self.rules => winner,
+            _ => return,
+        };
+
+        // The history goes back to the start of the game, so we undo everything and then play it
+        // forward again
+        let mut replay = game.clone();
+        while replay.undo().is_some() {}
+        for &(row, col) in game.history().iter().take(plies) {
+            let weight = match (winner, replay.current_piece()) {
+                (Winner::Tie, _) => 1,
+                (Winner::X, Piece::X) | (Winner::O, Piece::O) => 2,
+                _ => 0,
+            };
+            if weight > 0 {
+                let (canonical, symmetry) = symmetry::canonical(&replay);
+                self.add(&canonical, symmetry, (row, col), weight);
+            }
+            replay.make_move(row, col).expect("moves in the history should still be valid");
+        }
+    }
+
+    // Adds `weight` to a move. `symmetry` turns the position the move was made in into `canonical`.
+    fn add(&mut self, canonical: &Game, symmetry: Symmetry, mv: (usize, usize), weight: u32) {
+        let mv = symmetry.apply(mv, canonical.rows(), canonical.cols());
+        let entry = self.entries.entry(canonical.hash_key()).or_insert_with(|| Entry {
+            position: canonical.to_position_string(),
+            moves: Vec::new(),
+        });
+        match entry.moves.iter_mut().find(|(existing, _)| *existing == mv) {
+            // Weights that add up to more than a u32 can hold stop at the largest one there is
+            // instead of overflowing
+            Some((_, existing)) => *existing = existing.saturating_add(weight),
+            None => entry.moves.push((mv, weight)),
+        }
+    }
+
+    // The rules string of the game the book is for
+    pub fn rules(&self) -> &str {
+        &self.rules
+    }
+
+    // The number of positions in the book. Symmetric positions only count once.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Returns the book moves for the position along with their weights, or nothing if the position
+    // isn't in the book
+    pub fn moves(&self, game: &Game) -> Vec<((usize, usize), u32)> {
+        if game.is_finished() || game.rules_string() != self.rules {
+            return Vec::new();
+        }
+        let (canonical, symmetry) = symmetry::canonical(game);
+        let entry = match self.entries.get(&canonical.hash_key()) {
+            Some(entry) => entry,
+            None => return Vec::new(),
+        };
+        // The moves are stored for the canonical variant, so they have to be turned back
+        let inverse = symmetry.inverse();
+        entry.moves.iter()
+            .map(|&(mv, weight)| (inverse.apply(mv, game.rows(), game.cols()), weight))
+            .collect()
+    }
+
+    // Picks one of the book moves for the position, or returns None if the position isn't in the
+    // book. `roll` can be any random number; each move is picked for a share of the numbers that
+    // matches its weight.
+    pub fn pick(&self, game: &Game, roll: u64) -> Option<(usize, usize)> {
+        let moves = self.moves(game);
+        let total: u64 = moves.iter().map(|&(_, weight)| weight as u64).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut roll = roll % total;
+        for (mv, weight) in moves {
+            if roll < weight as u64 {
+                return Some(mv);
+            }
+            roll -= weight as u64;
+        }
+        unreachable!("the roll is always less than the total weight")
+    }
+
+    // Reads a book from the text format described at the top of this file
+    pub fn parse(text: &str) -> Result<Self, BookError> {
+        let mut book: Option<OpeningBook> = None;
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let syntax = |message: &str| BookError::Syntax {line: i + 1, message: message.to_string()};
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            // The Variant header has to come before the positions
+            let book = match &mut book {
+                Some(book) => book,
+                None => {
+                    let rules = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']'))
+                        .and_then(|inner| inner.strip_prefix(VARIANT))
+                        .map(|value| value.trim().trim_matches('"'))
+                        .ok_or_else(|| syntax("a book has to start with a Variant header"))?;
+                    let game = Game::from_rules_string(rules)
+                        .map_err(|error| BookError::InvalidPosition {line: i + 1, error})?;
+                    book = Some(Self::new(&game));
+                    continue;
+                },
+            };
+
+            let (position, moves) = line.split_once(':').ok_or_else(|| syntax("position has no moves"))?;
+            let game = Game::from_position_string(position.trim())
+                .map_err(|error| BookError::InvalidPosition {line: i + 1, error})?;
+            if game.rules_string() != book.rules {
+                return Err(syntax("position has different rules from the book"));
+            }
+
+            // Hand-written positions don't have to be canonical, so the moves are turned like any
+            // other moves
+            let (canonical, symmetry) = symmetry::canonical(&game);
+            for text in moves.split(',') {
+                let (mv, weight) = text.trim().split_once(' ').ok_or_else(|| syntax("move has no weight"))?;
+                let (row, col) = parse_move(mv, game.rows(), game.cols())
+                    .map_err(|error| syntax(&error.to_string()))?;
+                if !game.legal_moves().contains(&(row, col)) {
+                    return Err(syntax(&format!("{} can't be played in this position", mv)));
+                }
+                let weight = weight.trim().parse().map_err(|_| syntax("weight is not a number"))?;
+                book.add(&canonical, symmetry, (row, col), weight);
+            }
+        }
+        book.ok_or_else(|| BookError::Syntax {line: 1, message: "the book is empty".to_string()})
+    }
+
+    // Saves the book to a file, replacing the file if it already exists
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    // Loads a book saved with `save`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, BookError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+}
+
+// Writing a book uses the Display trait, so `book.to_string()` and `write!` both work. The
+// positions are sorted so that the same book is always written the same way.
+impl fmt::Display for OpeningBook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "[{} \"{}\"]", VARIANT, self.rules)?;
+        writeln!(f)?;
+        let mut entries: Vec<&Entry> = self.entries.values().collect();
+        entries.sort_by(|a, b| a.position.cmp(&b.position));
+        for entry in entries {
+            let moves: Vec<String> = entry.moves.iter()
+                .map(|&((row, col), weight)| format!("{} {}", format_move(row, col), weight))
+                .collect();
+            writeln!(f, "{}: {}", entry.position, moves.join(", "))?;
+        }
+        Ok(())
+    }
+}
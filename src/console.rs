@@ -0,0 +1,243 @@
+//
+// file: console.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module contains everything that talks to the people playing the game. Instead of using
+// stdin and stdout directly, everything goes through a Console, which can wrap any input and
+// output. That lets the game be embedded in other programs or driven by a script in tests.
+
+// `BufRead` is the trait for inputs that can be read a line at a time and `Write` is the trait for
+// anything that bytes can be written to, such as stdout, a file or a Vec<u8>.
+use std::io::{self, BufRead, Write};
+
+use crate::player::Action;
+use crate::theme::Theme;
+use crate::{parse_move, Game, MoveError, Tile};
+
+// A Console holds on to the input and output of the game. `&'a mut dyn BufRead` is a reference
+// to any type that implements BufRead. The `'a` is a "lifetime" and tells Rust that the Console
+// can't outlive the input and output that it borrows.
+// The theme decides what the board looks like when it is printed.
+pub struct Console<'a> {
+    input: &'a mut dyn BufRead,
+    output: &'a mut dyn Write,
+    theme: Theme,
+}
+
+impl<'a> Console<'a> {
+    // Creates a console that prints the board using the default theme, without colors
+    pub fn new(input: &'a mut dyn BufRead, output: &'a mut dyn Write) -> Self {
+        Self::with_theme(input, output, Theme::default())
+    }
+
+    pub fn with_theme(input: &'a mut dyn BufRead, output: &'a mut dyn Write, theme: Theme) -> Self {
+        Self {input, output, theme}
+    }
+
+    // This function is something we've defined to make reading a line of input convenient. It
+    // returns None once we reach the end of the input. The caller decides what to do about that,
+    // which is much friendlier to other programs than exiting the whole process.
+    // The `?` after a Result returns the error from this function if there was one. That way any
+    // error that occurs while reading is passed on to the caller instead of being ignored.
+    pub fn read_line(&mut self) -> io::Result<Option<String>> {
+        // This creates a new growable/heap-allocated string. The `mut` after `let` declares that
+        // we plan to modify the string.
+        let mut input = String::new();
+        // `&mut input` passes a mutable reference to the String in the input variable. This
+        // allows the function to modify input without taking ownership of its value. That way we
+        // can return it from this function afterwards.
+        self.input.read_line(&mut input)?;
+
+        // An empty string will only be returned if we reach the end of input (otherwise we always
+        // receive at least a newline character).
+        if input.is_empty() {
+            return Ok(None);
+        }
+
+        // read_line leaves the trailing newline on the string, so we remove it using truncate. By
+        // modifying the string in place, we avoid copying its contents after it was just
+        // allocated.
+        let len_without_newline = input.trim_end().len();
+        input.truncate(len_without_newline);
+
+        Ok(Some(input))
+    }
+
+    // This function returns the action that the user entered, or None if the input was closed.
+    // Moves are turned into the row and column of the selected tile.
+    // The game is needed so that we know which moves are on the board. With gravity, the user can
+    // also just name a column (e.g. D or 4) and the piece lands on top of that column.
+    pub fn prompt_action(&mut self, game: &Game) -> io::Result<Option<Action>> {
+        let (rows, cols) = (game.rows(), game.cols());
+        // We'll use `loop` to continuously prompt for input until the user provides what we want.
+        // When we get the answer we want, the loop will return the value and it will be used as
+        // the return value of this function
+        loop {
+            // `write!` works just like `print!` except that it writes to the output we give it.
+            // Since the Console implements Write (see below), we can write to it directly.
+            if game.has_gravity() {
+                write!(self, "Enter column (e.g. D or 4), u to undo, r to redo, h for a hint or save <file>: ")?;
+            }
+            else {
+                write!(self, "Enter move (e.g. 1A), u to undo, r to redo, h for a hint or save <file>: ")?;
+            }
+
+            // Line-buffering is when something waits until it sees a new line character before
+            // actually writing to its designated destination. Outputs like stdout are
+            // line-buffered, so the prompt won't show up unless we "flush" it in the line below.
+            self.flush()?;
+
+            // When there is no more input, there is nothing left to prompt for.
+            // We print a final newline because otherwise the output may still be at the end of
+            // the prompt above.
+            let line = match self.read_line()? {
+                Some(line) => line,
+                None => {
+                    writeln!(self)?;
+                    return Ok(None);
+                },
+            };
+
+            // The undo, redo and hint commands are checked before trying to read the line as a move.
+            // `eq_ignore_ascii_case` lets the user type either u or U.
+            if line.eq_ignore_ascii_case("u") {
+                return Ok(Some(Action::Undo));
+            }
+            else if line.eq_ignore_ascii_case("r") {
+                return Ok(Some(Action::Redo));
+            }
+            else if line.eq_ignore_ascii_case("h") || line.eq_ignore_ascii_case("hint") || line == "?" {
+                return Ok(Some(Action::Hint));
+            }
+            // Everything after `save ` is the name of the file. `strip_prefix` returns None if the
+            // line doesn't start with it.
+            else if let Some(path) = line.strip_prefix("save ") {
+                return Ok(Some(Action::Save(path.trim().to_string())));
+            }
+
+            // A column on its own is the same as dropping a piece into it. A full column is reported
+            // the same way as `Game::drop_piece` would. The commands were checked first, so on
+            // a wide board columns H, R and U have to be typed as numbers.
+            if let Some(col) = parse_column(&line, cols).filter(|_| game.has_gravity()) {
+                match game.landing_row(col) {
+                    Some(row) => return Ok(Some(Action::Move(row, col))),
+                    None => {
+                        writeln!(self, "{}. Please try again.", MoveError::ColumnFull {col})?;
+                        continue;
+                    },
+                }
+            }
+
+            // We delegate reading the line as a move to the parse_move function. That function
+            // takes a string and converts it to a "tuple" of two values (row, col).
+            match parse_move(&line, rows, cols) {
+                Ok((row, col)) => return Ok(Some(Action::Move(row, col))),
+                // InvalidMove implements Display, so printing it with {} explains what was wrong
+                // with the move
+                Err(error) => writeln!(self, "{}. Please try again.", error)?,
+            }
+        }
+    }
+
+    // This function is used to print out the board in a human readable way
+    pub fn print_tiles(&mut self, tiles: &[Vec<Tile>]) -> io::Result<()> {
+        // `&[]` is an empty slice, so no tiles are highlighted
+        self.print_highlighted(tiles, &[])
+    }
+
+    // Prints the board of a game. Once someone has won, the pieces in the winning line are shown
+    // differently so that it is easy to see why the game ended. Without colors, the default theme
+    // uses capital letters:
+    //   A B C
+    // 1 X o o
+    // 2 ▢ X ▢
+    // 3 ▢ ▢ X
+    pub fn print_game(&mut self, game: &Game) -> io::Result<()> {
+        // `unwrap_or_default` gives us an empty Vec when there is no winning line
+        let winning_line = game.winning_line().unwrap_or_default();
+        self.print_highlighted(game.tiles(), &winning_line)
+    }
+
+    // Prints the board with the pieces on the tiles in `highlight` shown as part of the winning
+    // line
+    fn print_highlighted(&mut self, tiles: &[Vec<Tile>], highlight: &[(usize, usize)]) -> io::Result<()> {
+        // The result of this function will be something like the following:
+        //   A B C
+        // 1 x ▢ ▢
+        // 2 ▢ ▢ o
+        // 3 ▢ ▢ ▢
+        //
+        // The boxes represent empty tiles, and x and o are placed wherever a tile is filled.
+
+        // Boards with ten or more rows need more room for the row numbers. Converting the number
+        // of rows to a string is an easy way to find out how many digits it has.
+        let width = tiles.len().to_string().len() + 1;
+
+        // First we print the space before the column letters
+        // `{:width$}` pads the value to `width` characters, so this prints `width` spaces.
+        write!(self, "{:width$}", "", width = width)?;
+        // Then we loop over the numbers of the columns.
+        // `a..b` creates a "range" of numbers from a to one less than b.
+        // `as u8` converts the length from the type `usize` to the type `u8` so that it works in
+        // the body of the loop
+        for j in 0..tiles[0].len() as u8 {
+            // `b'A'` produces the ASCII character code for the letter A (i.e. 65)
+            // By adding j to it, we get 'A', then 'B', and then 'C'.
+            write!(self, " {}", (b'A' + j) as char)?;
+        }
+        // This prints the final newline after the row of column letters
+        writeln!(self)?;
+
+        // Now we print each row preceeded by its row number
+        // .iter().enumerate() goes through each row and provides a row number with each element
+        // using a tuple.
+        for (i, row) in tiles.iter().enumerate() {
+            // We print the row number with enough spaces in front of it to line up with the
+            // others. `>` pads on the left so that the numbers are right-aligned.
+            write!(self, "{:>width$}", i + 1, width = width)?;
+            // Now we go through each tile in the row and print it out
+            // The theme decides which characters and colors to use
+            for (j, tile) in row.iter().enumerate() {
+                let text = self.theme.tile(*tile, highlight.contains(&(i, j)));
+                write!(self, " {}", text)?;
+            }
+            // We finish each row by printing a final new line
+            writeln!(self)?;
+        }
+
+        // Add an extra line at the end of the board to space it out from the prompts that follow
+        writeln!(self)
+    }
+}
+
+// Implementing Write for Console means that the `write!` and `writeln!` macros work on it
+// directly. Everything is passed straight through to the output.
+impl<'a> Write for Console<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+// Reads a column on its own, either as its letter (e.g. D) or its number counting from 1 (e.g. 4).
+// Returns None if the text isn't a column on a board with `cols` columns.
+fn parse_column(text: &str, cols: usize) -> Option<usize> {
+    let col = match text.parse::<usize>() {
+        Ok(number) if text.bytes().all(|b| b.is_ascii_digit()) => number.checked_sub(1)?,
+        _ => {
+            let mut chars = text.chars();
+            match (chars.next(), chars.next()) {
+                (Some(letter), None) if letter.is_ascii_alphabetic() => {
+                    (letter.to_ascii_uppercase() as u8 - b'A') as usize
+                },
+                _ => return None,
+            }
+        },
+    };
+    if col < cols { Some(col) } else { None }
+}
@@ -0,0 +1,347 @@
+//
+// file: engine.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module lets bots written in any language play against each other, with this library as the
+// referee. A bot runs as its own program (an "engine") and talks to the referee (the "controller")
+// over its stdin and stdout, one command per line, a lot like UCI for chess or GTP for Go.
+//
+// The controller sends these commands:
+//
+//     hello                         the engine replies with `hello <name>`
+//     isready                       the engine replies with `readyok` once it has caught up
+//     newgame [rules]               start a new game, e.g. `newgame 6x7k4g` (see Game::rules_string)
+//     position start [moves ...]    set up the position after playing the moves on an empty board
+//     position <board> <piece> [moves ...]
+//                                   the same, starting from a position string such as `x2/1o1/3 x`
+//     go                            the engine replies with its move, e.g. `bestmove 2B`
+//     quit                          the engine stops
+//
+// Moves are written the same way the user types them in, e.g. 1A. An engine that doesn't
+// understand a command replies with `error <message>` and keeps going. Games start with the
+// Tic-Tac-Toe rules until a `newgame` says otherwise. The controller only waits so long for a reply
+// to `hello` or `go` (a minute, unless the engine was started with a different time limit).
+//
+// `serve` is the engine side, which lets any Player be used as an engine (`connect-rusty engine`
+// uses it for the built-in players), and `EnginePlayer` is the controller side, which starts an
+// engine program and plays its moves in a game like any other Player. Every move goes through
+// `Game::make_move`, so an engine that plays an illegal move forfeits like any other bot. So does an
+// engine whose `bestmove` isn't a move at all, or that doesn't reply to `go` in time.
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::str::SplitWhitespace;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::console::Console;
+use crate::player::{Action, Player};
+use crate::{format_move, parse_move, Game, MoveError, MAX_COLS};
+
+// How long an engine gets to stop after being told to quit before it is killed
+const QUIT_TIMEOUT: Duration = Duration::from_secs(1);
+
+// How long an engine gets to reply to a command unless it is given another time limit
+const REPLY_TIMEOUT: Duration = Duration::from_secs(60);
+
+// This type represents the ways that talking to an engine can go wrong. An engine that plays a
+// move that isn't allowed isn't one of them, since that is a MoveError like for any other player.
+#[derive(Debug)]
+pub enum EngineError {
+    /// The engine program couldn't be started, or reading or writing its input failed
+    Io(io::Error),
+
+    /// The engine closed its output, usually because it quit or crashed
+    Closed,
+
+    /// The engine didn't reply in time
+    Timeout(Duration),
+
+    /// The engine replied with something other than what the protocol says
+    UnexpectedReply { expected: &'static str, reply: String },
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EngineError::Io(error) => write!(f, "{}", error),
+            EngineError::Closed => write!(f, "the engine stopped"),
+            EngineError::Timeout(timeout) => write!(f, "the engine didn't reply within {:?}", timeout),
+            EngineError::UnexpectedReply {expected, reply} => {
+                write!(f, "expected {} from the engine, got '{}'", expected, reply)
+            },
+        }
+    }
+}
+
+impl Error for EngineError {}
+
+// `From` lets the `?` operator turn an io::Error into an EngineError for us
+impl From<io::Error> for EngineError {
+    fn from(error: io::Error) -> Self {
+        EngineError::Io(error)
+    }
+}
+
+// Runs an engine that reads commands from `input` and writes its replies to `output` until it is
+// told to quit or the input is closed. `player` picks the moves and `name` is sent back for
+// `hello`. Only errors reading or writing stop the engine; bad commands just get an error reply.
+pub fn serve<R: BufRead, W: Write>(player: &mut dyn Player, name: &str, input: R, mut output: W) -> io::Result<()> {
+    let mut game = Game::new();
+    for line in input.lines() {
+        let line = line?;
+        let mut words = line.split_whitespace();
+        // Commands that only change the game don't have a reply, so they `continue` straight to
+        // the next line
+        let reply = match words.next() {
+            None => continue,
+            Some("hello") => format!("hello {}", name),
+            Some("isready") => "readyok".to_string(),
+            Some("newgame") => {
+                // Without rules, the new game has the same rules as the last one
+                let rules = words.next().map_or_else(|| game.rules_string(), str::to_string);
+                match Game::from_rules_string(&rules) {
+                    Ok(new_game) => {
+                        game = new_game;
+                        continue;
+                    },
+                    Err(error) => format!("error invalid rules '{}': {}", rules, error),
+                }
+            },
+            Some("position") => match set_position(&game, words) {
+                Ok(position) => {
+                    game = position;
+                    continue;
+                },
+                Err(message) => format!("error {}", message),
+            },
+            Some("go") if game.is_finished() => "error the game is already over".to_string(),
+            Some("go") => {
+                // The player is asked for an action so that it can say what went wrong. Nothing is
+                // read from its console, since the input is ours, and what it writes becomes the
+                // error message.
+                let mut messages = Vec::new();
+                let action = player.choose_action(&game, &mut Console::new(&mut io::empty(), &mut messages));
+                let message = String::from_utf8_lossy(&messages).trim().to_string();
+                match action {
+                    Ok(Some(Action::Move(row, col))) => format!("bestmove {}", format_move(row, col)),
+                    Ok(Some(Action::Invalid(error))) => format!("error {}", error),
+                    Ok(_) if !message.is_empty() => format!("error {}", message.replace('\n', " ")),
+                    Ok(_) => "error no move was chosen".to_string(),
+                    Err(error) => format!("error {}", error),
+                }
+            },
+            Some("quit") => break,
+            Some(other) => format!("error unknown command '{}'", other),
+        };
+        // The controller is waiting for the reply, so it can't sit in a buffer
+        writeln!(output, "{}", reply)?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+// Reads the rest of a `position` command. The new position has the same rules as `game`.
+fn set_position(game: &Game, mut words: SplitWhitespace) -> Result<Game, String> {
+    let mut position = match words.next() {
+        Some("start") => Game::from_rules_string(&game.rules_string()).map_err(|error| error.to_string())?,
+        Some(board) => {
+            let piece = words.next().ok_or("position needs the piece to move after the board")?;
+            let text = format!("{} {} {}", board, piece, game.rules_string());
+            Game::from_position_string(&text).map_err(|error| format!("invalid position: {}", error))?
+        },
+        None => return Err("position needs a board or start".to_string()),
+    };
+
+    match words.next() {
+        None => {},
+        Some("moves") => {
+            for text in words {
+                let (row, col) = parse_move(text, position.rows(), position.cols())
+                    .map_err(|error| error.to_string())?;
+                position.make_move(row, col).map_err(|error| format!("{} can't be played: {}", text, error))?;
+            }
+        },
+        Some(other) => return Err(format!("expected moves, got '{}'", other)),
+    }
+    Ok(position)
+}
+
+// Writes the `position` command for a game. Games that were started on an empty board are sent
+// as all of their moves, so the engine can reuse what it worked out for the earlier positions.
+fn position_command(game: &Game) -> String {
+    let mut start = game.clone();
+    while start.undo().is_some() {}
+    let mut command = if start.tiles().iter().flatten().all(|tile| tile.is_none()) {
+        "position start".to_string()
+    }
+    else {
+        // The position string ends with the rules, which the engine already has from `newgame`
+        let position = start.to_position_string();
+        let fields: Vec<&str> = position.split(' ').take(2).collect();
+        format!("position {}", fields.join(" "))
+    };
+
+    if !game.history().is_empty() {
+        command.push_str(" moves");
+        for &(row, col) in game.history() {
+            command.push(' ');
+            command.push_str(&format_move(row, col));
+        }
+    }
+    command
+}
+
+// A player whose moves come from an engine program. The engine is started when the player is
+// created and told to quit when the player is dropped.
+pub struct EnginePlayer {
+    name: String,
+    child: Child,
+    // The lines the engine writes are read on another thread and sent here, which lets us stop
+    // waiting for a reply once the time is up. A blocked read can't be given a time limit.
+    input: Receiver<io::Result<String>>,
+    output: ChildStdin,
+    timeout: Duration,
+    // The rules and number of moves of the last position sent, which tells us when a new game
+    // has started
+    last: Option<(String, usize)>,
+}
+
+impl EnginePlayer {
+    // Starts `program` with `args` and says hello. The engine's name is whatever it replies with.
+    pub fn spawn(program: &str, args: &[String]) -> Result<Self, EngineError> {
+        Self::spawn_with_timeout(program, args, REPLY_TIMEOUT)
+    }
+
+    // The same as `spawn`, but the engine gets `timeout` to reply to each command instead of a
+    // minute. An engine that takes longer to say hello isn't started.
+    pub fn spawn_with_timeout(program: &str, args: &[String], timeout: Duration) -> Result<Self, EngineError> {
+        let mut child = Command::new(program).args(args).stdin(Stdio::piped()).stdout(Stdio::piped()).spawn()?;
+        // Both were piped above, so they are always there
+        let output = child.stdin.take().expect("stdin should be piped");
+        let reader = BufReader::new(child.stdout.take().expect("stdout should be piped"));
+
+        // The thread stops once the engine closes its output, which it does when it exits (or
+        // is killed when the player is dropped). Sending fails once the player is gone, which
+        // stops it too.
+        let (sender, input) = mpsc::channel();
+        thread::spawn(move || {
+            for line in reader.lines() {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut engine = Self {name: program.to_string(), child, input, output, timeout, last: None};
+
+        engine.send("hello")?;
+        let reply = engine.receive()?;
+        match reply.strip_prefix("hello") {
+            Some(name) if !name.trim().is_empty() => engine.name = name.trim().to_string(),
+            // An engine without a name keeps the name of its program
+            Some(_) => {},
+            None => return Err(EngineError::UnexpectedReply {expected: "hello", reply}),
+        }
+        Ok(engine)
+    }
+
+    // Asks the engine for its move in this position. The move isn't checked, except that it has to
+    // be written like a move; it is up to `Game::make_move` to decide if it is allowed. A reply
+    // that isn't a move at all is a MoveError, since the engine did answer but broke the rules.
+    pub fn request_move(&mut self, game: &Game) -> Result<Result<(usize, usize), MoveError>, EngineError> {
+        // A different board, or fewer moves than last time, means that this is another game (or
+        // that moves were undone). Either way the engine should forget what it knows.
+        let rules = game.rules_string();
+        let moves = game.history().len();
+        if self.last.as_ref().is_none_or(|(last_rules, last_moves)| *last_rules != rules || *last_moves > moves) {
+            self.send(&format!("newgame {}", rules))?;
+        }
+        self.last = Some((rules, moves));
+
+        self.send(&position_command(game))?;
+        self.send("go")?;
+        let reply = self.receive()?;
+        let text = match reply.strip_prefix("bestmove") {
+            Some(text) => text.trim(),
+            None => return Err(EngineError::UnexpectedReply {expected: "bestmove", reply}),
+        };
+        // Moves off the board are allowed through so that they are reported like any other
+        // invalid move
+        Ok(parse_move(text, usize::MAX, MAX_COLS).map_err(|_| MoveError::Unreadable {text: text.to_string()}))
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), EngineError> {
+        writeln!(self.output, "{}", command)?;
+        self.output.flush()?;
+        Ok(())
+    }
+
+    // Reads the next line from the engine, skipping blank lines. The time limit is for the whole
+    // reply, so an engine can't keep going by writing blank lines.
+    fn receive(&mut self) -> Result<String, EngineError> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let line = match self.input.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(line) => line?,
+                Err(RecvTimeoutError::Timeout) => return Err(EngineError::Timeout(self.timeout)),
+                // The reading thread is done, so there is nothing more to read
+                Err(RecvTimeoutError::Disconnected) => return Err(EngineError::Closed),
+            };
+            if !line.trim().is_empty() {
+                return Ok(line.trim().to_string());
+            }
+        }
+    }
+}
+
+impl Player for EnginePlayer {
+    // This is only used outside of a game, where there is no way to report what went wrong. An
+    // engine that stops working or doesn't answer with a move has no move. Games ask for an action
+    // instead, which can say why.
+    fn choose_move(&mut self, game: &Game) -> Option<(usize, usize)> {
+        self.request_move(game).ok()?.ok()
+    }
+
+    // An engine that runs out of time forfeits, like one that plays an invalid move. An engine
+    // that stops talking can't go on with the game, just like a person whose input was closed.
+    fn choose_action(&mut self, game: &Game, console: &mut Console) -> io::Result<Option<Action>> {
+        match self.request_move(game) {
+            Ok(Ok((row, col))) => Ok(Some(Action::Move(row, col))),
+            Ok(Err(error)) => Ok(Some(Action::Invalid(error))),
+            Err(EngineError::Timeout(timeout)) => {
+                Ok(Some(Action::Invalid(MoveError::TimedOut {millis: timeout.as_millis() as u64})))
+            },
+            Err(error) => {
+                writeln!(console, "{} stopped working: {}", self.name, error)?;
+                Ok(None)
+            },
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+// `Drop` runs when the player goes out of scope. The engine is asked to quit, and killed if it
+// doesn't, so that no engine programs are left running after a game.
+impl Drop for EnginePlayer {
+    fn drop(&mut self) {
+        // The engine may already be gone, in which case there is nothing to do
+        let _ = self.send("quit");
+        let start = Instant::now();
+        while start.elapsed() < QUIT_TIMEOUT {
+            match self.child.try_wait() {
+                Ok(None) => thread::sleep(Duration::from_millis(10)),
+                _ => return,
+            }
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -7,25 +7,85 @@
 // This is how we "import" a module from the standard library. A module is a group of functions and
 // types. "std" stands for "standard library" and "io" stands for "input/output". We will use this
 // module to read input from the user of our application.
-// The import "self" imports the name "io" itself, and "Write" imports the "Write trait" which we
-// need to flush stdout below.
-use std::io::{self, Write};
-// We use the process::exit function to quit the program when we need to.
-use std::process;
+// The import "self" imports the name "io" itself, "BufRead" imports the trait for reading input a
+// line at a time and "Write" imports the "Write trait" which we need to write output below.
+use std::io::{self, BufRead, Write};
+// The Error trait is implemented by every error type that wants to work with the rest of Rust's
+// error handling. fmt has the Display trait, which is how a type decides what it looks like when
+// printed with {}.
+use std::error::Error;
+use std::fmt;
+// mem::take lets us move a value out of a field and leave an empty value in its place
+use std::mem;
+
+// Modules let us split the program into several files. This tells Rust to look for the `ai` module
+// in src/ai.rs. `pub` makes it available to users of this library as `program::ai`.
+pub mod ai;
+pub mod book;
+pub mod console;
+pub mod engine;
+pub mod mcts;
+pub mod player;
+pub mod position;
+pub mod record;
+mod rng;
+pub mod solver;
+pub mod stats;
+pub mod symmetry;
+pub mod tablebase;
+pub mod theme;
+pub mod tournament;
+pub mod transposition;
+pub mod zobrist;
+#[cfg(feature = "serde")]
+mod serialization;
+#[cfg(feature = "tui")]
+pub mod tui;
+
+// `pub use` re-exports a type from one of our modules, so users of this library can write
+// `program::PositionError` instead of `program::position::PositionError`.
+pub use position::PositionError;
+#[cfg(feature = "serde")]
+pub use serialization::GameDataError;
+
+// `use` also works for our own modules. This brings the most commonly used player types into scope
+// so that we don't have to write `player::` in front of them below.
+use console::Console;
+use player::{Action, ComputerPlayer, HumanPlayer, Player};
+use record::GameRecord;
+use theme::Theme;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 // This constant can be used to set the board size
-// Since Rust's arrays are fat pointers, you won't see this constant referred to again after the
-// we declare the type of Game. I mention this because if you were writing in a language like C,
-// you would either need to pass the size to every function with the board or rely on this global
-// constant. In Rust, that information is stored directly in the array so you always have the
-// correct value.
+// Since Rust's vectors know their own length, you won't see this constant referred to again after
+// we create a new Game. I mention this because if you were writing in a language like C, you would
+// either need to pass the size to every function with the board or rely on this global constant.
+// In Rust, that information is stored directly in the vector so you always have the correct value.
 const BOARD_SIZE: usize = 3;
 
+// Connect Four is played on a seven-column, six-row grid and you need four discs in a line to win.
+// These are only used by the Connect Four constructor below.
+const CONNECT_FOUR_ROWS: usize = 6;
+const CONNECT_FOUR_COLS: usize = 7;
+const CONNECT_FOUR_WIN_LENGTH: usize = 4;
+
+// Columns are named using the letters A to Z (e.g. 1A, 2C), so that is the widest board we can
+// support without running out of names.
+pub const MAX_COLS: usize = 26;
+
+// Rows are numbered, so they never run out of names. A board still needs memory for every tile, so
+// the number of rows is limited too. Otherwise a rules string like `100000000000x26k3` from a file
+// or an engine would try to allocate far more memory than there is.
+pub const MAX_ROWS: usize = 100;
+
 // We want to use an enum for piece because we can either have one piece or the other on a tile,
 // but never both at the same time
 // `derive` automatically derives certain useful traits. These make this custom type that we've
 // defined copyable, comparable for equality, and more without any additional work!
+// With the `serde` feature turned on, pieces are written as "x" and "o".
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "lowercase"))]
 pub enum Piece {
     // Access these variants using `Piece::X` or `Piece::O`
     X,
@@ -55,6 +115,23 @@ impl Piece {
             Piece::O => Piece::X,
         }
     }
+
+    // The name of the piece as it is shown to the user, "x" or "o"
+    pub fn name(self) -> &'static str {
+        match self {
+            Piece::X => "x",
+            Piece::O => "o",
+        }
+    }
+
+    // X is 0 and O is 1. The game loops store their players in an array, so this is how they look
+    // up the right one for a piece.
+    pub(crate) fn index(self) -> usize {
+        match self {
+            Piece::X => 0,
+            Piece::O => 1,
+        }
+    }
 }
 
 // By using an Option type, we can represent the possibility of having one of the valid piece
@@ -63,14 +140,18 @@ impl Piece {
 // piece. The current piece can never be "empty", so it doesn't make sense to have an Empty variant
 // in the Piece enum.
 pub type Tile = Option<Piece>;
-// We represent the tiles of the board using a 2D array
-// Each element of the first array is a row of the board.
+// We represent the tiles of the board using a 2D vector
+// Each element of the first vector is a row of the board.
 // tiles[1][2] accesses the second row and third column of the board.
-pub type Tiles = [[Tile; BOARD_SIZE]; BOARD_SIZE];
+// Row 0 is the top of the board, so in Connect Four the discs fall towards the last row.
+// A Vec is used instead of a fixed size array because Tic-Tac-Toe and Connect Four boards have
+// different sizes, and the size of an array has to be known when the program is compiled.
+pub type Tiles = Vec<Vec<Tile>>;
 
 // There are three possibilities for the winner at the end of the game. We represent them as an
 // enum because only one of them can ever occur at a given time.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "lowercase"))]
 pub enum Winner {
     X,
     O,
@@ -78,7 +159,10 @@ pub enum Winner {
 }
 
 // This type represents the possible errors that can occur when making a move
-#[derive(Debug, Clone)]
+// With the `serde` feature, the variant is stored in a "kind" field next to the other fields, e.g.
+// `{"kind": "column_full", "col": 3}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(tag = "kind", rename_all = "snake_case"))]
 pub enum MoveError {
     // Putting /// instead of // means that Rust's documentation tool will automatically pickup
     // that comment and use it when generating beautiful documentation for this module.
@@ -93,49 +177,202 @@ pub enum MoveError {
 
     /// The tile already contained another piece
     TileNotEmpty { other_piece: Piece, row: usize, col: usize },
+
+    /// The column has no empty tiles left to drop a piece into
+    ColumnFull { col: usize },
+
+    /// There is no such column to drop a piece into
+    InvalidColumn { col: usize },
+
+    /// The move couldn't be read, e.g. because a bot wrote it in a way that isn't a move
+    Unreadable { text: String },
+
+    /// No move was made within the time limit, e.g. because an engine stopped replying
+    TimedOut { millis: u64 },
+}
+
+// Display is what gets used when an error is printed with `{}`, so this is where the messages
+// shown to the user live. The positions are written the same way the user types them in, e.g. 1A.
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MoveError::GameAlreadyOver => write!(f, "The game is already over"),
+            MoveError::InvalidPosition {row, col} => {
+                write!(f, "A piece can't be placed at position {}", format_move(row, col))
+            },
+            MoveError::TileNotEmpty {other_piece, row, col} => write!(
+                f,
+                "The tile at position {} already has piece {} in it",
+                format_move(row, col),
+                other_piece.name(),
+            ),
+            MoveError::ColumnFull {col} => write!(f, "Column {} is already full", column_name(col)),
+            MoveError::InvalidColumn {col} => write!(f, "There is no column {} on the board", column_name(col)),
+            // `ref` borrows the text instead of moving it out of `*self`
+            MoveError::Unreadable {ref text} => write!(f, "'{}' is not a move", text),
+            MoveError::TimedOut {millis} => write!(f, "No move was made within {}ms", millis),
+        }
+    }
+}
+
+// Implementing Error lets MoveError be used with `?` in functions that return
+// `Result<_, Box<dyn Error>>`. All of its methods have default implementations.
+impl Error for MoveError {}
+
+// This type represents the possible errors that can occur when creating a game with a custom
+// board size and win length
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The board must have at least one row and one column
+    EmptyBoard { rows: usize, cols: usize },
+
+    /// There are only enough letters to name MAX_COLS columns
+    TooManyColumns { cols: usize },
+
+    /// A board can have at most MAX_ROWS rows
+    TooManyRows { rows: usize },
+
+    /// The win length must be at least 1 and must fit on the board in some direction
+    InvalidWinLength { win_length: usize, rows: usize, cols: usize },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::EmptyBoard {rows, cols} => {
+                write!(f, "A board needs at least one row and one column, not {}x{}", rows, cols)
+            },
+            ConfigError::TooManyColumns {cols} => {
+                write!(f, "A board can have at most {} columns, not {}", MAX_COLS, cols)
+            },
+            ConfigError::TooManyRows {rows} => {
+                write!(f, "A board can have at most {} rows, not {}", MAX_ROWS, rows)
+            },
+            ConfigError::InvalidWinLength {win_length, rows, cols} => {
+                write!(f, "A line of {} pieces can't be made on a board with {} rows and {} columns", win_length, rows, cols)
+            },
+        }
+    }
 }
 
+impl Error for ConfigError {}
+
+// With the `serde` feature, a game is converted to and from the GameData type in the serialization
+// module. That gives the JSON a stable shape and lets us check a game before accepting it.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "serialization::GameData", into = "serialization::GameData"))]
 pub struct Game {
     tiles: Tiles,
     // There is always a current piece, so we don't need to wrap it in an Option type.
     current_piece: Piece,
-    // There is only a winner at the end of the game, and once there is, it never changes. If we
-    // wanted to, we could use the Rust type system to enforce this invariant and make sure the
-    // program can't even be written in a way that would violate that. I decided to keep it simple
-    // and not do that, but it's a great exercise to try out!
-    // Hint: Replace the Winner enum declaration with a `struct Winner(...)` and make the type of
-    // this field `Winner`. If you make that type so that the winner can only be set to something
-    // other than None once, it will no longer be possible to write a program that violates the
-    // invariant stated above.
+    // There is only a winner at the end of the game, and once there is, it only changes if the
+    // winning move is undone. Since no move can be made after the game is over, undoing a move
+    // always takes us back to a position where there was no winner yet.
     winner: Option<Winner>,
+    // The number of pieces in a line needed to win (3 for Tic-Tac-Toe, 4 for Connect Four)
+    win_length: usize,
+    // When gravity is on, pieces fall to the lowest empty tile of the column they are dropped in
+    gravity: bool,
+    // Every move that has been made so far, in the order it was made. The piece that made each
+    // move doesn't need to be stored because X always goes first and the pieces alternate.
+    history: Vec<(usize, usize)>,
+    // Moves that were taken back with `undo`. The last element is the next move that `redo` will
+    // play again. Making any new move clears this because the undone moves no longer apply.
+    undone: Vec<(usize, usize)>,
+    // The Zobrist hash of the tiles and the current piece (see the zobrist module). It is updated a
+    // little with every move instead of being worked out from the whole board each time.
+    hash: u64,
+}
+
+// Implementing Default lets Game be used anywhere a default value is expected, for example with
+// `Default::default()` or `#[derive(Default)]` on a struct containing a Game.
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Game {
     // Using Self inside of an impl allows us to refer to its type (i.e. `Game`) without using the
     // type name explicitly. This is useful for renaming!
     pub fn new() -> Self {
+        // We know that these values are valid, so we skip the checks done by `with_config`
+        Self::empty(BOARD_SIZE, BOARD_SIZE, BOARD_SIZE, false)
+    }
+
+    // This creates a new, empty Connect Four board with seven columns and six rows. Pieces fall
+    // to the bottom of the column they are dropped in and four in a row in any direction wins.
+    pub fn connect_four() -> Self {
+        Self::empty(CONNECT_FOUR_ROWS, CONNECT_FOUR_COLS, CONNECT_FOUR_WIN_LENGTH, true)
+    }
+
+    // This creates a new, empty board with any number of rows and columns where pieces can be
+    // placed on any empty tile and `win_length` pieces in a line wins. These are known as m,n,k
+    // games. For example, `Game::with_config(15, 15, 5)` is Gomoku and
+    // `Game::with_config(3, 3, 3)` is the same as `Game::new()`.
+    pub fn with_config(rows: usize, cols: usize, win_length: usize) -> Result<Self, ConfigError> {
+        Self::check_config(rows, cols, win_length)?;
+        Ok(Self::empty(rows, cols, win_length, false))
+    }
+
+    // This is just like `with_config` except that pieces fall to the bottom of their column like
+    // in Connect Four. `Game::with_gravity_config(6, 7, 4)` is the same as `Game::connect_four()`.
+    pub fn with_gravity_config(rows: usize, cols: usize, win_length: usize) -> Result<Self, ConfigError> {
+        Self::check_config(rows, cols, win_length)?;
+        Ok(Self::empty(rows, cols, win_length, true))
+    }
+
+    // Checks that a board with these settings can actually be played
+    fn check_config(rows: usize, cols: usize, win_length: usize) -> Result<(), ConfigError> {
+        if rows == 0 || cols == 0 {
+            Err(ConfigError::EmptyBoard {rows, cols})
+        }
+        else if cols > MAX_COLS {
+            Err(ConfigError::TooManyColumns {cols})
+        }
+        else if rows > MAX_ROWS {
+            Err(ConfigError::TooManyRows {rows})
+        }
+        // A line can be at most as long as the longest side of the board (the diagonals are never
+        // longer than the shorter side)
+        else if win_length == 0 || win_length > rows.max(cols) {
+            Err(ConfigError::InvalidWinLength {win_length, rows, cols})
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    // Creates a game with an empty board without checking the settings
+    fn empty(rows: usize, cols: usize, win_length: usize, gravity: bool) -> Self {
         // Here we construct and return a new instance of Game
         Self {
-            // Here, we take advantage of the Default trait to make it so that this code doesn't
-            // have to know the type we defined for tiles in order to initialize it. Rust has
-            // already defined the trait for arrays and the Option type, so we don't need to
-            // implement it ourself!
-            // More info: https://doc.rust-lang.org/std/default/trait.Default.html
-            tiles: Default::default(),
+            // The `vec!` macro can repeat a value, so this creates `rows` rows that each have
+            // `cols` empty tiles.
+            tiles: vec![vec![None; cols]; rows],
             // We want to start with X
             current_piece: Piece::X,
             // There is no winner at the start of the game. We cleanly represent this with `None`.
             // Rust will warn us before our program even tries to run if we forget that this value
             // might be None.
             winner: None,
+            win_length,
+            gravity,
+            // `Vec::new()` doesn't allocate any memory until the first move is pushed
+            history: Vec::new(),
+            undone: Vec::new(),
+            // The hash of an empty board with X to move is 0
+            hash: 0,
         }
     }
 
     // `&mut self` reflects that we plan to modify this struct in this method. Rust will ensure
     // that no other thread can access this object while we are modifying it. Thus eliminating any
     // possible data races.
-    // Both row and col must be values from 0 to (BOARD_SIZE-1)
+    // row must be a value from 0 to (rows-1) and col must be a value from 0 to (cols-1)
+    // When the game has gravity, row must be the lowest empty tile of the column (see
+    // `landing_row`). Use `drop_piece` to make a move using just the column.
     // In the return type, () indicates the "unit type". That means that on success, this function
     // returns nothing.
     pub fn make_move(&mut self, row: usize, col: usize) -> Result<(), MoveError> {
@@ -148,8 +385,8 @@ impl Game {
         // The usize type is "unsigned", meaning it is always positive. That means that this
         // potential error case is unrepresentable. We don't need to check for it if it can't
         // happen!
-        // Notice that we use `.len()` instead of the BOARD_SIZE constant we defined because Rust
-        // arrays provide their length.
+        // Notice that we use `.len()` instead of storing the size separately because Rust vectors
+        // provide their length.
         else if row >= self.tiles.len() || col >= self.tiles[0].len() {
             // Rust supports a "field shorthand" syntax which allows us to write {row, col} instead
             // of {row: row, col: col}
@@ -162,6 +399,10 @@ impl Game {
             // in one quick sweep. This makes writing the next line very easy!
             return Err(MoveError::TileNotEmpty {other_piece, row, col});
         }
+        // With gravity, a piece can only ever rest on top of another piece or on the bottom row
+        else if self.gravity && self.landing_row(col) != Some(row) {
+            return Err(MoveError::InvalidPosition {row, col});
+        }
 
         // Now that we've done all of the error checking, we can proceed with making the move and
         // modifying the tiles and current piece
@@ -173,118 +414,150 @@ impl Game {
         // always be sure that it will be updated correctly and according the rules we expect.
         self.current_piece = self.current_piece.other();
 
+        // XOR-ing in the new piece and the change of turn is all it takes to update the hash
+        self.hash ^= zobrist::tile_key(row, col, self.current_piece.other()) ^ zobrist::side_key();
+
         // After making a move, it may be that someone won the game. We'll use another method for
         // that since this one is getting quite long.
         self.update_winner(row, col);
 
+        // Remember the move so that it can be undone later. A new move means that any moves that
+        // were undone can no longer be redone.
+        self.history.push((row, col));
+        self.undone.clear();
+
         // Now that everything is complete, we can go ahead and return our "nothing" value `()`
         // called "unit" to indicate that this operation was a success. We construct a Result type
         // using its `Ok` variant as the constructor.
         Ok(())
     }
 
-    // We use a private method to separate code that shouldn't be accessed publically
-    fn update_winner(&mut self, row: usize, col: usize) {
-        // To find a potential winner, we only need to check the row, column and (maybe) diagonal
-        // that the last move was made in.
-
-        // Let's make some convenience variables for the number of rows and columns
-        let rows = self.tiles.len();
-        let cols = self.tiles[0].len();
-
-        // We can extract the row pretty easily because of how we stored tiles
-        let tiles_row = self.tiles[row];
-
-        // To get the correct column, we could do something very fancy that would work for every
-        // size of board, but in this case we'll just do the simplest thing and get the column
-        // directly using indexing.
-        let tiles_col = [self.tiles[0][col], self.tiles[1][col], self.tiles[2][col]];
-
-        // This relies on the assumption that the board has size 3, so let's assert that so that if
-        // someone ever changes this code there are no weird bugs
-        // This will produce an error at runtime if this assumption is broken.
-        assert!(rows == 3 && cols == 3,
-            "This code was written with the assumption that there are three rows and columns");
-
-        // There are two diagonals on the board. Their positions are as follows:
-        // 1. (0, 0), (1, 1), (2, 2)
-        // 2. (0, 2), (1, 1), (2, 0)
-        // Due to the possibility of being on (1, 1), we might be on both diagonals. We will check
-        // both diagonals separately.
-        // Notice that on a 3x3 board, if row == col, we are on the first diagonal
-        // and if (rows - row - 1) == col, we are on the second diagonal.
-        // If we are on neither diagonal, we can just use an array of None's so that it definitely
-        // won't find a match.
-
-        // Here, we see that if statements can be used as expressions just like match statements.
-        // That means that we can assign this variable to the result of the if statement.
-        let tiles_diagonal_1 = if row == col {
-            // Once again, we'll do the simplest thing and just use an array.
-
-            // Diagonal 1
-            [self.tiles[0][0], self.tiles[1][1], self.tiles[2][2]]
+    // Takes back the last move that was made and returns it, or returns None if no moves have been
+    // made yet. The piece that made the move becomes the current piece again.
+    pub fn undo(&mut self) -> Option<(usize, usize)> {
+        // `?` on an Option returns None from this function if there is nothing to pop
+        let (row, col) = self.history.pop()?;
+
+        // The tile must contain the piece that made the move, which is the piece that should be
+        // allowed to move again
+        self.current_piece = self.tiles[row][col].take().expect("moves in the history should have a piece");
+        // XOR-ing the same keys again takes them back out of the hash
+        self.hash ^= zobrist::tile_key(row, col, self.current_piece) ^ zobrist::side_key();
+
+        // The game could not have been over before this move was made, otherwise the move would
+        // have been rejected. That means there was no winner yet.
+        self.winner = None;
+
+        self.undone.push((row, col));
+        Some((row, col))
+    }
+
+    // Plays the last undone move again and returns it, or returns None if there is nothing to
+    // redo.
+    pub fn redo(&mut self) -> Option<(usize, usize)> {
+        let (row, col) = self.undone.pop()?;
+
+        // make_move clears the undone moves, but we want to keep the rest of them around so that
+        // they can be redone too. `mem::take` moves the vector out and leaves an empty one behind.
+        let undone = mem::take(&mut self.undone);
+        self.make_move(row, col).expect("undone moves should still be valid");
+        self.undone = undone;
+
+        Some((row, col))
+    }
+
+    // A 64-bit number that identifies the position: the pieces on the board and whose turn it is.
+    // The same position always has the same key, no matter which order the moves were played in,
+    // which makes it useful as the key of a transposition table (see the transposition module).
+    // Games with different rules can share keys, so a table should only hold one kind of game.
+    pub fn hash_key(&self) -> u64 {
+        self.hash
+    }
+
+    // Every move made so far in the order that it was made, as (row, col) pairs. X made the moves
+    // at even indexes and O made the moves at odd indexes.
+    pub fn history(&self) -> &[(usize, usize)] {
+        &self.history
+    }
+
+    // Returns every move that the current piece is allowed to make as (row, col) pairs. There are
+    // no legal moves once the game is over. With gravity, there is at most one move per column.
+    pub fn legal_moves(&self) -> Vec<(usize, usize)> {
+        if self.is_finished() {
+            Vec::new()
+        }
+        else if self.gravity {
+            // `filter_map` skips the columns where `landing_row` returns None (i.e. full columns)
+            (0..self.cols()).filter_map(|col| self.landing_row(col).map(|row| (row, col))).collect()
         }
         else {
-            // This will never produce a winner, so it is suitable to use for the case where the
-            // last move isn't on diagonal 1 anyway.
-            [None, None, None]
-        };
+            // `flat_map` lets us turn every row into a list of moves and then join those lists
+            // together into one
+            (0..self.rows())
+                .flat_map(|row| (0..self.cols()).map(move |col| (row, col)))
+                .filter(|&(row, col)| self.tiles[row][col].is_none())
+                .collect()
+        }
+    }
+
+    // Returns true if there are moves that can be taken back with `undo`
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty()
+    }
 
-        let tiles_diagonal_2 = if (rows - row - 1) == col {
-            // Diagonal 2
-            [self.tiles[0][2], self.tiles[1][1], self.tiles[2][0]]
+    // Returns true if there are undone moves that can be played again with `redo`
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    // Drops the current piece into the given column. The piece falls to the lowest empty tile of
+    // that column, just like a disc in a real Connect Four grid. On success, the row that the
+    // piece landed in is returned.
+    pub fn drop_piece(&mut self, col: usize) -> Result<usize, MoveError> {
+        if self.is_finished() {
+            return Err(MoveError::GameAlreadyOver);
         }
-        else {
-            // Our last move isn't on diagonal 2.
-            [None, None, None]
+        else if col >= self.tiles[0].len() {
+            return Err(MoveError::InvalidColumn {col});
+        }
+
+        // `ok_or` converts the Option into a Result so that `?` can return the error for us
+        let row = self.landing_row(col).ok_or(MoveError::ColumnFull {col})?;
+        self.make_move(row, col)?;
+        Ok(row)
+    }
+
+    // Returns the lowest empty row of the given column or None if the column is full (or does not
+    // exist). This is where a piece dropped into the column would land.
+    pub fn landing_row(&self, col: usize) -> Option<usize> {
+        // Rows are numbered from the top, so we search from the bottom row upwards. `rev()`
+        // reverses the range so that we visit the last row first.
+        (0..self.tiles.len()).rev().find(|&row| {
+            // `get` returns None instead of panicking when col is out of range, so an out of range
+            // column is never considered empty
+            self.tiles[row].get(col) == Some(&None)
+        })
+    }
+
+    // We use a private method to separate code that shouldn't be accessed publically
+    fn update_winner(&mut self, row: usize, col: usize) {
+        // To find a potential winner, we only need to check the lines that pass through the last
+        // move. Any line that was complete before this move would have already ended the game.
+
+        // The piece that was just placed. If there is somehow no piece here, nobody can win.
+        let piece = match self.tiles[row][col] {
+            Some(piece) => piece,
+            None => return,
         };
+        let won = self.makes_line(row, col, piece);
 
-        // Now that we have the row, column and diagonal of the last move, let's check if we have
-        // a winner. To do that, we'll use a check_winner function that either returns a new
-        // Winner or None. This is useful because we can chain together the methods of the Option
-        // type to produce a result. This is an alternative to multiple if statements that works
-        // just as well.
-        fn check_winner(row: &[Tile]) -> Option<Winner> {
-            // This is an "inner function". It is only visible to this update_winner method. We
-            // could have defined this as a method or defined it as a function separate from this
-            // impl too.
-            // The type `&[Tile]` is known as a slice. This is how we pass an array by reference.
-            // We don't have to pass the size with the array because the array pointer also stores
-            // its length.
-            // By returning an option type, we signal that this function may return some value or
-            // no value (i.e. None).
-
-            // Here, we once again do the simplest thing possible and just use indexes to check
-            // if the entire row is the same. We could potentially do something more general using
-            // iterators, but why do that if this simpler way works?
-            if row[0] == row[1] && row[1] == row[2] {
-                // We use a match to retrieve the correct winner based on the piece that has filled
-                // this row.
-                match row[0] {
-                    Some(Piece::X) => Some(Winner::X),
-                    Some(Piece::O) => Some(Winner::O),
-                    None => None,
-                }
-            }
-            else {
-                // All the tiles are not the same, there is no winner yet, so let's signal that
-                // with None
-                None
-            }
+        if won {
+            // We use a match to retrieve the correct winner based on the piece that made the line
+            self.winner = Some(match piece {
+                Piece::X => Winner::X,
+                Piece::O => Winner::O,
+            });
         }
-        // Now that we can determine if there is a winner or not, we can use the option type's
-        // methods to chain together the results. See the Option type documentation for more info:
-        // https://doc.rust-lang.org/std/option/enum.Option.html
-        self.winner = self.winner
-            // The || syntax is actually defining a special function called a "closure" (or
-            // "lambda" in some languages). That allows us to delay calling the check_winner
-            // function until we actually need it.
-            // By using or_else over and over again, we never overwrite a previously found winner
-            // and the code is only run in case a previous winner was *not* found.
-            .or_else(|| check_winner(&tiles_row))
-            .or_else(|| check_winner(&tiles_col))
-            .or_else(|| check_winner(&tiles_diagonal_1))
-            .or_else(|| check_winner(&tiles_diagonal_2));
 
         // The final case is when the board has filled up. Here, for the first time, we'll be a
         // bit fancy and use the Iterator trait. For more info, see the book:
@@ -307,6 +580,115 @@ impl Game {
         });
     }
 
+    // Returns true if `piece` would have a winning line through (row, col) with a piece there.
+    // The tile itself isn't looked at, so this can also tell whether placing a piece on an empty
+    // tile would win.
+    pub(crate) fn makes_line(&self, row: usize, col: usize, piece: Piece) -> bool {
+        // Let's make some convenience variables for the number of rows and columns. We convert
+        // them to isize because walking along a line can step off the top or left of the board,
+        // which would be a negative index.
+        let rows = self.tiles.len() as isize;
+        let cols = self.tiles[0].len() as isize;
+
+        // Every line through a tile points in one of these four directions. The opposite
+        // directions are covered by walking backwards along the same line below.
+        // 1. (0, 1) horizontal
+        // 2. (1, 0) vertical
+        // 3. (1, 1) diagonal going down and to the right
+        // 4. (1, -1) diagonal going down and to the left
+        const DIRECTIONS: [(isize, isize); 4] = [(0, 1), (1, 0), (1, 1), (1, -1)];
+
+        // This closure counts how many tiles in a row contain `piece`, starting next to
+        // (row, col) and stepping by (d_row, d_col) until it hits another piece or the edge of the
+        // board.
+        let tiles = &self.tiles;
+        let count = |d_row: isize, d_col: isize| {
+            let mut count = 0;
+            let (mut r, mut c) = (row as isize + d_row, col as isize + d_col);
+            while r >= 0 && r < rows && c >= 0 && c < cols && tiles[r as usize][c as usize] == Some(piece) {
+                count += 1;
+                r += d_row;
+                c += d_col;
+            }
+            count
+        };
+
+        // `any` stops as soon as one direction gives us a winning line
+        DIRECTIONS.iter().any(|&(d_row, d_col)| {
+            // The line is made up of the tile at (row, col) plus the matching tiles on either
+            // side of it.
+            1 + count(d_row, d_col) + count(-d_row, -d_col) >= self.win_length
+        })
+    }
+
+    // Returns the tiles of the line that won the game, or None if nobody has won. If the last move
+    // completed more than one line at once, the tiles of all of them are included (once each).
+    pub fn winning_line(&self) -> Option<Vec<(usize, usize)>> {
+        let mut tiles: Vec<(usize, usize)> = self.winning_lines().into_iter().flatten().collect();
+        if tiles.is_empty() {
+            return None;
+        }
+        // Sorting puts the tiles in order from the top left and lets `dedup` remove the tiles
+        // shared by two lines, since they end up next to each other
+        tiles.sort_unstable();
+        tiles.dedup();
+        Some(tiles)
+    }
+
+    // Returns each line that won the game separately, with the tiles of each line in order from
+    // one end to the other. This is empty unless X or O has won.
+    pub fn winning_lines(&self) -> Vec<Vec<(usize, usize)>> {
+        match self.winner {
+            Some(Winner::X) | Some(Winner::O) => self.lines().into_iter().map(|(_, line)| line).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    // Finds every line of at least `win_length` matching pieces anywhere on the board. Each line
+    // is returned with its piece and the (row, col) of every tile in it. Unlike update_winner,
+    // this doesn't need to know the last move, so it works on any board.
+    pub(crate) fn lines(&self) -> Vec<(Piece, Vec<(usize, usize)>)> {
+        let (rows, cols) = (self.rows() as isize, self.cols() as isize);
+        // This closure returns the piece at (r, c), or None if that is off the board
+        let at = |r: isize, c: isize| {
+            if r >= 0 && r < rows && c >= 0 && c < cols {
+                self.tiles[r as usize][c as usize]
+            }
+            else {
+                None
+            }
+        };
+
+        let mut lines = Vec::new();
+        for &(d_row, d_col) in &[(0, 1), (1, 0), (1, 1), (1, -1)] {
+            for row in 0..rows {
+                for col in 0..cols {
+                    let piece = match at(row, col) {
+                        Some(piece) => piece,
+                        None => continue,
+                    };
+                    // Only start walking at the first tile of a line, so that each line is
+                    // found exactly once
+                    if at(row - d_row, col - d_col) == Some(piece) {
+                        continue;
+                    }
+
+                    let mut line = Vec::new();
+                    let (mut r, mut c) = (row, col);
+                    while at(r, c) == Some(piece) {
+                        line.push((r as usize, c as usize));
+                        r += d_row;
+                        c += d_col;
+                    }
+                    if line.len() >= self.win_length {
+                        lines.push((piece, line));
+                    }
+                }
+            }
+        }
+        lines
+    }
+
     // We can define helpful accessor functions for common questions that will be asked about this
     // type. This makes it so that people using this type won't have to rely on how the type is
     // represented.
@@ -342,176 +724,349 @@ impl Game {
         // field of this struct.
         &self.tiles
     }
+
+    // The number of rows on the board
+    pub fn rows(&self) -> usize {
+        self.tiles.len()
+    }
+
+    // The number of columns on the board
+    pub fn cols(&self) -> usize {
+        self.tiles[0].len()
+    }
+
+    // The number of pieces that need to be in a line to win the game
+    pub fn win_length(&self) -> usize {
+        self.win_length
+    }
+
+    // Returns true if pieces fall to the bottom of their column (i.e. this is Connect Four)
+    pub fn has_gravity(&self) -> bool {
+        self.gravity
+    }
 }
 
-// This type is used to provide an error when the user provides an invalid move string. If we
+// This type is used to provide an error when the user provides an invalid move string. Each variant
+// keeps a copy of the string so that the error can be shown without the original input. If we
 // wanted to avoid copying the invalid string, we could use &str instead and Rust would enforce at
-// compile time that the reference remained valid until any instance of InvalidPiece containing it
-// goes out of scope. String is used for the same of simplicity. By marking the type stored in this
-// struct as `pub`, its value can be freely accessed even in patterns (for example, match
-// statements).
+// compile time that the reference remained valid until any instance of InvalidMove containing it
+// goes out of scope. String is used for the sake of simplicity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidMove {
+    /// The move was too short to have both a row number and a column letter
+    WrongLength { input: String },
+
+    /// The row wasn't a number from 1 to the number of rows on the board
+    BadRow { input: String, rows: usize },
+
+    /// The column wasn't a letter naming one of the columns on the board
+    BadColumn { input: String, cols: usize },
+}
+
+impl InvalidMove {
+    // Returns the string that couldn't be read as a move
+    pub fn input(&self) -> &str {
+        match self {
+            InvalidMove::WrongLength {input}
+            | InvalidMove::BadRow {input, ..}
+            | InvalidMove::BadColumn {input, ..} => input,
+        }
+    }
+}
+
+impl fmt::Display for InvalidMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid move: '{}'. ", self.input())?;
+        match *self {
+            InvalidMove::WrongLength {..} => {
+                write!(f, "A move is a row number followed by a column letter, e.g. 1A")
+            },
+            InvalidMove::BadRow {rows, ..} => write!(f, "The rows are numbered from 1 to {}", rows),
+            InvalidMove::BadColumn {cols: 0, ..} => write!(f, "The board has no columns"),
+            InvalidMove::BadColumn {cols, ..} => {
+                write!(f, "The columns are lettered from A to {}", column_name(cols - 1))
+            },
+        }
+    }
+}
+
+impl Error for InvalidMove {}
+
+// This type describes a player that isn't a person making an invalid move. Bots don't get a
+// second chance, so the game ends and `piece` loses. `error` explains what was wrong with the
+// move.
+#[derive(Debug, Clone)]
+pub struct Forfeit {
+    pub piece: Piece,
+    pub error: MoveError,
+}
+
+// These are the ways that running a game can end
 #[derive(Debug, Clone)]
-pub struct InvalidMove(pub String);
+pub enum Outcome {
+    /// The game was played to the end
+    Finished(Winner),
+
+    /// A player that isn't a person made an invalid move
+    Forfeit(Forfeit),
+
+    /// The input ran out before the game was finished
+    InputClosed,
+}
 
 //
 // foundation of the program and related
 // application logic must be implemented
 // in the foundation.
 //
-pub fn foundation()
+// The game is played starting from `game`, which is usually `Game::new()` but can also be a game
+// that was loaded from a file. If `computer` is Some piece, the computer plays that piece and the
+// user plays the other one. Otherwise two people take turns at the same keyboard.
+//
+pub fn foundation(game: Game, computer: Option<Piece>)
 {
-    // The constructor for Game creates a new, empty Tic-Tac-Toe board. `mut` signals that we plan
-    // to modify the value of the game variable. Rust will tell us if we forget to use this and
-    // warn us if we use it but it isn't needed.
-    let mut game = Game::new();
+    // `Box<dyn Player>` lets us store any kind of player in the same variable. The `as` tells Rust
+    // that we want the box to hold a trait object rather than this specific type.
+    let human = || Box::new(HumanPlayer) as Box<dyn Player>;
+    let computer_player = || Box::new(ComputerPlayer::new()) as Box<dyn Player>;
+    let (x, o) = match computer {
+        Some(Piece::X) => (computer_player(), human()),
+        Some(Piece::O) => (human(), computer_player()),
+        None => (human(), human()),
+    };
+
+    // `play` already printed the result of the game, so the only thing left to handle is an error
+    // while reading or writing the terminal.
+    if let Err(error) = play(game, x, o) {
+        eprintln!("Error: {}", error);
+    }
+} // end of function foundation
+
+// Plays a game in the terminal using stdin and stdout. See `run` for the details. The board is
+// printed in colour when stdout is a terminal that can show it (see `Theme::detect`).
+pub fn play(game: Game, x: Box<dyn Player>, o: Box<dyn Player>) -> io::Result<Outcome> {
+    play_with_theme(game, x, o, Theme::detect())
+}
+
+// Plays a game in the terminal like `play`, but prints the board using `theme`
+pub fn play_with_theme(mut game: Game, x: Box<dyn Player>, o: Box<dyn Player>, theme: Theme) -> io::Result<Outcome> {
+    // Locking stdin gives us a handle that implements BufRead. Locking stdout once up front is
+    // also faster than having every write lock it again.
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let (mut input, mut output) = (stdin.lock(), stdout.lock());
+    run_console(&mut game, &mut [x, o], &mut Console::with_theme(&mut input, &mut output, theme))
+}
 
-    // Let's continuously prompt the user for input using a loop until the game is finished
+// This is the game loop. It asks each player for their action in turn until the game is finished,
+// printing the board as it goes. `x` plays the X pieces and `o` plays the O pieces. Any type that
+// implements the Player trait can be used, so this is where custom bots plug in.
+// People playing the game read from `input` and everything is written to `output`. These are
+// generic, so anything from stdin to a byte string in a test can be used.
+// The board is printed without colours using the default theme.
+pub fn run<R: BufRead, W: Write>(mut game: Game, x: Box<dyn Player>, o: Box<dyn Player>, mut input: R,
+    mut output: W) -> io::Result<Outcome> {
+    run_console(&mut game, &mut [x, o], &mut Console::new(&mut input, &mut output))
+}
+
+// The game loop behind `run` and `play`, which works with any console. The tournament module plays
+// its games with it too. The game is borrowed, so the caller can still look at it once the loop is
+// over, e.g. to find out whose input was closed.
+pub(crate) fn run_console(game: &mut Game, players: &mut [Box<dyn Player>; 2], console: &mut Console) -> io::Result<Outcome> {
+    // Let's continuously ask for moves using a loop until the game is finished
     while !game.is_finished() {
         // First, print out the current board
-        print_tiles(game.tiles());
+        console.print_tiles(game.tiles())?;
 
         // Inform the user of who's turn it currently is
         // match will enforce that we do not forget any case and the string that it produces will
         // replace `{}` in the printed string.
-        println!("Current piece: {}", match game.current_piece() {
-            Piece::X => "x",
-            Piece::O => "o",
-        });
-
-        // prompt_move continuously prompts for a valid move from the user, determines exactly
-        // which position on the board that move is referring to, and then returns that move
-        let (row, col) = prompt_move();
+        let piece = game.current_piece();
+        writeln!(console, "Current piece: {}", piece.name())?;
+
+        // Each player decides what to do on their turn. For people, this prompts for a valid
+        // command and determines exactly which position on the board a move is referring to.
+        let player = &mut players[piece.index()];
+        let action = player.choose_action(game, console)?;
+
+        // People can see the move they just typed, but we have to tell them what the other
+        // players did
+        if let Some(Action::Move(row, col)) = action {
+            if !player.is_interactive() {
+                writeln!(console, "{} plays: {}", player.name(), format_move(row, col))?;
+            }
+        }
 
-        // Now that we have a move, let's attempt to make it
-        // We use match to account for every case of the result
-        match game.make_move(row, col) {
+        // `take_turn` makes the move (or undoes one) and tells us what happened
+        match take_turn(game, players, action) {
             // If the move is made successfully, we can just move on. You can think of empty
             // curly braces as an "empty expression". We could have also used the unit value `()`.
-            Ok(()) => {},
-            // Match allows us to conveniently match even nested types like Result and pull out the
-            // fields as variables
-
-            // Since we are using is_finished(), it should never be possible for this error to
-            // occur. If it does, that means that we (the programmer) did something wrong, not the
-            // user. `unreachable!()` works a lot like `println!();` except it exits the program
-            // with an error using the message that we provided it. Use `unreachable!()` whenever
-            // you encounter a case that you think should never be reached.
-            Err(MoveError::GameAlreadyOver) => unreachable!("Game was already over when it should not have been"),
-            // Since prompt_move limits the range of what can be returned, it should never allow
-            // the user to enter a move that is out of range. Thus, this case is unreachable as
-            // well.
-            Err(MoveError::InvalidPosition {row, col}) => {
-                unreachable!("Should not be able to enter an invalid move, but still got ({}, {})", row, col)
+            Turn::Played => {},
+
+            // The user gets told what was wrong and is asked again
+            Turn::Retry(message) => writeln!(console, "{}", message)?,
+
+            // Match allows us to conveniently match even nested types like Outcome and pull out
+            // the fields as variables. `ref` borrows the error instead of moving it out of the
+            // outcome, which we still want to return.
+            Turn::Over(outcome) => {
+                if let Outcome::Forfeit(Forfeit {piece, ref error}) = outcome {
+                    writeln!(console, "{} made an invalid move and forfeits! {}.", players[piece.index()].name(), error)?;
+                }
+                return Ok(outcome);
             },
 
-            // Notice that we have already eliminated two possible errors just by structuring our
-            // code in a certain way!
-
-            // This is the only case that prompt_move does not account for, so if this happens, we
-            // print an error message.
-            // The `eprintln!` macro is exactly the same as `println!` except it prints to stderr
-            // instead of stdout.
-            Err(MoveError::TileNotEmpty {other_piece, row, col}) => eprintln!(
-                // Each {} will be replaced with one of the arguments following this string
-                "The tile at position {}{} already has piece {} in it!",
-                // The row number that is displayed starts at 1, not zero, so we add 1 to get the
-                // correct value
-                row + 1,
-                // `b'A'` produces the ASCII character code for the letter A (i.e. 65)
-                // Adding col to it will produce either 65 (A), 66 (B), or 67 (C).
-                // `as u8` is necessary because b'A' has type u8 and we can't add u8 to usize
-                // without performing a conversion first.
-                // Converting it to char using `as char` will get Rust to format this as a
-                // character rather than printing the number out
-                (b'A' + col as u8) as char,
-                // match allows us to print something for each case and will tell us if something
-                // ever changes such that this is no longer complete
-                match other_piece {
-                    Piece::X => "x",
-                    Piece::O => "o",
-                },
-            ),
+            // The hint lists every move from best to worst, e.g. "Hint: 2B draws, 1A draws, ..."
+            Turn::Other(Action::Hint) => {
+                let hints: Vec<String> = hint(game, players).into_iter()
+                    .map(|((row, col), evaluation)| format!("{} {}", format_move(row, col), evaluation))
+                    .collect();
+                writeln!(console, "Hint: {}", hints.join(", "))?;
+            },
+            Turn::Other(Action::Save(path)) => {
+                let mut record = GameRecord::new(game);
+                record.set_header("X", players[0].name());
+                record.set_header("O", players[1].name());
+                // A file that can't be written shouldn't end the game, so we just report it
+                match record::save(&record, &path) {
+                    Ok(()) => writeln!(console, "Game saved to {}", path)?,
+                    Err(error) => writeln!(console, "Could not save the game to {}: {}", path, error)?,
+                }
+            },
+            // `take_turn` handles every other action itself
+            Turn::Other(_) => {},
         }
     }
 
     // Once the loop is over, the game is finished. Let's output the results
 
-    // First, we'll print the board again
-    print_tiles(game.tiles());
+    // First, we'll print the board again. print_game shows the winning line in capital letters.
+    console.print_game(game)?;
 
     // Then print out which piece won the game
     // We use expect() to express that there should definitely be a winner now and if the winner
     // method returns None, the program should exit with this error
-    match game.winner().expect("finished game should have winner") {
-        Winner::X => println!("x wins!"),
-        Winner::O => println!("o wins!"),
-        Winner::Tie => println!("Tie!"),
+    let winner = game.winner().expect("finished game should have winner");
+    match winner {
+        Winner::X => writeln!(console, "x wins!")?,
+        Winner::O => writeln!(console, "o wins!")?,
+        Winner::Tie => writeln!(console, "Tie!")?,
     }
-} // end of function foundation
+    Ok(Outcome::Finished(winner))
+} // end of function run
+
+// This is what happened when a player took their turn, see `take_turn`
+pub(crate) enum Turn {
+    /// A move was made, undone or redone
+    Played,
+
+    /// The action couldn't be done and the player gets to try again. The message says why.
+    Retry(String),
+
+    /// The game ended before it was finished
+    Over(Outcome),
+
+    /// Hints and saving depend on how the game is being shown, so they are left to the game loop
+    Other(Action),
+}
+
+// Carries out the action that the current player chose, or stops the game if they didn't choose
+// one. Every game loop (`run_console` and the one in the tui module) takes its turns through here,
+// so the rules for undoing and forfeiting are the same everywhere.
+pub(crate) fn take_turn(game: &mut Game, players: &[Box<dyn Player>; 2], action: Option<Action>) -> Turn {
+    let piece = game.current_piece();
+    let result = match action {
+        // The player has nothing more to say, so we stop here without a winner
+        None => return Turn::Over(Outcome::InputClosed),
+        Some(Action::Move(row, col)) => game.make_move(row, col),
+        // A move that couldn't even be read is handled like any other invalid move
+        Some(Action::Invalid(error)) => Err(error),
+        // When playing against the computer, we also take back (or replay) the computer's move so
+        // that it is the user's turn again afterwards
+        Some(Action::Undo) => {
+            if game.undo().is_none() {
+                return Turn::Retry("There are no moves to undo!".to_string());
+            }
+            if !players[game.current_piece().index()].is_interactive() {
+                game.undo();
+            }
+            return Turn::Played;
+        },
+        Some(Action::Redo) => {
+            if game.redo().is_none() {
+                return Turn::Retry("There are no moves to redo!".to_string());
+            }
+            if !players[game.current_piece().index()].is_interactive() {
+                game.redo();
+            }
+            return Turn::Played;
+        },
+        Some(other) => return Turn::Other(other),
+    };
+
+    // We use match to account for every case of the result of the move
+    match result {
+        Ok(()) => Turn::Played,
+
+        // The game loops only ask for a move while the game is going, so it should never be
+        // possible for this error to occur. If it does, that means that we (the programmer) did
+        // something wrong, not the user. `unreachable!()` works a lot like `println!();` except it
+        // exits the program with an error using the message that we provided it. Use
+        // `unreachable!()` whenever you encounter a case that you think should never be reached.
+        Err(MoveError::GameAlreadyOver) => unreachable!("Game was already over when it should not have been"),
+
+        // The `if` after a pattern is called a "match guard". Bots don't get a second chance,
+        // so an invalid move from them ends the game.
+        Err(error) if !players[piece.index()].is_interactive() => Turn::Over(Outcome::Forfeit(Forfeit {piece, error})),
+
+        // Display puts the message together for us, see `impl fmt::Display for MoveError` above
+        Err(error) => Turn::Retry(format!("{}!", error)),
+    }
+}
+
+// Scores every legal move for the hint, best move first. A player that knows the answers without
+// searching, like one with a tablebase, is asked first. Otherwise the position is searched by
+// `ai::analyze`.
+pub(crate) fn hint(game: &Game, players: &[Box<dyn Player>; 2]) -> Vec<((usize, usize), ai::Evaluation)> {
+    players.iter().find_map(|player| player.analyze(game)).unwrap_or_else(|| ai::analyze(game))
+}
 
 // Functions do not need to be ordered in any particular way in the file. That means that Rust
 // doesn't suffer from any forward declaration issues where those declarations can get out of sync
 // with the actual function implementation.
 
-// This function returns a "tuple" of two values, the row and column of the selected move. Tuples
-// are very useful for when you have a function that needs to return two values because it saves
-// you from having to define a custom struct just for that purpose.
-fn prompt_move() -> (usize, usize) {
-    // We'll use `loop` to continuously prompt for input until the user provides what we want. When
-    // we get the answer we want, the loop will return the value and it will be used as the return
-    // value of this function
-    loop {
-        // Rust supports convenient `print!` and `println!` macros which support easy and
-        // customizable formatting of values from your program. Here we are just using them to
-        // prompt for some values that we want the user of our program to provide.
-        print!("Enter move (e.g. 1A): ");
-
-        // Line-buffering is when something waits until it sees a new line character before
-        // actually writing to its designated destination. Rust's stdout is line-buffered by
-        // default, so `print!` does not produce any output unless we "flush" the contents of
-        // stdout's buffer in the line below.
-        // expect() is how we "ignore" any error that could occur during this process. If an error
-        // does occur, the program will exit with the message we provided.
-        io::stdout().flush().expect("Failed to flush stdout");
-
-        // The read_line() function is something we defined below to make reading input quick and
-        // easy.
-        let line = read_line();
-
-        // We delegate reading the line as a move to the parse_move function. That function takes a
-        // string and converts it to a "tuple" of two values (row, col). The read_line function
-        // returns the type String, but parse_move expects a &str. We use `&` here to convert
-        // String to &String. Rust then automatically converts &String to &str. This isn't a
-        // special case for just strings, Rust supports a feature called "deref conversions" and
-        // this is just a consequence of that. For more information, see:
-        // http://hermanradtke.com/2015/05/03/string-vs-str-in-rust-functions.html
-        match parse_move(&line) {
-            // The benefit of parse_move returning a Result is that we can't forget to handle the
-            // case where the input might be invalid. match gives us a convenient syntax for
-            // handling each case.
-
-            // Rust allows us to "return" a value from a loop by providing it to break. When
-            // the loop exits, this will be the return value of the function too because the loop
-            // is the last statement in this function.
-            Ok((row, col)) => break (row, col),
-            // Instead of defining methods to extract the value from InvalidMove, we can use
-            // pattern matching to extract its value and print a helpful error message. The
-            // `eprintln!` macro is exactly the same as `println!` except it prints to stderr
-            // instead of stdout.
-            Err(InvalidMove(invalid_str)) => eprintln!(
-                // The `{}` is replaced with the next argument passed to eprintln. We can pass an
-                // arbitrary amount of arguments and Rust can even tell us at compile time if there
-                // is a mismatch between the number of {} and the number of additional arguments
-                // passed.
-                "Invalid move: '{}'. Please try again.",
-                invalid_str,
-            ),
-        }
+// Formats a move the same way that the user types it in, e.g. (0, 0) becomes 1A. A move can come
+// from a bot and be anywhere, so a column without a letter is written out in full instead, e.g.
+// (0, 200) becomes "row 1, column 201".
+pub fn format_move(row: usize, col: usize) -> String {
+    // Converting to u128 first means that adding 1 can't overflow, even for the biggest usize
+    let row = row as u128 + 1;
+    if col < MAX_COLS {
+        format!("{}{}", row, column_name(col))
     }
+    else {
+        format!("row {}, column {}", row, column_name(col))
+    }
+}
+
+// Returns the letter used to name a column, e.g. 0 becomes A. Columns past Z don't have a letter,
+// so they are numbered from 1 instead.
+fn column_name(col: usize) -> String {
+    if col >= MAX_COLS {
+        return (col as u128 + 1).to_string();
+    }
+    // `b'A'` produces the ASCII character code for the letter A (i.e. 65)
+    // Adding col to it will produce either 65 (A), 66 (B), or 67 (C), and so on.
+    // `as u8` is necessary because b'A' has type u8 and we can't add u8 to usize without
+    // performing a conversion first. The check above makes sure that this can't overflow.
+    // Converting it to char using `as char` will get Rust to format this as a character rather
+    // than printing the number out
+    ((b'A' + col as u8) as char).to_string()
 }
 
-// This function gets the row and column of the move the user entered. If the string doesn't
-// represent a valid move, we return Result::Err to indicate failure.
+// This function gets the row and column of a move written the way the user types it in, such as
+// 1A or 12c. If the string doesn't represent a move on a board with `rows` rows and `cols`
+// columns, we return Result::Err to indicate failure along with the reason why.
 // We pretty much always want to use &str instead of String in function arguments.
 // For learn why, see:
 // http://hermanradtke.com/2015/05/03/string-vs-str-in-rust-functions.html
@@ -519,150 +1074,50 @@ fn prompt_move() -> (usize, usize) {
 // features of Rust. However, notice though that we don't really lose anything or make anything
 // worse for ourselves by keeping it simple. Rust lets you write nice code even if you haven't
 // mastered all of its features just yet.
-fn parse_move(input: &str) -> Result<(usize, usize), InvalidMove> {
-    // The move will be in the format 1A, 2C, 3B, etc.
-    // Let's start by rejecting any input that isn't of size 2
-    if input.len() != 2 {
+pub fn parse_move(input: &str, rows: usize, cols: usize) -> Result<(usize, usize), InvalidMove> {
+    // The move will be in the format 1A, 2C, 12B, etc. That is, a row number followed by a
+    // single column letter.
+    // Let's start by rejecting any input that is too short to contain both.
+    // `chars` goes through the characters of the string rather than its bytes, so a letter with an
+    // accent counts as one character even though it takes up more than one byte.
+    if input.chars().count() < 2 {
         // We use `return` to exit early from this function in case the size of the input is
         // incorrect.
-        return Err(InvalidMove(input.to_string()));
+        return Err(InvalidMove::WrongLength {input: input.to_string()});
     }
 
+    // `char_indices` gives us the position of each character in bytes, so the last one tells us
+    // where to split off the column letter. `unwrap` is fine here because we know the string isn't
+    // empty.
+    let (last, _) = input.char_indices().last().unwrap();
+    let (row_str, col_str) = input.split_at(last);
+
     // Let's start by getting the row number
+    // `parse` would also accept something like "+1", so we make sure that there are only digits.
     // Using match allows us to easily accept the cases we want to support and reject everything
-    // else. If none of the cases match, an error will be returned.
-    let row = match &input[0..1] {
-        "1" => 0,
-        "2" => 1,
-        "3" => 2,
-        _ => return Err(InvalidMove(input.to_string())),
+    // else. The `if` after a pattern is called a "match guard" and must also be true for that
+    // case to match.
+    let row = match row_str.parse::<usize>() {
+        Ok(row) if row_str.bytes().all(|b| b.is_ascii_digit()) && row >= 1 && row <= rows => row - 1,
+        _ => return Err(InvalidMove::BadRow {input: input.to_string(), rows}),
     };
 
-    let col = match &input[1..2] {
-        // Rust lets us match against multiple patterns using | to separate them. This
-        // lets us accept either lowercase or uppercase versions of the letters.
-        "A" | "a" => 0,
-        "B" | "b" => 1,
-        "C" | "c" => 2,
+    // `to_ascii_uppercase` lets us accept either lowercase or uppercase versions of the letters.
+    // Characters that aren't ASCII are left as they are, so they won't match below.
+    let letter = col_str.chars().next().unwrap().to_ascii_uppercase();
+    let col = match letter {
+        // `'A'..='Z'` matches any letter, then we convert it to a column number and make sure
+        // that column is actually on the board.
+        'A'..='Z' if ((letter as u8 - b'A') as usize) < cols => (letter as u8 - b'A') as usize,
 
         // We didn't find a match so far, so the string must be invalid. We use the `Err`
         // variant of Result to express that.
         // We can convert a &str to a String using `to_string()`. InvalidMove expects a String,
         // so we need to do this for this code to work.
-        invalid => return Err(InvalidMove(invalid.to_string())),
+        _ => return Err(InvalidMove::BadColumn {input: input.to_string(), cols}),
     };
 
     // The last line of the function is the return value, so we construct the tuple that we want
     // to return with the move that the user selected
     Ok((row, col))
 }
-
-// This function is something we've defined to make reading a line of input convenient. Rust gives
-// us a lot of control over our program so we could do many fancy things like buffer the input as
-// we read it or properly handle error conditions. However, since this is a simple application, we
-// have chosen to just exit the program when an error occurs and do no extra buffering of the
-// input. Since we're just reading a line at a time and we expect the lines to be short, this
-// should not cause problems in the majority of cases. Rust gives us the power to make that choice
-// explicitly and know that we are making it in the code.
-fn read_line() -> String {
-    // This creates a new growable/heap-allocated string. The `mut` after `let` declares that we
-    // plan to modify the string. Saying this explicitly lets the compiler automatically check that
-    // we don't modify any variables that we don't intend to. Many languages encourage you to use
-    // `const` or `final` on pretty much everything until you don't need to. In Rust, that
-    // behaviour is by default.
-    let mut input = String::new();
-    // Here, we read a line of input from the standard input stream stdin. `&mut input` passes a
-    // mutable reference to the String in the input variable. This allows the function to modify
-    // input without taking ownership of its value. That way we can return it from this function
-    // afterwards.
-    // expect() is a function that takes a Result value and exits the program with an error message
-    // if the Result value is anything other than Ok(...). This in a way is "ignoring" any error
-    // that can occur while reading input. However, instead of ignoring it implicitly, we explciitly
-    // call out that we intend to just exit the program with an error if this operation fails. This
-    // is one of the ways that Rust gives you control. Don't want to deal with a potential failure?
-    // You don't have to! But it's really nice to know where the error came from if something ever
-    // does go wrong and you want to figure out why.
-    io::stdin().read_line(&mut input).expect("Failed to read input");
-
-    // An empty string will only be returned if we reach the end of input (otherwise we always
-    // receive at least a newline character).
-    if input.is_empty() {
-        // We print a final newline because otherwise the cursor may still be at the end of one
-        // of our `print!` calls earlier.
-        println!();
-
-        // process::exit(0) indicates that the program exited successfully. This will end the
-        // program right here, and none of the rest of our code will run.
-        process::exit(0);
-    }
-
-    // read_line leaves the trailing newline on the string, so we remove it using truncate. By
-    // modifying the string in place, we avoid copying its contents after it was just allocated.
-    let len_without_newline = input.trim_end().len();
-    input.truncate(len_without_newline);
-
-    // The last expression in a function is returned from that function. We want to return the
-    // line that was read, so we put that variable on its own at the end of the function in order
-    // to provide it as the result of this function.
-    input
-}
-
-// This function is used to print out the board in a human readable way
-fn print_tiles(tiles: &Tiles) {
-    // The result of this function will be something like the following:
-    //   A B C
-    // 1 x ▢ ▢
-    // 2 ▢ ▢ o
-    // 3 ▢ ▢ ▢
-    //
-    // The boxes represent empty tiles, and x and o are placed wherever a tile is filled.
-
-    // First we print the space before the column letters
-    print!("  ");
-    // Then we look from the numbers 0 to 2.
-    // `a..b` creates a "range" of numbers from a to one less than b.
-    // `tiles[0].len()` gets the number of columns (i.e. 2)
-    // `as u8` converts the length from the type `usize` to the type `u8` so that it works in the
-    // body of the loop
-    for j in 0..tiles[0].len() as u8 {
-        // `b'A'` produces the ASCII character code for the letter A (i.e. 65)
-        // By adding j to it, we get 'A', then 'B', and then 'C'.
-        // We don't just want to print the ASCII character code, so we convert that number into
-        // a character using `as char`. That way Rust will print it correctly.
-        print!(" {}", (b'A' + j) as char);
-    }
-    // This prints the final newline after the row of column letters
-    println!();
-
-    // Now we print each row preceeded by its row number
-    // .iter().enumerate() goes through each row and provides a row number with each element using
-    // a tuple.
-    for (i, row) in tiles.iter().enumerate() {
-        // We print the row number with a space in front of it
-        print!(" {}", i + 1);
-        // Now we go through each tile in the row and print it out
-        for tile in row {
-            // Here, we match on the value of the tile. We use `*` to "dereference" the tile and
-            // match on its value of type Option<Piece>. This is just for convenience and is
-            // actually something that future versions of Rust might not even require in order to
-            // match on something as simple as this.
-            print!(" {}", match *tile {
-                // The string produced by this match will be printed in `print!`. This match works
-                // because we return the same type, &str, in each branch. Rust still requires that
-                // if a match statement produces a value, it produces a value of the same type in
-                // every branch.
-                // Notice that we don't need to create another match for the piece produced in
-                // Some(...). Rust allows us to match arbitrarily nested structures with no
-                // additional syntax.
-                Some(Piece::X) => "x",
-                Some(Piece::O) => "o",
-                None => "\u{25A2}",
-            });
-        }
-        // We finish each row by printing a final new line
-        println!();
-    }
-
-    // Add an extra line at the end of the board to space it out from the prompts that follow
-    println!();
-}
@@ -4,10 +4,792 @@
 // gmail: <michaelbrockus@gmail.com>
 //
 extern crate program;
-use program::foundation;
+use program::console::Console;
+use program::engine::{self, EnginePlayer};
+use program::mcts::{Budget, Playout};
+use program::book::OpeningBook;
+use program::player::{
+    BookPlayer, ComputerPlayer, Difficulty, HumanPlayer, MctsPlayer, Player, RandomPlayer, TablebasePlayer, DIFFICULTY_NAMES,
+};
+use program::theme::{Theme, THEME_NAMES};
+use program::tournament::{self, Format, Settings, Sprt};
+use program::solver::{self, Position, Solver};
+use program::stats::{self, Tally};
+use program::tablebase::Tablebase;
+use program::{ai, format_move, record, Game};
+use std::env;
+use std::io;
+use std::process;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// This is printed for `--help` and when the command-line arguments don't make sense
+const USAGE: &str = "usage: connect-rusty [command] [options]
+
+commands:
+    play                 play a game in the terminal (the default)
+    replay <file>        show every move of a game saved with the save command
+    analyze <position>   score every move in a position string, e.g. \"x2/1o1/3 x\"
+    bench                time the computer player on a few positions
+    solve <moves>        solve a Connect Four position given as columns from 1 to 7, e.g. 4453
+    stats                count every position and game on a small board
+    tablebase <file>     work out every position on a small board and save the results to a file
+    book <file>          build an opening book and save it to a file
+    engine               let another program play the computer over stdin and stdout
+    tournament           play games between computer players and compare how strong they are
+
+play options:
+    --variant <name>     tic-tac-toe (the default), connect-four or a rules string like 7x7k4g
+    --size <rows>x<cols> change the size of the board
+    --win <length>       change the number of pieces in a line needed to win
+    --gravity            make pieces fall to the bottom of their column
+    -x <player>          who plays x: human (the default), computer, random, mcts or engine:<program>
+    -o <player>          who plays o: human (the default), computer, random, mcts or engine:<program>
+    --ai <x|o>           let the computer play x or o
+    --depth <moves>      how many moves ahead the computer looks (the default depends on the board)
+    --level <name>       how well the computer plays: easy, medium, hard or perfect (the default)
+    --iterations <n>     how many playouts the mcts player runs per move
+    --millis <ms>        how long the mcts player thinks per move (the default is 1000)
+    --playout <kind>     how the mcts player finishes games: heuristic (the default) or random
+    --seed <n>           make the random and mcts players repeatable
+    --tablebase <file>   look the computer's moves and the hints up in a tablebase, and only search
+                         for the positions it doesn't have
+    --book <file>        let the computer and mcts players start with moves from an opening book
+    --load <file>        continue a game saved with the save command
+    --theme <name>       how the board looks: plain, unicode, color, discs or high-contrast
+    --tui                play full screen with the arrow keys (needs the tui feature)
+
+analyze and bench options:
+    --depth <moves>      how many moves ahead to look
+    --tablebase <file>   look the moves up in a tablebase, and only search to --depth for the
+                         positions it doesn't have (analyze only)
+
+solve options:
+    --weak               only find out who wins, which is much faster
+
+stats, tablebase and book options:
+    --variant, --size, --win and --gravity pick the board, just like for play
+    --symmetry           count positions that are the same after turning the board only once (stats)
+    --plies <moves>      how many moves from the start the book covers (the default is 4)
+    --self-play <games>  build the book from games between mcts players instead of searching
+
+engine options:
+    --player <player>    who makes the moves: computer (the default), random, mcts or engine:<program>
+    --depth, --level, --iterations, --millis, --playout, --seed, --tablebase and --book set up the
+    player, just like for play
+
+tournament options:
+    --player <player>    a player and its options, e.g. \"mcts --iterations 500\" (at least two)
+    --gauntlet           only let the first player play the others (the default is everyone)
+    --games <n>          how many games each pair of players plays (the default is 10)
+    --openings <moves>   how many random moves start each game (the default is 2)
+    --threads <n>        how many games are played at the same time (the default is one per CPU)
+    --seed <n>           play the same games again
+    --sprt <elo0,elo1>   stop once it is clear whether the first of two players is elo0 or elo1 better
+    --variant, --size, --win and --gravity pick the board, just like for play
+
+other options:
+    -h, --help           print this message
+    -V, --version        print the version of connect-rusty";
+
+// The kinds of player that can be picked with -x and -o
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PlayerKind {
+    Human,
+    Computer,
+    Random,
+    Mcts,
+    // An engine program along with its arguments, separated by spaces
+    Engine(String),
+}
+
+// Everything that can be set with the options of the play command
+struct PlayOptions {
+    variant: Option<String>,
+    size: Option<(usize, usize)>,
+    win_length: Option<usize>,
+    gravity: bool,
+    x: PlayerKind,
+    o: PlayerKind,
+    depth: Option<usize>,
+    level: Option<Difficulty>,
+    budget: Budget,
+    playout: Playout,
+    seed: Option<u64>,
+    tablebase: Option<String>,
+    book: Option<String>,
+    load: Option<String>,
+    theme: Option<Theme>,
+    tui: bool,
+}
+
+// The things that the program can be asked to do
+enum Command {
+    Play(PlayOptions),
+    Replay(String),
+    Analyze { position: String, depth: Option<usize>, tablebase: Option<String> },
+    Bench { depth: usize },
+    Solve { moves: String, weak: bool },
+    Stats { options: PlayOptions, symmetric: bool },
+    Tablebase { path: String, options: PlayOptions },
+    Book { path: String, options: PlayOptions, plies: usize, self_play: Option<usize> },
+    Engine(PlayOptions),
+    Tournament { entrants: Vec<(String, PlayOptions)>, board: PlayOptions, settings: Settings },
+    Help,
+    Version,
+}
 
 // main is where program execution starts
 fn main()
 {
-    foundation();
+    // The first argument is always the name of the program, so we skip it
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = match parse_args(&args) {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        },
+    };
+
+    let result = match command {
+        Command::Play(options) => play(options),
+        Command::Replay(path) => replay(&path),
+        Command::Analyze {position, depth, tablebase} => analyze(&position, depth, tablebase.as_deref()),
+        Command::Bench {depth} => bench(depth),
+        Command::Solve {moves, weak} => solve(&moves, weak),
+        Command::Stats {options, symmetric} => stats(&options, symmetric),
+        Command::Tablebase {path, options} => tablebase(&path, &options),
+        Command::Book {path, options, plies, self_play} => book(&path, &options, plies, self_play),
+        Command::Engine(options) => engine(&options),
+        Command::Tournament {entrants, board, settings} => tournament(&entrants, &board, &settings),
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        },
+        Command::Version => {
+            // Cargo sets this environment variable to the version in Cargo.toml while compiling
+            println!("connect-rusty {}", env!("CARGO_PKG_VERSION"));
+            Ok(())
+        },
+    };
+
+    if let Err(message) = result {
+        eprintln!("error: {}", message);
+        process::exit(1);
+    }
 } // end of function main
+
+// Works out which command to run from the command-line arguments. Problems with the arguments are
+// returned as a message for the user.
+fn parse_args(args: &[String]) -> Result<Command, String> {
+    // Without a command name, the arguments are options for `play`, which keeps the original
+    // `connect-rusty --ai o` style working
+    let (name, rest) = match args.first() {
+        Some(first) if !first.starts_with('-') => (first.as_str(), &args[1..]),
+        _ => ("play", args),
+    };
+
+    // --help and --version work with any command
+    if rest.iter().any(|arg| arg == "-h" || arg == "--help") {
+        return Ok(Command::Help);
+    }
+    if rest.iter().any(|arg| arg == "-V" || arg == "--version") {
+        return Ok(Command::Version);
+    }
+
+    match name {
+        "play" => parse_play_options(rest).map(Command::Play),
+        "replay" => match rest {
+            [path] => Ok(Command::Replay(path.clone())),
+            _ => Err("replay needs the file of a saved game".to_string()),
+        },
+        "analyze" => match rest {
+            [position, options @ ..] if !position.starts_with('-') => parse_analyze_options(position, options),
+            _ => Err("analyze needs a position string".to_string()),
+        },
+        // The benchmark needs a depth so that it finishes in a reasonable amount of time
+        "bench" => Ok(Command::Bench {depth: parse_depth_option(rest)?.unwrap_or(8)}),
+        // The empty board is written as an empty string, or left out
+        "solve" => {
+            let weak = rest.iter().any(|arg| arg == "--weak");
+            match rest.iter().filter(|arg| *arg != "--weak").collect::<Vec<_>>().as_slice() {
+                [] => Ok(Command::Solve {moves: String::new(), weak}),
+                [moves] if !moves.starts_with('-') => Ok(Command::Solve {moves: moves.to_string(), weak}),
+                _ => Err("solve needs a string of moves such as 4453".to_string()),
+            }
+        },
+        // The board is picked with the same options as for play. The other play options don't
+        // mean anything here, so they are rejected. None of the values start with '-', so every
+        // argument that does is an option.
+        "stats" => {
+            let symmetric = rest.iter().any(|arg| arg == "--symmetry");
+            let rest: Vec<String> = rest.iter().filter(|arg| *arg != "--symmetry").cloned().collect();
+            Ok(Command::Stats {options: parse_board_options(&rest)?, symmetric})
+        },
+        "tablebase" => match rest {
+            [path, options @ ..] if !path.starts_with('-') => {
+                Ok(Command::Tablebase {path: path.clone(), options: parse_board_options(options)?})
+            },
+            _ => Err("tablebase needs the file to save the tablebase to".to_string()),
+        },
+        // --plies and --self-play are taken out first, and the rest pick the board
+        "book" => match rest {
+            [path, options @ ..] if !path.starts_with('-') => {
+                let (mut plies, mut self_play, mut board) = (4, None, Vec::new());
+                let mut options = options.iter();
+                while let Some(arg) = options.next() {
+                    match arg.as_str() {
+                        "--plies" | "--self-play" => {
+                            let value = options.next().ok_or_else(|| format!("{} needs a value", arg))?;
+                            let value = parse_number(value)?;
+                            if arg == "--plies" { plies = value } else { self_play = Some(value) }
+                        },
+                        _ => board.push(arg.clone()),
+                    }
+                }
+                Ok(Command::Book {path: path.clone(), options: parse_board_options(&board)?, plies, self_play})
+            },
+            _ => Err("book needs the file to save the book to".to_string()),
+        },
+        // --player picks the built-in player, and the rest set it up like for play
+        "engine" => {
+            let (mut kind, mut options) = (PlayerKind::Computer, Vec::new());
+            let mut args = rest.iter();
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--player" => {
+                        let value = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+                        kind = parse_player(value)?;
+                    },
+                    _ => options.push(arg.clone()),
+                }
+            }
+            Ok(Command::Engine(parse_bot_options(kind, &options, true)?))
+        },
+        // Each --player is a player followed by its options, e.g. "mcts --iterations 500", and
+        // the options that aren't about the tournament pick the board
+        "tournament" => {
+            let (mut entrants, mut board) = (Vec::new(), Vec::new());
+            let mut settings = Settings {
+                threads: thread::available_parallelism().map_or(1, |threads| threads.get()),
+                seed: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64),
+                ..Settings::default()
+            };
+            let mut args = rest.iter();
+            while let Some(arg) = args.next() {
+                if arg == "--gauntlet" {
+                    settings.format = Format::Gauntlet;
+                    continue;
+                }
+                let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+                match arg.as_str() {
+                    "--player" => {
+                        let spec = value()?;
+                        entrants.push((spec.clone(), parse_entrant(spec)?));
+                    },
+                    "--games" => settings.games = parse_number(value()?)?,
+                    "--openings" => settings.opening_plies = parse_number(value()?)?,
+                    "--threads" => settings.threads = parse_number(value()?)?.max(1),
+                    "--seed" => settings.seed = parse_number(value()?)? as u64,
+                    "--sprt" => {
+                        let value = value()?;
+                        let bounds = value.split_once(',')
+                            .and_then(|(elo0, elo1)| Some((elo0.trim().parse().ok()?, elo1.trim().parse().ok()?)));
+                        let (elo0, elo1) = bounds.ok_or_else(|| format!("--sprt needs two Elo numbers like 0,10, not '{}'", value))?;
+                        settings.sprt = Some(Sprt::new(elo0, elo1));
+                    },
+                    _ => board.push(arg.clone()),
+                }
+            }
+            if entrants.len() < 2 {
+                return Err("tournament needs at least two --player options".to_string());
+            }
+            if settings.sprt.is_some() && entrants.len() != 2 {
+                return Err("--sprt only works with two players".to_string());
+            }
+            Ok(Command::Tournament {entrants, board: parse_board_options(&board)?, settings})
+        },
+        "help" => Ok(Command::Help),
+        other => Err(format!("unknown command '{}'", other)),
+    }
+}
+
+// Reads the options of the play command
+fn parse_play_options(args: &[String]) -> Result<PlayOptions, String> {
+    let mut options = PlayOptions {
+        variant: None,
+        size: None,
+        win_length: None,
+        gravity: false,
+        x: PlayerKind::Human,
+        o: PlayerKind::Human,
+        depth: None,
+        level: None,
+        budget: Budget::Time(Duration::from_millis(1000)),
+        playout: Playout::Heuristic,
+        seed: None,
+        tablebase: None,
+        book: None,
+        load: None,
+        theme: None,
+        tui: false,
+    };
+
+    // Each option except --gravity and --tui is followed by its value, which we get by calling
+    // `next` again
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--gravity" {
+            options.gravity = true;
+            continue;
+        }
+        if arg == "--tui" {
+            options.tui = true;
+            continue;
+        }
+        let value = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+        match arg.as_str() {
+            "--variant" => options.variant = Some(value.clone()),
+            "--size" => {
+                let size = value.split_once('x')
+                    .and_then(|(rows, cols)| Some((rows.parse().ok()?, cols.parse().ok()?)));
+                options.size = Some(size.ok_or_else(|| format!("invalid board size '{}'", value))?);
+            },
+            "--win" => options.win_length = Some(parse_number(value)?),
+            "-x" => options.x = parse_player(value)?,
+            "-o" => options.o = parse_player(value)?,
+            "--ai" => match value.as_str() {
+                "x" | "X" => options.x = PlayerKind::Computer,
+                "o" | "O" => options.o = PlayerKind::Computer,
+                _ => return Err(format!("--ai must be x or o, not '{}'", value)),
+            },
+            "--depth" => options.depth = Some(parse_number(value)?),
+            "--level" => options.level = Some(Difficulty::named(value).ok_or_else(|| {
+                format!("unknown level '{}', expected one of {}", value, DIFFICULTY_NAMES.join(", "))
+            })?),
+            "--iterations" => options.budget = Budget::Iterations(parse_number(value)? as u64),
+            "--millis" => options.budget = Budget::Time(Duration::from_millis(parse_number(value)? as u64)),
+            "--playout" => options.playout = match value.as_str() {
+                "heuristic" => Playout::Heuristic,
+                "random" => Playout::Random,
+                _ => return Err(format!("--playout must be heuristic or random, not '{}'", value)),
+            },
+            "--seed" => options.seed = Some(parse_number(value)? as u64),
+            "--tablebase" => options.tablebase = Some(value.clone()),
+            "--book" => options.book = Some(value.clone()),
+            "--load" => options.load = Some(value.clone()),
+            "--theme" => options.theme = Some(Theme::named(value).ok_or_else(|| {
+                format!("unknown theme '{}', expected one of {}", value, THEME_NAMES.join(", "))
+            })?),
+            _ => return Err(format!("unknown option '{}'", arg)),
+        }
+    }
+    if options.depth.is_some() && options.level.is_some() {
+        return Err("--depth and --level can't be used together".to_string());
+    }
+    Ok(options)
+}
+
+// Reads the options that pick the board, which are the same as for play. The other play options
+// don't mean anything outside of a game, so they are rejected. None of the values start with '-',
+// so every argument that does is an option.
+fn parse_board_options(args: &[String]) -> Result<PlayOptions, String> {
+    let board = ["--variant", "--size", "--win", "--gravity"];
+    match args.iter().find(|arg| arg.starts_with('-') && !board.contains(&arg.as_str())) {
+        Some(arg) => Err(format!("unknown option '{}'", arg)),
+        None => parse_play_options(args),
+    }
+}
+
+// Reads the options of a player that plays without a person, such as an engine or a player in a
+// tournament. Only the options that set up a player are allowed, and --seed only if `seeded`.
+fn parse_bot_options(kind: PlayerKind, args: &[String], seeded: bool) -> Result<PlayOptions, String> {
+    let player = ["--depth", "--level", "--iterations", "--millis", "--playout", "--tablebase", "--book"];
+    let allowed = |arg: &str| player.contains(&arg) || (seeded && arg == "--seed");
+    if let Some(arg) = args.iter().find(|arg| arg.starts_with('-') && !allowed(arg.as_str())) {
+        return Err(format!("unknown option '{}'", arg));
+    }
+    if kind == PlayerKind::Human {
+        return Err("a person can't play here, pick a computer player".to_string());
+    }
+    let mut options = parse_play_options(args)?;
+    options.x = kind;
+    Ok(options)
+}
+
+// Reads a player of a tournament, such as "computer --level easy". The players get their seeds
+// from the tournament. An engine's options belong to its program, so the whole string is its
+// command line.
+fn parse_entrant(spec: &str) -> Result<PlayOptions, String> {
+    if spec.starts_with("engine:") {
+        return parse_bot_options(parse_player(spec)?, &[], false);
+    }
+    let words: Vec<String> = spec.split_whitespace().map(str::to_string).collect();
+    match words.split_first() {
+        Some((kind, options)) => parse_bot_options(parse_player(kind)?, options, false),
+        None => Err("--player needs a player".to_string()),
+    }
+}
+
+// Reads the options of analyze, which can come in any order
+fn parse_analyze_options(position: &str, args: &[String]) -> Result<Command, String> {
+    let mut depth = None;
+    let mut tablebase = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or_else(|| format!("{} needs a value", arg))?;
+        match arg.as_str() {
+            "--depth" => depth = Some(parse_number(value)?),
+            "--tablebase" => tablebase = Some(value.clone()),
+            _ => return Err(format!("unknown option '{}'", arg)),
+        }
+    }
+    Ok(Command::Analyze {position: position.to_string(), depth, tablebase})
+}
+
+// Reads the options of commands that only take --depth
+fn parse_depth_option(args: &[String]) -> Result<Option<usize>, String> {
+    match args {
+        [] => Ok(None),
+        [option, value] if option == "--depth" => parse_number(value).map(Some),
+        _ => Err(format!("unknown options '{}'", args.join(" "))),
+    }
+}
+
+fn parse_number(value: &str) -> Result<usize, String> {
+    value.parse().map_err(|_| format!("'{}' is not a number", value))
+}
+
+fn parse_player(value: &str) -> Result<PlayerKind, String> {
+    match value {
+        "human" => Ok(PlayerKind::Human),
+        "computer" => Ok(PlayerKind::Computer),
+        "random" => Ok(PlayerKind::Random),
+        "mcts" => Ok(PlayerKind::Mcts),
+        _ => match value.strip_prefix("engine:") {
+            Some(command) if !command.trim().is_empty() => Ok(PlayerKind::Engine(command.to_string())),
+            _ => Err(format!("unknown player '{}', expected human, computer, random, mcts or engine:<program>", value)),
+        },
+    }
+}
+
+// Sets up the game and players and plays the game in the terminal
+fn play(options: PlayOptions) -> Result<(), String> {
+    let game = match &options.load {
+        // `and_then` only converts the record into a game if it was loaded successfully
+        Some(path) => record::load(path).and_then(|record| record.to_game())
+            .map_err(|error| format!("could not load {}: {}", path, error))?,
+        None => new_game(&options)?,
+    };
+
+    // Without --seed every game is different. x and o get different seeds so that two random
+    // players don't just copy each other.
+    let seed = options.seed.unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64)
+    });
+    let shared = Shared::load(&options)?;
+    let x = make_player(&options.x, seed, &options, &shared)?;
+    let o = make_player(&options.o, seed.wrapping_add(1), &options, &shared)?;
+
+    // A theme picked on the command line still leaves out the colors when they aren't wanted
+    let theme = options.theme.map_or_else(Theme::detect, Theme::for_stdout);
+    // `play` already printed the result of the game, so the only thing left to handle is an error
+    // while reading or writing the terminal
+    let result = if options.tui { play_tui(game, x, o, theme) } else { program::play_with_theme(game, x, o, theme) };
+    match result {
+        Ok(_) => Ok(()),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+// The tablebase and opening book are loaded once and shared by both players
+struct Shared {
+    tablebase: Option<Arc<Tablebase>>,
+    book: Option<Arc<OpeningBook>>,
+}
+
+impl Shared {
+    fn load(options: &PlayOptions) -> Result<Self, String> {
+        let tablebase = match &options.tablebase {
+            Some(path) => Some(Arc::new(Tablebase::load(path).map_err(|error| format!("could not load {}: {}", path, error))?)),
+            None => None,
+        };
+        let book = match &options.book {
+            Some(path) => Some(Arc::new(OpeningBook::load(path).map_err(|error| format!("could not load {}: {}", path, error))?)),
+            None => None,
+        };
+        Ok(Self {tablebase, book})
+    }
+}
+
+// Creates a player of the given kind using the rest of the play options. `Box<dyn Player>` lets us
+// return any kind of player from the same function. Only the players that search use the book. A
+// computer player with a tablebase only searches, to --depth or at its --level, for the positions
+// that aren't in the table.
+fn make_player(kind: &PlayerKind, seed: u64, options: &PlayOptions, shared: &Shared) -> Result<Box<dyn Player>, String> {
+    let player: Box<dyn Player> = match kind {
+        PlayerKind::Human => Box::new(HumanPlayer),
+        PlayerKind::Computer => {
+            let computer = match (options.depth, options.level) {
+                (Some(depth), _) => ComputerPlayer::with_depth(depth),
+                (None, Some(level)) => ComputerPlayer::with_difficulty(level, seed),
+                (None, None) => ComputerPlayer::new(),
+            };
+            match &shared.tablebase {
+                Some(tablebase) => Box::new(TablebasePlayer::new(Arc::clone(tablebase)).with_fallback(computer)),
+                None => Box::new(computer),
+            }
+        },
+        PlayerKind::Random => Box::new(RandomPlayer::with_seed(seed)),
+        PlayerKind::Mcts => Box::new(MctsPlayer::new(options.budget, seed).with_playout(options.playout)),
+        // The engine has its own settings, so it is started and left alone
+        PlayerKind::Engine(command) => {
+            let mut words = command.split_whitespace();
+            let program = words.next().ok_or("engine needs a program to run")?;
+            let args: Vec<String> = words.map(str::to_string).collect();
+            let engine = EnginePlayer::spawn(program, &args)
+                .map_err(|error| format!("could not start engine '{}': {}", command, error))?;
+            return Ok(Box::new(engine));
+        },
+    };
+    Ok(match (&shared.book, kind) {
+        (Some(book), PlayerKind::Computer) | (Some(book), PlayerKind::Mcts) => {
+            Box::new(BookPlayer::new(Arc::clone(book), player, seed))
+        },
+        _ => player,
+    })
+}
+
+// Lets another program play against one of the built-in players using the protocol in the engine
+// module. The player is picked with --player and set up with the same options as for play.
+fn engine(options: &PlayOptions) -> Result<(), String> {
+    let seed = options.seed.unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos() as u64)
+    });
+    let shared = Shared::load(options)?;
+    let mut player = make_player(&options.x, seed, options, &shared)?;
+    let name = player.name().to_string();
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    engine::serve(player.as_mut(), &name, stdin.lock(), stdout.lock()).map_err(|error| error.to_string())
+}
+
+// `#[cfg]` picks which version of this function gets compiled, depending on whether the tui
+// feature was turned on
+#[cfg(feature = "tui")]
+fn play_tui(game: Game, x: Box<dyn Player>, o: Box<dyn Player>, theme: Theme) -> io::Result<program::Outcome> {
+    program::tui::play_with_theme(game, x, o, theme)
+}
+
+#[cfg(not(feature = "tui"))]
+fn play_tui(_game: Game, _x: Box<dyn Player>, _o: Box<dyn Player>, _theme: Theme) -> io::Result<program::Outcome> {
+    Err(io::Error::other("this build doesn't include the terminal UI, rebuild it with --features tui"))
+}
+
+// Creates the board described by the --variant, --size, --win and --gravity options. The variant
+// gives the starting point and the other options change parts of it.
+fn new_game(options: &PlayOptions) -> Result<Game, String> {
+    let base = match options.variant.as_deref() {
+        None | Some("tic-tac-toe") => Game::new(),
+        Some("connect-four") => Game::connect_four(),
+        Some(rules) => Game::from_rules_string(rules)
+            .map_err(|error| format!("invalid variant '{}': {}", rules, error))?,
+    };
+
+    let (rows, cols) = options.size.unwrap_or((base.rows(), base.cols()));
+    let win_length = options.win_length.unwrap_or(base.win_length());
+    let game = if base.has_gravity() || options.gravity {
+        Game::with_gravity_config(rows, cols, win_length)
+    }
+    else {
+        Game::with_config(rows, cols, win_length)
+    };
+    game.map_err(|error| format!("invalid board: {}", error))
+}
+
+// Prints the board after every move of a saved game
+fn replay(path: &str) -> Result<(), String> {
+    let record = record::load(path).map_err(|error| format!("could not load {}: {}", path, error))?;
+    let game = record.to_game().map_err(|error| format!("could not replay {}: {}", path, error))?;
+
+    // The history of the loaded game has every move, so we can go back to the start and then
+    // step forward through it with redo
+    let mut replay = game.clone();
+    while replay.undo().is_some() {}
+
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let (mut input, mut output) = (stdin.lock(), stdout.lock());
+    let mut console = Console::with_theme(&mut input, &mut output, Theme::detect());
+    let io_error = |error: io::Error| error.to_string();
+
+    for (name, value) in &record.headers {
+        println!("{}: {}", name, value);
+    }
+    println!();
+    console.print_tiles(replay.tiles()).map_err(io_error)?;
+    for (i, recorded) in record.moves.iter().enumerate() {
+        let piece = replay.current_piece();
+        replay.redo();
+        println!("{}. {} plays {}", i + 1, piece.name(), format_move(recorded.row, recorded.col));
+        if let Some(comment) = &recorded.comment {
+            println!("   {{{}}}", comment);
+        }
+        console.print_game(&replay).map_err(io_error)?;
+    }
+    Ok(())
+}
+
+// Prints every move in a position from best to worst along with how good it is
+fn analyze(position: &str, depth: Option<usize>, tablebase: Option<&str>) -> Result<(), String> {
+    let game = Game::from_position_string(position)
+        .map_err(|error| format!("invalid position '{}': {}", position, error))?;
+    if game.is_finished() {
+        println!("The game is already over");
+        return Ok(());
+    }
+
+    // A position that isn't in the tablebase is only searched if --depth says how far. Without
+    // --depth, the search decides how far ahead it can look by itself.
+    let looked_up = match tablebase {
+        Some(path) => {
+            let tablebase = Tablebase::load(path).map_err(|error| format!("could not load {}: {}", path, error))?;
+            match (tablebase.analyze(&game), depth) {
+                (None, None) => return Err(format!("the position isn't in {}", path)),
+                (moves, _) => moves,
+            }
+        },
+        None => None,
+    };
+    let moves = match (looked_up, depth) {
+        (Some(moves), _) => moves,
+        (None, Some(depth)) => ai::analyze_with_depth(&game, depth),
+        (None, None) => ai::analyze(&game),
+    };
+    for ((row, col), evaluation) in moves {
+        println!("{} {}", format_move(row, col), evaluation);
+    }
+    Ok(())
+}
+
+// Times the search on an empty board of each of the built in variants
+fn bench(depth: usize) -> Result<(), String> {
+    for (name, game) in &[("tic-tac-toe", Game::new()), ("connect-four", Game::connect_four())] {
+        let start = Instant::now();
+        let best = ai::best_move_with_depth(game, depth).map_or("none".to_string(), |(row, col)| format_move(row, col));
+        println!("{:<14} depth {:<3} best move {:<4} {:>10.3?}", name, depth, best, start.elapsed());
+    }
+    Ok(())
+}
+
+// Solves a Connect Four position and prints its score and the score of every column
+fn solve(moves: &str, weak: bool) -> Result<(), String> {
+    let position = Position::from_moves(moves).map_err(|error| format!("invalid moves '{}': {}", moves, error))?;
+    let mut solver = Solver::new();
+    let start = Instant::now();
+
+    if weak {
+        let result = match solver.solve_weak(&position) {
+            1 => "win",
+            -1 => "loss",
+            _ => "draw",
+        };
+        println!("{} for {}", result, position.current_piece().name());
+    }
+    else {
+        let score = solver.solve(&position);
+        println!("score: {} ({} {})", score, position.current_piece().name(), solver::evaluation(&position, score));
+        // A column's score is the score of the position after playing there
+        let scores: Vec<String> = solver.column_scores(&position).iter()
+            .map(|score| score.map_or("-".to_string(), |score| score.to_string()))
+            .collect();
+        println!("columns: {}", scores.join(" "));
+    }
+    println!("positions searched: {} in {:.3?}", solver.nodes(), start.elapsed());
+    Ok(())
+}
+
+// Counts every position and game on the board picked by the options
+fn stats(options: &PlayOptions, symmetric: bool) -> Result<(), String> {
+    let game = new_game(options)?;
+    let start = Instant::now();
+    let stats = stats::enumerate(&game, symmetric);
+
+    println!("moves  positions");
+    for (depth, positions) in stats.positions_by_depth.iter().enumerate() {
+        println!("{:>5}  {:>9}", depth, positions);
+    }
+    let tally = |tally: &Tally| format!("x wins {}, o wins {}, ties {}", tally.x_wins, tally.o_wins, tally.ties);
+    println!("positions: {}", stats.positions());
+    println!("finished positions: {} ({})", stats.finished_positions.total(), tally(&stats.finished_positions));
+    println!("games: {} ({})", stats.games.total(), tally(&stats.games));
+    println!("counted in {:.3?}", start.elapsed());
+    Ok(())
+}
+
+// Works out every position on the board picked by the options and saves the tablebase to a file
+fn tablebase(path: &str, options: &PlayOptions) -> Result<(), String> {
+    let game = new_game(options)?;
+    let start = Instant::now();
+    let tablebase = Tablebase::generate(&game).map_err(|error| error.to_string())?;
+    tablebase.save(path).map_err(|error| format!("could not save {}: {}", path, error))?;
+    println!("{} positions of {} saved to {} in {:.3?}", tablebase.len(), tablebase.rules(), path, start.elapsed());
+    Ok(())
+}
+
+// Builds an opening book for the board picked by the options and saves it to a file
+fn book(path: &str, options: &PlayOptions, plies: usize, self_play: Option<usize>) -> Result<(), String> {
+    let game = new_game(options)?;
+    let start = Instant::now();
+    let book = match self_play {
+        // Every game gets different seeds so that the players try different openings
+        Some(games) => {
+            let mut book = OpeningBook::new(&game);
+            for seed in 0..games as u64 {
+                let mut players = [
+                    MctsPlayer::new(Budget::Iterations(2000), seed * 2).with_playout(Playout::Heuristic),
+                    MctsPlayer::new(Budget::Iterations(2000), seed * 2 + 1).with_playout(Playout::Heuristic),
+                ];
+                let mut played = game.clone();
+                while !played.is_finished() {
+                    let player = &mut players[played.history().len() % 2];
+                    let (row, col) = player.choose_move(&played).expect("unfinished game should have a move");
+                    played.make_move(row, col).map_err(|error| error.to_string())?;
+                }
+                book.add_game(&played, plies);
+            }
+            book
+        },
+        None => OpeningBook::from_analysis(&game, plies, ai::analyze),
+    };
+    book.save(path).map_err(|error| format!("could not save {}: {}", path, error))?;
+    println!("{} positions of {} saved to {} in {:.3?}", book.len(), book.rules(), path, start.elapsed());
+    Ok(())
+}
+
+// Plays a tournament between the players and prints the results
+fn tournament(entrants: &[(String, PlayOptions)], board: &PlayOptions, settings: &Settings) -> Result<(), String> {
+    let game = new_game(board)?;
+    // Each player's tablebase and book are loaded once and shared by all of its games. Creating
+    // every player once first catches missing files and engines that won't start before any games
+    // are played. An engine that stops starting later on (e.g. because it was replaced) forfeits
+    // the games it can't play.
+    let mut shared = Vec::new();
+    for (name, options) in entrants {
+        let files = Shared::load(options).map_err(|error| format!("{}: {}", name, error))?;
+        make_player(&options.x, 0, options, &files).map_err(|error| format!("{}: {}", name, error))?;
+        shared.push(files);
+    }
+
+    let names: Vec<String> = entrants.iter().map(|(name, _)| name.clone()).collect();
+    let start = Instant::now();
+    let results = tournament::run(&game, &names, settings, |index, seed| {
+        let options = &entrants[index].1;
+        make_player(&options.x, seed, options, &shared[index])
+    });
+    print!("{}", results);
+    println!("\n{} games of {} in {:.3?} with seed {}", results.games(), game.rules_string(), start.elapsed(), settings.seed);
+    Ok(())
+}
@@ -0,0 +1,310 @@
+//
+// file: mcts.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module contains a second computer player that uses Monte Carlo Tree Search (MCTS). Instead
+// of looking at every possible continuation like the ai module, it plays lots of quick games from
+// the current position and builds a tree of the moves that keep turning out well. That makes it
+// useful on big boards where a full search is hopeless. Each iteration of the search has four
+// steps:
+//
+// 1. Selection: walk down the tree, picking the child with the best UCT score at each step. UCT
+//    balances moves that have won a lot (exploitation) against moves that haven't been tried
+//    much yet (exploration).
+// 2. Expansion: add one move that hasn't been tried yet to the tree.
+// 3. Simulation: play the rest of the game (a "playout") from there.
+// 4. Backpropagation: record the result in every node on the way back up to the root.
+//
+// For more information, see: https://en.wikipedia.org/wiki/Monte_Carlo_tree_search
+
+use std::time::{Duration, Instant};
+
+use crate::rng::Rng;
+use crate::{Game, Piece, Winner};
+
+// How much the search prefers moves that haven't been tried much. This is the usual value of √2.
+const DEFAULT_EXPLORATION: f64 = std::f64::consts::SQRT_2;
+
+// How long the search is allowed to take
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    /// Run this many iterations. Searches with the same seed always choose the same moves.
+    Iterations(u64),
+
+    /// Keep searching until this much time has passed. The number of iterations depends on the
+    /// speed of the computer, so the moves chosen can be different from run to run.
+    Time(Duration),
+}
+
+// How the rest of the game is played during a simulation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Playout {
+    /// Every move is picked at random
+    Random,
+
+    /// Moves that win straight away are always played, and so are moves that stop the opponent
+    /// from winning on their next move. Everything else is random. This takes longer per playout
+    /// but gives much more realistic results.
+    Heuristic,
+}
+
+// One node of the search tree. The nodes are stored in a Vec and refer to each other by index,
+// which is simpler in Rust than having the nodes own each other.
+#[derive(Debug, Clone)]
+struct Node {
+    // The move that led to this node and the piece that made it. The root has neither.
+    mv: Option<(usize, usize)>,
+    piece: Option<Piece>,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    // Legal moves from this node that don't have a child yet
+    untried: Vec<(usize, usize)>,
+    visits: u32,
+    // The total reward for the piece that made `mv`: 1 for each win and 0.5 for each tie
+    reward: f64,
+}
+
+impl Node {
+    fn new(mv: Option<(usize, usize)>, piece: Option<Piece>, parent: Option<usize>, game: &Game) -> Self {
+        // A finished game has no moves left to try
+        let untried = if game.is_finished() { Vec::new() } else { game.legal_moves() };
+        Self {mv, piece, parent, children: Vec::new(), untried, visits: 0, reward: 0.0}
+    }
+}
+
+// The tree is kept between searches, so that the work done thinking about the last move isn't thrown
+// away. `game` is the position at the root of the tree.
+#[derive(Debug, Clone)]
+struct Tree {
+    game: Game,
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Mcts {
+    budget: Budget,
+    playout: Playout,
+    exploration: f64,
+    rng: Rng,
+    tree: Option<Tree>,
+    // The number of iterations run by the last search
+    iterations: u64,
+}
+
+impl Mcts {
+    // Creates a search with random playouts. The same seed and budget always produce the same
+    // moves when the budget is a number of iterations.
+    pub fn new(budget: Budget, seed: u64) -> Self {
+        Self {
+            budget,
+            playout: Playout::Random,
+            exploration: DEFAULT_EXPLORATION,
+            rng: Rng::new(seed),
+            tree: None,
+            iterations: 0,
+        }
+    }
+
+    // Changes how the simulations are played
+    pub fn with_playout(mut self, playout: Playout) -> Self {
+        self.playout = playout;
+        self
+    }
+
+    // Changes how much the search prefers moves that haven't been tried much
+    pub fn with_exploration(mut self, exploration: f64) -> Self {
+        self.exploration = exploration;
+        self
+    }
+
+    // The number of iterations run by the last search
+    pub fn iterations(&self) -> u64 {
+        self.iterations
+    }
+
+    // Searches for the best move for the current piece, or returns None if the game is over
+    pub fn best_move(&mut self, game: &Game) -> Option<(usize, usize)> {
+        if game.is_finished() {
+            return None;
+        }
+
+        let mut tree = self.reuse_tree(game).unwrap_or_else(|| Tree {
+            game: game.clone(),
+            nodes: vec![Node::new(None, None, None, game)],
+        });
+
+        let start = Instant::now();
+        self.iterations = 0;
+        loop {
+            let done = match self.budget {
+                Budget::Iterations(iterations) => self.iterations >= iterations,
+                Budget::Time(time) => start.elapsed() >= time,
+            };
+            // There is always at least one iteration so that the root has a child to choose
+            if done && self.iterations > 0 {
+                break;
+            }
+            self.iterate(&mut tree);
+            self.iterations += 1;
+        }
+
+        // The move that was visited the most is the one the search trusts the most. Picking the
+        // highest average instead can choose a move that got lucky in a few playouts.
+        let root = &tree.nodes[0];
+        let best = root.children.iter()
+            .max_by_key(|&&child| tree.nodes[child].visits)
+            .and_then(|&child| tree.nodes[child].mv);
+        self.tree = Some(tree);
+        best
+    }
+
+    // Runs one iteration of the search: selection, expansion, simulation and backpropagation
+    fn iterate(&mut self, tree: &mut Tree) {
+        let mut game = tree.game.clone();
+        let mut node = 0;
+
+        // Selection
+        while tree.nodes[node].untried.is_empty() && !tree.nodes[node].children.is_empty() {
+            node = self.select_child(tree, node);
+            let (row, col) = tree.nodes[node].mv.expect("only the root has no move");
+            game.make_move(row, col).expect("moves in the tree should always be valid");
+        }
+
+        // Expansion
+        if !tree.nodes[node].untried.is_empty() {
+            let untried = &mut tree.nodes[node].untried;
+            // `swap_remove` is faster than `remove` and the order doesn't matter here
+            let (row, col) = untried.swap_remove(self.rng.below(untried.len()));
+            let piece = game.current_piece();
+            game.make_move(row, col).expect("untried moves should always be valid");
+            tree.nodes.push(Node::new(Some((row, col)), Some(piece), Some(node), &game));
+            let child = tree.nodes.len() - 1;
+            tree.nodes[node].children.push(child);
+            node = child;
+        }
+
+        // Simulation
+        let winner = self.simulate(&mut game);
+
+        // Backpropagation
+        let mut current = Some(node);
+        while let Some(index) = current {
+            let node = &mut tree.nodes[index];
+            node.visits += 1;
+            node.reward += match (winner, node.piece) {
+                (Winner::Tie, _) => 0.5,
+                (Winner::X, Some(Piece::X)) | (Winner::O, Some(Piece::O)) => 1.0,
+                _ => 0.0,
+            };
+            current = node.parent;
+        }
+    }
+
+    // Returns the child of `node` with the highest UCT score
+    fn select_child(&self, tree: &Tree, node: usize) -> usize {
+        let log_visits = (tree.nodes[node].visits as f64).ln();
+        let uct = |child: usize| {
+            let child = &tree.nodes[child];
+            let visits = child.visits as f64;
+            child.reward / visits + self.exploration * (log_visits / visits).sqrt()
+        };
+
+        // f64 can't be compared with `max_by_key` because NaN isn't equal to anything, so we
+        // compare the scores ourselves. The first of several equal children is kept.
+        let mut best = tree.nodes[node].children[0];
+        let mut best_score = uct(best);
+        for &child in &tree.nodes[node].children[1..] {
+            let score = uct(child);
+            if score > best_score {
+                best = child;
+                best_score = score;
+            }
+        }
+        best
+    }
+
+    // Plays the game to the end and returns the winner
+    fn simulate(&mut self, game: &mut Game) -> Winner {
+        while !game.is_finished() {
+            let moves = game.legal_moves();
+            let (row, col) = match self.playout {
+                Playout::Random => moves[self.rng.below(moves.len())],
+                Playout::Heuristic => match forced_move(game, &moves) {
+                    Some(mv) => mv,
+                    None => moves[self.rng.below(moves.len())],
+                },
+            };
+            game.make_move(row, col).expect("legal moves should always be valid");
+        }
+        game.winner().expect("finished game should have winner")
+    }
+
+    // Looks for the current position in the tree from the last search. That works when the game
+    // has carried on from the old root, e.g. after our move and the opponent's reply. The part of
+    // the tree below the new root is kept and everything else is thrown away.
+    fn reuse_tree(&mut self, game: &Game) -> Option<Tree> {
+        let tree = self.tree.take()?;
+        let (old, new) = (tree.game.history(), game.history());
+        if new.len() < old.len() || new[..old.len()] != *old {
+            return None;
+        }
+
+        // The moves in the history only tell us the same game carried on if both games started
+        // from the same position, so we also compare the boards at the old root
+        let played = &new[old.len()..];
+        let mut at_old_root = game.clone();
+        for _ in played {
+            at_old_root.undo();
+        }
+        if at_old_root.to_position_string() != tree.game.to_position_string() {
+            return None;
+        }
+
+        // Follow the moves played since the last search down the tree
+        let mut root = 0;
+        for &mv in played {
+            root = *tree.nodes[root].children.iter().find(|&&child| tree.nodes[child].mv == Some(mv))?;
+        }
+
+        // Copy the subtree into a new Vec, with the new root at index 0
+        let mut nodes = Vec::new();
+        let mut stack = vec![(root, None)];
+        while let Some((old_index, parent)) = stack.pop() {
+            let mut node = tree.nodes[old_index].clone();
+            node.parent = parent;
+            let children = std::mem::take(&mut node.children);
+            let new_index = nodes.len();
+            nodes.push(node);
+            if let Some(parent) = parent {
+                let parent: &mut Node = &mut nodes[parent];
+                parent.children.push(new_index);
+            }
+            // Pushing the children in reverse keeps them in the same order after popping
+            for &child in children.iter().rev() {
+                stack.push((child, Some(new_index)));
+            }
+        }
+        nodes[0].mv = None;
+        nodes[0].piece = None;
+        Some(Tree {game: game.clone(), nodes})
+    }
+}
+
+// Returns a move that wins straight away, or failing that a move that stops the opponent from
+// winning straight away
+fn forced_move(game: &Game, moves: &[(usize, usize)]) -> Option<(usize, usize)> {
+    let me = game.current_piece();
+    let mut block = None;
+    for &(row, col) in moves {
+        if game.makes_line(row, col, me) {
+            return Some((row, col));
+        }
+        // If the opponent could complete a line on this tile, we have to get there first
+        if block.is_none() && game.makes_line(row, col, me.other()) {
+            block = Some((row, col));
+        }
+    }
+    block
+}
@@ -0,0 +1,439 @@
+//
+// file: player.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module defines the Player trait along with the players that come with this library. Any
+// type that implements Player can take part in a game run by `program::play`, so you can plug in
+// your own strategies without changing the game loop.
+
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use crate::book::OpeningBook;
+use crate::console::Console;
+use crate::mcts::{Budget, Mcts, Playout};
+use crate::rng::Rng;
+use crate::tablebase::Tablebase;
+use crate::ai::{self, Evaluation};
+use crate::{format_move, Game, MoveError};
+
+// These are the things a player can do on their turn. Enum variants can hold values, so a move
+// carries the row and column of the tile along with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Place the current piece at (row, col)
+    Move(usize, usize),
+
+    /// Take back the last move
+    Undo,
+
+    /// Play the last undone move again
+    Redo,
+
+    /// Save a record of the game so far to the file at this path
+    Save(String),
+
+    /// Show how good each of the possible moves is
+    Hint,
+
+    /// A move that was turned down before it could be made, e.g. because it couldn't be read. It
+    /// counts the same as a move that `Game::make_move` turned down.
+    Invalid(MoveError),
+}
+
+// A trait describes behaviour that many different types can share. Every player has to be able to
+// choose a move, and the other methods have default implementations that most players can use as
+// they are.
+// For more information, see: https://doc.rust-lang.org/book/ch10-02-traits.html
+pub trait Player {
+    // Returns the (row, col) of the move this player wants to make, or None if the player can't
+    // continue (for example, because their input was closed). The game is never finished when this
+    // is called. If the move isn't valid, the game ends and this player forfeits.
+    fn choose_move(&mut self, game: &Game) -> Option<(usize, usize)>;
+
+    // Returns what this player wants to do on their turn, or None if the player can't continue
+    // (for example, because their input was closed). Only interactive players need to override
+    // this so that they can use the console to ask for undo and redo as well.
+    fn choose_action(&mut self, game: &Game, _console: &mut Console) -> io::Result<Option<Action>> {
+        Ok(self.choose_move(game).map(|(row, col)| Action::Move(row, col)))
+    }
+
+    // Returns true if a person is making the choices for this player. People are asked again when
+    // they make an invalid move and are never skipped over when moves are undone.
+    fn is_interactive(&self) -> bool {
+        false
+    }
+
+    // A short name used when telling the user what this player did
+    fn name(&self) -> &str {
+        "Computer"
+    }
+
+    // Scores every legal move like `ai::analyze`, for players that can do that without searching
+    // (for example, by looking the moves up in a tablebase). The hint command uses this when it
+    // can and searches otherwise.
+    fn analyze(&self, _game: &Game) -> Option<Vec<((usize, usize), Evaluation)>> {
+        None
+    }
+}
+
+// A person entering moves at the keyboard. During a game, the moves are read from the game's
+// console.
+#[derive(Debug, Clone, Default)]
+pub struct HumanPlayer;
+
+impl Player for HumanPlayer {
+    // This is only used outside of a game, so it prompts on stdin and stdout directly. Input that
+    // can't be read is no more use than input that was closed, so both mean that there is no move.
+    fn choose_move(&mut self, game: &Game) -> Option<(usize, usize)> {
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        let (mut input, mut output) = (stdin.lock(), stdout.lock());
+        let mut console = Console::new(&mut input, &mut output);
+
+        // Keep asking until we get an actual move. The `Some(_)` arm ignores the other actions,
+        // since there is no game here to undo or save.
+        loop {
+            match self.choose_action(game, &mut console) {
+                Ok(Some(Action::Move(row, col))) => break Some((row, col)),
+                Ok(Some(_)) => {},
+                Ok(None) | Err(_) => break None,
+            }
+        }
+    }
+
+    fn choose_action(&mut self, game: &Game, console: &mut Console) -> io::Result<Option<Action>> {
+        console.prompt_action(game)
+    }
+
+    fn is_interactive(&self) -> bool {
+        true
+    }
+
+    fn name(&self) -> &str {
+        "Human"
+    }
+}
+
+// A player that picks any legal move at random. It is a good first opponent for a new bot.
+#[derive(Debug, Clone)]
+pub struct RandomPlayer {
+    rng: Rng,
+}
+
+impl RandomPlayer {
+    // Creates a random player that makes different choices every time the program runs
+    pub fn new() -> Self {
+        Self {rng: Rng::from_time()}
+    }
+
+    // Creates a random player that always makes the same choices given the same seed
+    pub fn with_seed(seed: u64) -> Self {
+        Self {rng: Rng::new(seed)}
+    }
+}
+
+impl Default for RandomPlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Player for RandomPlayer {
+    fn choose_move(&mut self, game: &Game) -> Option<(usize, usize)> {
+        let moves = game.legal_moves();
+        Some(moves[self.rng.below(moves.len())])
+    }
+
+    fn name(&self) -> &str {
+        "Random"
+    }
+}
+
+// The names that can be given to `Difficulty::named`, e.g. from the --level command-line option
+pub const DIFFICULTY_NAMES: [&str; 4] = ["easy", "medium", "hard", "perfect"];
+
+// How well a ComputerPlayer plays. The easier levels don't look as far ahead, and every so often
+// they play a random move or deliberately pick a move that isn't the best one, so that people who
+// are just learning the game can beat them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difficulty {
+    /// Looks one move ahead and makes plenty of mistakes
+    Easy,
+
+    /// Looks two moves ahead, so it sees most threats, but still slips up now and then
+    Medium,
+
+    /// Looks four moves ahead and rarely makes a mistake
+    Hard,
+
+    /// Never makes a mistake on purpose. It searches to the end of the game on small boards and as
+    /// far as it can in a reasonable time on larger ones (see `ComputerPlayer::new`).
+    Perfect,
+}
+
+impl Difficulty {
+    // Returns the difficulty with the given name (see DIFFICULTY_NAMES), or None if there isn't one
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "easy" => Some(Difficulty::Easy),
+            "medium" => Some(Difficulty::Medium),
+            "hard" => Some(Difficulty::Hard),
+            "perfect" => Some(Difficulty::Perfect),
+            _ => None,
+        }
+    }
+
+    // How many moves ahead the player looks, or None to search all the way to the end of the game
+    pub fn depth(self) -> Option<usize> {
+        match self {
+            Difficulty::Easy => Some(1),
+            Difficulty::Medium => Some(2),
+            Difficulty::Hard => Some(4),
+            Difficulty::Perfect => None,
+        }
+    }
+
+    // The chance (in percent) of playing any legal move at random instead of searching
+    pub fn random_chance(self) -> usize {
+        match self {
+            Difficulty::Easy => 30,
+            Difficulty::Medium => 10,
+            Difficulty::Hard | Difficulty::Perfect => 0,
+        }
+    }
+
+    // The chance (in percent) of playing one of the moves that the search thinks is worse than the
+    // best one. Unlike a random move, this is always a mistake when there is one to make.
+    pub fn blunder_chance(self) -> usize {
+        match self {
+            Difficulty::Easy => 30,
+            Difficulty::Medium => 15,
+            Difficulty::Hard => 5,
+            Difficulty::Perfect => 0,
+        }
+    }
+}
+
+// A player that uses the minimax search from the ai module
+#[derive(Debug, Clone)]
+pub struct ComputerPlayer {
+    // How many moves ahead to look, or None to look as far ahead as `ai::analyze` does. That is all
+    // the way to the end of the game on small boards.
+    depth: Option<usize>,
+    // The chances of a random move and of a blunder, both in percent
+    random_chance: usize,
+    blunder_chance: usize,
+    rng: Rng,
+}
+
+impl ComputerPlayer {
+    // Creates a computer player that plays perfectly on small boards like the 3x3 board. On larger
+    // boards a perfect search would take far too long, so it looks as far ahead as it can in about
+    // the time it takes to solve the 3x3 board.
+    pub fn new() -> Self {
+        Self::with_difficulty(Difficulty::Perfect, 0)
+    }
+
+    // Creates a computer player that only looks `depth` moves ahead
+    pub fn with_depth(depth: usize) -> Self {
+        Self {depth: Some(depth), ..Self::new()}
+    }
+
+    // Creates a computer player that plays at the given difficulty. The seed decides when it makes
+    // its mistakes, so the same seed always plays the same moves.
+    pub fn with_difficulty(difficulty: Difficulty, seed: u64) -> Self {
+        Self {
+            depth: difficulty.depth(),
+            random_chance: difficulty.random_chance(),
+            blunder_chance: difficulty.blunder_chance(),
+            rng: Rng::new(seed),
+        }
+    }
+
+    // Picks one of the moves that scores worse than the best move, or None if every move is
+    // just as good
+    fn blunder(&mut self, game: &Game) -> Option<(usize, usize)> {
+        let depth = self.depth.unwrap_or_else(|| ai::analyze_depth(game));
+        let moves = ai::analyze_with_depth(game, depth);
+        let best = moves.first()?.1;
+        let worse: Vec<_> = moves.iter().filter(|(_, evaluation)| *evaluation != best).collect();
+        if worse.is_empty() {
+            return None;
+        }
+        Some(worse[self.rng.below(worse.len())].0)
+    }
+}
+
+impl Default for ComputerPlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Player for ComputerPlayer {
+    fn choose_move(&mut self, game: &Game) -> Option<(usize, usize)> {
+        // The dice are only rolled when there is a chance of a mistake, so that perfect players
+        // don't need a seed
+        if self.random_chance > 0 && self.rng.below(100) < self.random_chance {
+            let moves = game.legal_moves();
+            return Some(moves[self.rng.below(moves.len())]);
+        }
+        if self.blunder_chance > 0 && self.rng.below(100) < self.blunder_chance {
+            if let Some(blunder) = self.blunder(game) {
+                return Some(blunder);
+            }
+        }
+
+        let depth = self.depth.unwrap_or_else(|| ai::analyze_depth(game));
+        ai::best_move_with_depth(game, depth)
+    }
+}
+
+// A player that uses Monte Carlo Tree Search from the mcts module. It works on boards of any size,
+// and it keeps its search tree from one move to the next.
+#[derive(Debug, Clone)]
+pub struct MctsPlayer {
+    mcts: Mcts,
+}
+
+impl MctsPlayer {
+    // Creates a player that searches for `budget` on every move using random playouts. With a
+    // number of iterations as the budget, the same seed always plays the same moves.
+    pub fn new(budget: Budget, seed: u64) -> Self {
+        Self {mcts: Mcts::new(budget, seed)}
+    }
+
+    // Changes how the simulations are played
+    pub fn with_playout(self, playout: Playout) -> Self {
+        Self {mcts: self.mcts.with_playout(playout)}
+    }
+}
+
+impl Player for MctsPlayer {
+    fn choose_move(&mut self, game: &Game) -> Option<(usize, usize)> {
+        self.mcts.best_move(game)
+    }
+
+    fn name(&self) -> &str {
+        "MCTS"
+    }
+}
+
+// A player that looks its moves up in a tablebase (see the tablebase module), so it plays perfectly
+// without searching. The tablebase is behind an Arc so that players can share one table, even on
+// different threads like in a tournament. If a position isn't in the table, e.g. because the table
+// was made for other rules, it asks a ComputerPlayer instead.
+#[derive(Debug, Clone)]
+pub struct TablebasePlayer {
+    tablebase: Arc<Tablebase>,
+    fallback: ComputerPlayer,
+}
+
+impl TablebasePlayer {
+    pub fn new(tablebase: Arc<Tablebase>) -> Self {
+        Self {tablebase, fallback: ComputerPlayer::new()}
+    }
+
+    // Changes the computer player that moves when a position isn't in the table, e.g. to one that
+    // only looks a few moves ahead
+    pub fn with_fallback(self, fallback: ComputerPlayer) -> Self {
+        Self {fallback, ..self}
+    }
+}
+
+impl Player for TablebasePlayer {
+    fn choose_move(&mut self, game: &Game) -> Option<(usize, usize)> {
+        self.tablebase.best_move(game).or_else(|| self.fallback.choose_move(game))
+    }
+
+    fn name(&self) -> &str {
+        "Tablebase"
+    }
+
+    fn analyze(&self, game: &Game) -> Option<Vec<((usize, usize), Evaluation)>> {
+        self.tablebase.analyze(game)
+    }
+}
+
+// Wraps another player so that it plays from an opening book (see the book module) while the game
+// is still in the book, and only asks the other player once the book runs out. The book is behind
+// an Arc so that players can share one book, even on different threads, and the seed decides which
+// of the book moves is played.
+pub struct BookPlayer {
+    book: Arc<OpeningBook>,
+    player: Box<dyn Player>,
+    rng: Rng,
+}
+
+impl BookPlayer {
+    pub fn new(book: Arc<OpeningBook>, player: Box<dyn Player>, seed: u64) -> Self {
+        Self {book, player, rng: Rng::new(seed)}
+    }
+}
+
+// Everything but the book moves is left to the other player, so a person or an engine behind the
+// book can still undo, ask for hints, quit or stop answering just like without it
+impl Player for BookPlayer {
+    fn choose_move(&mut self, game: &Game) -> Option<(usize, usize)> {
+        match self.book.pick(game, self.rng.next_u64()) {
+            Some(mv) => Some(mv),
+            None => self.player.choose_move(game),
+        }
+    }
+
+    // The game loop only tells people about the moves of other players, so a person gets told
+    // about the moves the book makes for them here
+    fn choose_action(&mut self, game: &Game, console: &mut Console) -> io::Result<Option<Action>> {
+        match self.book.pick(game, self.rng.next_u64()) {
+            Some((row, col)) => {
+                if self.player.is_interactive() {
+                    writeln!(console, "The book plays: {}", format_move(row, col))?;
+                }
+                Ok(Some(Action::Move(row, col)))
+            },
+            None => self.player.choose_action(game, console),
+        }
+    }
+
+    fn is_interactive(&self) -> bool {
+        self.player.is_interactive()
+    }
+
+    fn name(&self) -> &str {
+        self.player.name()
+    }
+
+    fn analyze(&self, game: &Game) -> Option<Vec<((usize, usize), Evaluation)>> {
+        self.player.analyze(game)
+    }
+}
+
+// This is the hook for custom bots. Wrap any function or closure that takes the game and returns
+// a move, and it becomes a Player. For example:
+//
+//     let first_free = BotFn::new("First free", |game: &Game| game.legal_moves()[0]);
+pub struct BotFn<F> {
+    name: String,
+    choose: F,
+}
+
+impl<F: FnMut(&Game) -> (usize, usize)> BotFn<F> {
+    pub fn new(name: &str, choose: F) -> Self {
+        Self {name: name.to_string(), choose}
+    }
+}
+
+impl<F: FnMut(&Game) -> (usize, usize)> Player for BotFn<F> {
+    fn choose_move(&mut self, game: &Game) -> Option<(usize, usize)> {
+        // The parentheses are needed so that Rust calls the closure stored in the field instead
+        // of looking for a method named `choose`
+        Some((self.choose)(game))
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
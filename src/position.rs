@@ -0,0 +1,359 @@
+//
+// file: position.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module lets us describe a position with a short string instead of replaying every move. The
+// format is inspired by the FEN notation used for chess positions and has three fields separated
+// by spaces:
+//
+//     x2/1o1/3 x 3x3k3
+//
+// 1. The board, one row at a time from the top, with the rows separated by `/`. `x` and `o` are
+//    pieces and a number is that many empty tiles in a row.
+// 2. The piece whose turn it is, `x` or `o`.
+// 3. The rules: the number of rows and columns, `k` followed by the win length, and a final `g`
+//    if the pieces fall with gravity (e.g. `6x7k4g` for Connect Four). The board size can be left
+//    out since the board already shows it (e.g. `k4g`). The whole field can be left out for the
+//    standard 3x3 Tic-Tac-Toe and 6x7 Connect Four boards.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::{format_move, zobrist, ConfigError, Game, Piece, Tiles, Winner, MAX_COLS, MAX_ROWS};
+
+// This type represents the possible errors that can occur when reading a position string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PositionError {
+    /// The string must have two or three fields separated by spaces
+    WrongFieldCount { fields: usize },
+
+    /// The board contained something other than `x`, `o`, `/` or a number
+    InvalidBoard { found: String },
+
+    /// Every row of the board must have the same number of tiles
+    UnevenRows,
+
+    /// The piece to move must be `x` or `o`
+    InvalidSide { found: String },
+
+    /// The rules field could not be read
+    InvalidRules { found: String },
+
+    /// The rules field had no rules and the board isn't a standard size
+    MissingRules { rows: usize, cols: usize },
+
+    /// The board size in the rules field doesn't match the board
+    SizeMismatch { rows: usize, cols: usize },
+
+    /// The board size or win length can't be played
+    InvalidConfig(ConfigError),
+
+    /// X always goes first, so X must have the same number of pieces as O or one more
+    PieceCount { x: usize, o: usize },
+
+    /// The piece to move doesn't match the number of pieces on the board
+    WrongSideToMove,
+
+    /// With gravity, a piece can't have an empty tile below it
+    FloatingPiece { row: usize, col: usize },
+
+    /// Both players have a winning line, or the winning lines couldn't have been made by a single
+    /// last move
+    ImpossibleWin,
+}
+
+impl fmt::Display for PositionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PositionError::WrongFieldCount {fields} => write!(
+                f,
+                "A position is the board, the piece to move and maybe the rules, e.g. \"x2/1o1/3 o\", not {} fields",
+                fields,
+            ),
+            PositionError::InvalidBoard {found} => write!(f, "The board '{}' can only have x, o, / and numbers", found),
+            PositionError::UnevenRows => write!(f, "Every row of the board must have the same number of tiles"),
+            PositionError::InvalidSide {found} => write!(f, "The piece to move must be x or o, not '{}'", found),
+            PositionError::InvalidRules {found} => write!(f, "The rules '{}' can't be read, e.g. 6x7k4g", found),
+            PositionError::MissingRules {rows, cols} => {
+                write!(f, "A board with {} rows and {} columns needs rules, e.g. {}x{}k3", rows, cols, rows, cols)
+            },
+            PositionError::SizeMismatch {rows, cols} => {
+                write!(f, "The rules are for a board with {} rows and {} columns, which isn't this board", rows, cols)
+            },
+            PositionError::InvalidConfig(error) => write!(f, "{}", error),
+            PositionError::PieceCount {x, o} => {
+                write!(f, "x has {} pieces and o has {}, but x needs as many as o or one more", x, o)
+            },
+            PositionError::WrongSideToMove => write!(f, "The piece to move doesn't match the pieces on the board"),
+            PositionError::FloatingPiece {row, col} => {
+                write!(f, "The piece at {} has an empty tile below it", format_move(*row, *col))
+            },
+            PositionError::ImpossibleWin => write!(f, "The winning lines couldn't have happened in a real game"),
+        }
+    }
+}
+
+impl Error for PositionError {}
+
+impl Game {
+    // Describes this game as a position string. The move history isn't included, so a game read
+    // back from this string can't undo the moves that led to it.
+    pub fn to_position_string(&self) -> String {
+        // Each row is turned into a string and then they are all joined with `/`
+        let board: Vec<String> = self.tiles.iter().map(|row| {
+            let mut text = String::new();
+            let mut empty = 0;
+            for tile in row {
+                match tile {
+                    None => empty += 1,
+                    Some(piece) => {
+                        // Write out the empty tiles that came before this piece
+                        if empty > 0 {
+                            text += &empty.to_string();
+                            empty = 0;
+                        }
+                        text.push(piece_char(*piece));
+                    },
+                }
+            }
+            if empty > 0 {
+                text += &empty.to_string();
+            }
+            text
+        }).collect();
+
+        format!("{} {} {}", board.join("/"), piece_char(self.current_piece), self.rules_string())
+    }
+
+    // Describes the rules of this game the same way as the last field of a position string,
+    // e.g. `3x3k3` for Tic-Tac-Toe and `6x7k4g` for Connect Four
+    pub fn rules_string(&self) -> String {
+        format!(
+            "{}x{}k{}{}",
+            self.rows(),
+            self.cols(),
+            self.win_length,
+            if self.gravity { "g" } else { "" },
+        )
+    }
+
+    // Creates a new, empty game from a rules string written by `rules_string`. Unlike in a
+    // position string, the board size can't be left out because there is no board to get it from.
+    pub fn from_rules_string(rules: &str) -> Result<Game, PositionError> {
+        match parse_rules(rules)? {
+            Rules {size: Some((rows, cols)), win_length, gravity} => new_game(rows, cols, win_length, gravity),
+            Rules {size: None, ..} => Err(PositionError::InvalidRules {found: rules.to_string()}),
+        }
+    }
+
+    // Reads a position string written by `to_position_string` (or by hand). The position is checked
+    // to make sure that it could actually happen in a game and the winner is worked out from the
+    // board.
+    pub fn from_position_string(position: &str) -> Result<Game, PositionError> {
+        // `split_whitespace` also ignores extra spaces at the start and end of the string
+        let fields: Vec<&str> = position.split_whitespace().collect();
+        if fields.len() != 2 && fields.len() != 3 {
+            return Err(PositionError::WrongFieldCount {fields: fields.len()});
+        }
+
+        let tiles = parse_board(fields[0])?;
+        let (rows, cols) = (tiles.len(), tiles[0].len());
+
+        let current_piece = match fields[1] {
+            "x" | "X" => Piece::X,
+            "o" | "O" => Piece::O,
+            other => return Err(PositionError::InvalidSide {found: other.to_string()}),
+        };
+
+        // `get` returns None when there is no third field
+        let (win_length, gravity) = match fields.get(2) {
+            Some(rules) => match parse_rules(rules)? {
+                Rules {size: Some(size), ..} if size != (rows, cols) => {
+                    return Err(PositionError::SizeMismatch {rows: size.0, cols: size.1});
+                },
+                Rules {win_length, gravity, ..} => (win_length, gravity),
+            },
+            None => match (rows, cols) {
+                (3, 3) => (3, false),
+                (6, 7) => (4, true),
+                _ => return Err(PositionError::MissingRules {rows, cols}),
+            },
+        };
+
+        // new_game checks that the size and win length make sense, and then we fill in the board
+        // we read
+        let mut game = new_game(rows, cols, win_length, gravity)?;
+        game.tiles = tiles;
+        game.current_piece = current_piece;
+        game.hash = zobrist::hash(&game.tiles, game.current_piece);
+
+        game.check_position()?;
+        game.winner = game.find_winner()?;
+        Ok(game)
+    }
+
+    // Checks that the pieces on the board could have been played in a real game
+    pub(crate) fn check_position(&self) -> Result<(), PositionError> {
+        let count = |piece| self.tiles.iter().flatten().filter(|&&tile| tile == Some(piece)).count();
+        let (x, o) = (count(Piece::X), count(Piece::O));
+
+        // Since X goes first, whoever has fewer pieces is the one to move
+        let expected = if x == o {
+            Piece::X
+        }
+        else if x == o + 1 {
+            Piece::O
+        }
+        else {
+            return Err(PositionError::PieceCount {x, o});
+        };
+        if expected != self.current_piece {
+            return Err(PositionError::WrongSideToMove);
+        }
+
+        if self.gravity {
+            // Every piece except those on the bottom row needs a piece below it
+            for row in 0..self.rows() - 1 {
+                for col in 0..self.cols() {
+                    if self.tiles[row][col].is_some() && self.tiles[row + 1][col].is_none() {
+                        return Err(PositionError::FloatingPiece {row, col});
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Works out the winner from the board. The winner must be the piece that moved last, and all of
+    // their lines must share a tile that could have been the last move.
+    pub(crate) fn find_winner(&self) -> Result<Option<Winner>, PositionError> {
+        let lines = self.lines();
+        let last_piece = self.current_piece.other();
+
+        if lines.is_empty() {
+            let full = self.tiles.iter().flatten().all(|tile| tile.is_some());
+            return Ok(if full { Some(Winner::Tie) } else { None });
+        }
+        if lines.iter().any(|(piece, _)| *piece != last_piece) {
+            return Err(PositionError::ImpossibleWin);
+        }
+
+        // The last move must be part of every line, because the game would have ended as soon as
+        // the first line was made. With gravity, the last move must also be the top of its column.
+        let last_move_possible = lines[0].1.iter().any(|&(row, col)| {
+            lines.iter().all(|(_, line)| line.contains(&(row, col)))
+                && (!self.gravity || row == 0 || self.tiles[row - 1][col].is_none())
+        });
+        if !last_move_possible {
+            return Err(PositionError::ImpossibleWin);
+        }
+
+        Ok(Some(match last_piece {
+            Piece::X => Winner::X,
+            Piece::O => Winner::O,
+        }))
+    }
+}
+
+// The character used for each piece in a position string
+fn piece_char(piece: Piece) -> char {
+    match piece {
+        Piece::X => 'x',
+        Piece::O => 'o',
+    }
+}
+
+// Reads the board field into rows of tiles
+fn parse_board(board: &str) -> Result<Tiles, PositionError> {
+    let invalid = || PositionError::InvalidBoard {found: board.to_string()};
+
+    let mut tiles = Vec::new();
+    for text in board.split('/') {
+        let mut row = Vec::new();
+        // Numbers can have more than one digit, so we collect the digits until we see a piece
+        let mut empty = String::new();
+        // The `chain` adds a `/` to the end so that a number at the end of the row is finished too
+        for c in text.chars().chain(Some('/')) {
+            if c.is_ascii_digit() {
+                empty.push(c);
+                continue;
+            }
+            if !empty.is_empty() {
+                let count: usize = empty.parse().map_err(|_| invalid())?;
+                if count == 0 {
+                    return Err(invalid());
+                }
+                // A huge number would take all of the memory to make the row, so the size is
+                // checked before the tiles are added
+                if row.len().saturating_add(count) > MAX_COLS {
+                    let cols = row.len().saturating_add(count);
+                    return Err(PositionError::InvalidConfig(ConfigError::TooManyColumns {cols}));
+                }
+                row.resize(row.len() + count, None);
+                empty.clear();
+            }
+            match c {
+                'x' | 'X' => row.push(Some(Piece::X)),
+                'o' | 'O' => row.push(Some(Piece::O)),
+                '/' => {},
+                _ => return Err(invalid()),
+            }
+        }
+        if row.is_empty() {
+            return Err(invalid());
+        }
+        tiles.push(row);
+        if tiles.len() > MAX_ROWS {
+            return Err(PositionError::InvalidConfig(ConfigError::TooManyRows {rows: tiles.len()}));
+        }
+    }
+
+    if tiles.iter().any(|row| row.len() != tiles[0].len()) {
+        return Err(PositionError::UnevenRows);
+    }
+    Ok(tiles)
+}
+
+// Creates an empty game, checking that the size and win length make sense
+fn new_game(rows: usize, cols: usize, win_length: usize, gravity: bool) -> Result<Game, PositionError> {
+    let game = if gravity {
+        Game::with_gravity_config(rows, cols, win_length)
+    }
+    else {
+        Game::with_config(rows, cols, win_length)
+    };
+    game.map_err(PositionError::InvalidConfig)
+}
+
+// The settings read from a rules field. The board size is optional in a position string.
+struct Rules {
+    size: Option<(usize, usize)>,
+    win_length: usize,
+    gravity: bool,
+}
+
+// Reads the rules field
+fn parse_rules(rules: &str) -> Result<Rules, PositionError> {
+    let invalid = || PositionError::InvalidRules {found: rules.to_string()};
+
+    // A `g` at the end turns on gravity. `strip_suffix` returns None if it isn't there.
+    let (rules_without_g, gravity) = match rules.strip_suffix('g') {
+        Some(rest) => (rest, true),
+        None => (rules, false),
+    };
+
+    // Everything after the `k` is the win length and everything before it is the board size
+    let k = rules_without_g.find('k').ok_or_else(invalid)?;
+    let win_length = rules_without_g[k + 1..].parse().map_err(|_| invalid())?;
+
+    let size = &rules_without_g[..k];
+    if size.is_empty() {
+        return Ok(Rules {size: None, win_length, gravity});
+    }
+    let x = size.find('x').ok_or_else(invalid)?;
+    let rows = size[..x].parse().map_err(|_| invalid())?;
+    let cols = size[x + 1..].parse().map_err(|_| invalid())?;
+    Ok(Rules {size: Some((rows, cols)), win_length, gravity})
+}
@@ -0,0 +1,374 @@
+//
+// file: record.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module saves and loads whole games so that they can be replayed and annotated later. The
+// format is inspired by the PGN format used for chess games. It starts with some headers, one per
+// line, followed by a blank line and then the moves:
+//
+//     [Variant "3x3k3"]
+//     [X "Human"]
+//     [O "Computer"]
+//     [Date "2026.10.16"]
+//     [Result "o"]
+//
+//     2B 1A {the only move that doesn't lose} 3C 1C 1B 3A 2A 2C 3B
+//
+// The moves use the same coordinates that are typed in at the prompt. Anything written between
+// `{` and `}` is a comment about the move before it. The Variant header uses the same rules string
+// as the last field of a position string (see the position module). A game that didn't start on an
+// empty board also has a Position header with the board and piece to move it started from, e.g.
+// `[Position "x2/1o1/3 x"]`. The Result header is `x`, `o`, `tie` or `*` for a game that isn't
+// finished. A `"` in a header value or a `}` in a comment has a backslash in front of it (e.g.
+// `[X "The \"Best\" Bot"]`), and so does a backslash itself.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{format_move, parse_move, Game, MoveError, PositionError, Winner};
+
+// The headers that every record starts with, in the order they are written
+const VARIANT: &str = "Variant";
+const POSITION: &str = "Position";
+const X_PLAYER: &str = "X";
+const O_PLAYER: &str = "O";
+const DATE: &str = "Date";
+const RESULT: &str = "Result";
+
+// One move of a recorded game along with any comment that was written about it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedMove {
+    pub row: usize,
+    pub col: usize,
+    pub comment: Option<String>,
+}
+
+// A whole game: its headers and the moves that were played. The headers are kept in a Vec instead
+// of a map so that they are written back out in the same order they were read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameRecord {
+    pub headers: Vec<(String, String)>,
+    pub moves: Vec<RecordedMove>,
+}
+
+// This type represents the possible errors that can occur when loading a game record
+#[derive(Debug)]
+pub enum RecordError {
+    /// The file could not be read or written
+    Io(io::Error),
+
+    /// A line of the record could not be understood
+    Syntax { line: usize, message: String },
+
+    /// The Variant header doesn't describe a game that can be played
+    InvalidVariant(PositionError),
+
+    /// The Position header isn't a position that can happen in the variant
+    InvalidPosition(PositionError),
+
+    /// A move couldn't be read, e.g. because it isn't on the board. `number` starts at 1.
+    InvalidMove { number: usize, text: String },
+
+    /// A move couldn't be played. `number` starts at 1.
+    IllegalMove { number: usize, error: MoveError },
+
+    /// The Result header doesn't match the result of playing the moves
+    ResultMismatch { recorded: String, actual: String },
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordError::Io(error) => write!(f, "{}", error),
+            RecordError::Syntax {line, message} => write!(f, "Line {}: {}", line, message),
+            RecordError::InvalidVariant(error) => write!(f, "Invalid variant: {}", error),
+            RecordError::InvalidPosition(error) => write!(f, "Invalid position: {}", error),
+            RecordError::InvalidMove {number, text} => write!(f, "Move {} ('{}') isn't on the board", number, text),
+            RecordError::IllegalMove {number, error} => write!(f, "Move {} can't be played: {}", number, error),
+            RecordError::ResultMismatch {recorded, actual} => {
+                write!(f, "The result is recorded as {} but the moves end with {}", recorded, actual)
+            },
+        }
+    }
+}
+
+impl Error for RecordError {}
+
+// `From` lets the `?` operator turn an io::Error into a RecordError for us
+impl From<io::Error> for RecordError {
+    fn from(error: io::Error) -> Self {
+        RecordError::Io(error)
+    }
+}
+
+impl GameRecord {
+    // Creates a record of everything played so far in `game`. The players are unknown (`?`) until
+    // they are set with `set_header`, and the date is today's date.
+    pub fn new(game: &Game) -> Self {
+        // The history has every move since the start of the game, so replaying these moves on the
+        // board the game started from gives us the same game again
+        let moves = game.history().iter()
+            .map(|&(row, col)| RecordedMove {row, col, comment: None})
+            .collect();
+
+        let mut headers = vec![(VARIANT.to_string(), game.rules_string())];
+        // Undoing every move takes us back to where the game started. The rules are already in the
+        // Variant header, so only the board and the piece to move are kept.
+        let mut start = game.clone();
+        while start.undo().is_some() {}
+        if start.tiles().iter().flatten().any(|tile| tile.is_some()) {
+            let position = start.to_position_string();
+            let fields: Vec<&str> = position.split(' ').take(2).collect();
+            headers.push((POSITION.to_string(), fields.join(" ")));
+        }
+        headers.extend(vec![
+            (X_PLAYER.to_string(), "?".to_string()),
+            (O_PLAYER.to_string(), "?".to_string()),
+            (DATE.to_string(), today()),
+            (RESULT.to_string(), result_string(game.winner()).to_string()),
+        ]);
+        Self {headers, moves}
+    }
+
+    // Returns the value of a header, if the record has it
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+
+    // Sets the value of a header, adding it to the end if the record doesn't have it yet
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        match self.headers.iter_mut().find(|(key, _)| key == name) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.headers.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    // Replays the moves on a new board and returns the game. The game's history contains every
+    // move, so the moves can be stepped through with `undo` and `redo`.
+    pub fn to_game(&self) -> Result<Game, RecordError> {
+        let mut game = self.new_game()?;
+
+        // `enumerate` counts from 0, but people count moves from 1
+        for (i, recorded) in self.moves.iter().enumerate() {
+            game.make_move(recorded.row, recorded.col)
+                .map_err(|error| RecordError::IllegalMove {number: i + 1, error})?;
+        }
+
+        // A game that didn't finish can still have a result, e.g. when a bot forfeits. A game that
+        // did finish must have the result that actually happened.
+        if let (Some(recorded), Some(winner)) = (self.header(RESULT), game.winner()) {
+            let actual = result_string(Some(winner));
+            if recorded != actual {
+                return Err(RecordError::ResultMismatch {
+                    recorded: recorded.to_string(),
+                    actual: actual.to_string(),
+                });
+            }
+        }
+        Ok(game)
+    }
+
+    // Reads a record from the text format described at the top of this file. The moves are
+    // checked against the board size, but they aren't played. Use `to_game` for that.
+    pub fn parse(text: &str) -> Result<Self, RecordError> {
+        let mut headers = Vec::new();
+        let mut move_text = String::new();
+
+        // `lines` gives us each line without its newline. We keep track of the line numbers so
+        // that we can point at the problem when something goes wrong.
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            let syntax = |message: &str| RecordError::Syntax {line: i + 1, message: message.to_string()};
+
+            // Headers can only come before the moves. Their lines are left empty in the move
+            // text, so that the line numbers of the move text are the same as the line numbers of
+            // the file.
+            if line.starts_with('[') && move_text.trim().is_empty() {
+                headers.push(parse_header(line).map_err(syntax)?);
+                move_text.push('\n');
+            }
+            else {
+                move_text.push_str(line);
+                move_text.push('\n');
+            }
+        }
+
+        let mut record = Self {headers, moves: Vec::new()};
+        let game = record.new_game()?;
+        record.moves = parse_moves(&move_text, game.rows(), game.cols())?;
+        Ok(record)
+    }
+
+    // Creates the board that the game of this record started from. That is an empty board for the
+    // variant unless there is a Position header. Records without a Variant are assumed to be
+    // Tic-Tac-Toe.
+    fn new_game(&self) -> Result<Game, RecordError> {
+        let game = match self.header(VARIANT) {
+            Some(variant) => Game::from_rules_string(variant).map_err(RecordError::InvalidVariant)?,
+            None => Game::new(),
+        };
+        match self.header(POSITION) {
+            Some(position) => {
+                let text = format!("{} {}", position, game.rules_string());
+                Game::from_position_string(&text).map_err(RecordError::InvalidPosition)
+            },
+            None => Ok(game),
+        }
+    }
+}
+
+// Writing a record uses the Display trait, so `record.to_string()` and `write!` both work
+impl fmt::Display for GameRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, value) in &self.headers {
+            writeln!(f, "[{} \"{}\"]", name, escape(value, '"'))?;
+        }
+        writeln!(f)?;
+
+        // The moves are written ten to a line to keep the lines short
+        for (i, recorded) in self.moves.iter().enumerate() {
+            if i > 0 {
+                write!(f, "{}", if i % 10 == 0 { "\n" } else { " " })?;
+            }
+            write!(f, "{}", format_move(recorded.row, recorded.col))?;
+            if let Some(comment) = &recorded.comment {
+                write!(f, " {{{}}}", escape(comment, '}'))?;
+            }
+        }
+        writeln!(f)
+    }
+}
+
+// Writes a record to a file, replacing the file if it already exists
+pub fn save<P: AsRef<Path>>(record: &GameRecord, path: P) -> io::Result<()> {
+    fs::write(path, record.to_string())
+}
+
+// Reads a record from a file
+pub fn load<P: AsRef<Path>>(path: P) -> Result<GameRecord, RecordError> {
+    GameRecord::parse(&fs::read_to_string(path)?)
+}
+
+// Splits the move text into moves and the comments that follow them
+fn parse_moves(text: &str, rows: usize, cols: usize) -> Result<Vec<RecordedMove>, RecordError> {
+    let mut moves: Vec<RecordedMove> = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        if let Some(after_brace) = rest.strip_prefix('{') {
+            // Comments can contain spaces, so we look for the closing brace instead of splitting on
+            // whitespace. Lines are counted with `matches` since `lines` doesn't count a line that
+            // hasn't ended yet.
+            let (comment, length) = unescape(after_brace, '}').ok_or_else(|| RecordError::Syntax {
+                line: text[..text.len() - rest.len()].matches('\n').count() + 1,
+                message: "comment is missing a closing }".to_string(),
+            })?;
+            let comment = comment.trim().to_string();
+            match moves.last_mut() {
+                // A second comment on the same move is added to the first one
+                Some(RecordedMove {comment: Some(existing), ..}) => {
+                    existing.push(' ');
+                    existing.push_str(&comment);
+                },
+                Some(last) => last.comment = Some(comment),
+                // There is no move to attach a comment to before the first move, so it is skipped
+                None => {},
+            }
+            rest = after_brace[length..].trim_start();
+        }
+        else {
+            // A move ends at the next space or the start of a comment
+            let end = rest.find(|c: char| c.is_whitespace() || c == '{').unwrap_or(rest.len());
+            let text = &rest[..end];
+            let (row, col) = parse_move(text, rows, cols).map_err(|_| RecordError::InvalidMove {
+                number: moves.len() + 1,
+                text: text.to_string(),
+            })?;
+            moves.push(RecordedMove {row, col, comment: None});
+            rest = rest[end..].trim_start();
+        }
+    }
+    Ok(moves)
+}
+
+// Reads a header line such as `[X "Human"]` into its name and value
+fn parse_header(line: &str) -> Result<(String, String), &'static str> {
+    let inner = line.strip_prefix('[').ok_or("headers must start with [")?;
+    let (name, value) = inner.split_once(' ').ok_or("header has no value")?;
+    let quoted = value.trim_start().strip_prefix('"').ok_or("header values must be in double quotes")?;
+    let (value, length) = unescape(quoted, '"').ok_or("header values must be in double quotes")?;
+    if quoted[length..].trim() != "]" {
+        return Err("headers must end with ]");
+    }
+    Ok((name.to_string(), value))
+}
+
+// Puts a backslash in front of every `end` and backslash in the text, so that the text can be
+// written between quotes or braces without ending them early. Records are read a line at a time,
+// so line breaks are turned into spaces.
+fn escape(text: &str, end: char) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' | '\r' => escaped.push(' '),
+            c if c == end => {
+                escaped.push('\\');
+                escaped.push(c);
+            },
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Reads text written by `escape` up to the first `end` without a backslash in front of it. Returns
+// the text without the backslashes along with the number of bytes read, including the `end`, or
+// None if there is no `end`.
+fn unescape(text: &str, end: char) -> Option<(String, usize)> {
+    let mut unescaped = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => unescaped.push(chars.next().map_or('\\', |(_, next)| next)),
+            c if c == end => return Some((unescaped, i + c.len_utf8())),
+            c => unescaped.push(c),
+        }
+    }
+    None
+}
+
+// The value of the Result header for each possible winner
+fn result_string(winner: Option<Winner>) -> &'static str {
+    match winner {
+        Some(Winner::X) => "x",
+        Some(Winner::O) => "o",
+        Some(Winner::Tie) => "tie",
+        None => "*",
+    }
+}
+
+// Returns today's date (in UTC) in the form YYYY.MM.DD
+fn today() -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    let days = (seconds / 86_400) as i64;
+
+    // This turns the number of days since 1970-01-01 into a year, month and day. It is the
+    // "civil_from_days" algorithm from http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
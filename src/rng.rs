@@ -0,0 +1,47 @@
+//
+// file: rng.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module contains a small random number generator. The computer players only need "random
+// enough" numbers to pick between moves, so we use the SplitMix64 algorithm instead of pulling in
+// a whole crate for it. The same seed always produces the same numbers, which makes games with
+// random players repeatable.
+// For more information, see: https://prng.di.unimi.it/splitmix64.c
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone)]
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    // Creates a generator that always produces the same numbers for the same seed
+    pub(crate) fn new(seed: u64) -> Self {
+        Self {state: seed}
+    }
+
+    // Creates a generator seeded from the current time, so that every run is different
+    pub(crate) fn from_time() -> Self {
+        // If the clock is somehow set before 1970 we just fall back to a fixed seed
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_nanos());
+        Self::new(nanos as u64)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        // `wrapping_add` and `wrapping_mul` let the numbers overflow instead of panicking, which is
+        // exactly what this algorithm expects
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // Returns a number from 0 to (n-1). n must not be zero.
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
@@ -0,0 +1,185 @@
+//
+// file: serialization.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module is only compiled with the `serde` feature. It describes how a Game is turned into
+// data (e.g. JSON) and back again. In JSON, a Tic-Tac-Toe game after one move looks like this:
+//
+//     {
+//       "rows": 3, "cols": 3, "win_length": 3, "gravity": false,
+//       "tiles": [["x", null, null], [null, null, null], [null, null, null]],
+//       "current_piece": "o",
+//       "winner": null,
+//       "history": [[0, 0]],
+//       "undone": []
+//     }
+//
+// The fields of Game are private so that nobody can put it into a state that couldn't happen in a
+// real game. Data that comes from outside the program could say anything, so instead of deriving
+// Deserialize on Game directly, we read a GameData and then check it before turning it into a Game.
+
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{zobrist, ConfigError, Game, Piece, PositionError, Tiles, Winner};
+
+// Everything needed to recreate a game. The fields are in the order they appear in the JSON.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct GameData {
+    rows: usize,
+    cols: usize,
+    win_length: usize,
+    gravity: bool,
+    tiles: Tiles,
+    current_piece: Piece,
+    winner: Option<Winner>,
+    history: Vec<(usize, usize)>,
+    undone: Vec<(usize, usize)>,
+}
+
+// This type represents the reasons that deserialized data can't be turned into a game
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GameDataError {
+    /// The board size or win length can't be played
+    InvalidConfig(ConfigError),
+
+    /// The tiles don't have the number of rows and columns given
+    WrongSize { rows: usize, cols: usize },
+
+    /// The pieces on the board couldn't have been played in a real game
+    InvalidPosition(PositionError),
+
+    /// The winner doesn't match the pieces on the board
+    WinnerMismatch { stored: Option<Winner>, actual: Option<Winner> },
+
+    /// A move in the history doesn't lead to the board. `index` starts at 0.
+    InvalidHistory { index: usize },
+
+    /// A move that was undone can't be played again. `index` starts at 0.
+    InvalidUndone { index: usize },
+}
+
+// serde needs to be able to print the error, so that it can tell the user why the data was rejected
+impl fmt::Display for GameDataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameDataError::InvalidConfig(error) => write!(f, "invalid game settings: {}", error),
+            GameDataError::WrongSize {rows, cols} => {
+                write!(f, "the tiles don't make a board with {} rows and {} columns", rows, cols)
+            },
+            GameDataError::InvalidPosition(error) => write!(f, "impossible position: {}", error),
+            GameDataError::WinnerMismatch {stored, actual} => {
+                write!(f, "the winner is {} but the board says {}", winner_name(*stored), winner_name(*actual))
+            },
+            GameDataError::InvalidHistory {index} => write!(f, "move {} of the history is impossible", index),
+            GameDataError::InvalidUndone {index} => write!(f, "undone move {} can't be played again", index),
+        }
+    }
+}
+
+impl Error for GameDataError {}
+
+// How a winner is written in the messages above
+fn winner_name(winner: Option<Winner>) -> &'static str {
+    match winner {
+        Some(Winner::X) => "x",
+        Some(Winner::O) => "o",
+        Some(Winner::Tie) => "a tie",
+        None => "nobody",
+    }
+}
+
+impl From<Game> for GameData {
+    fn from(game: Game) -> Self {
+        Self {
+            rows: game.rows(),
+            cols: game.cols(),
+            win_length: game.win_length,
+            gravity: game.gravity,
+            tiles: game.tiles,
+            current_piece: game.current_piece,
+            winner: game.winner,
+            history: game.history,
+            undone: game.undone,
+        }
+    }
+}
+
+impl TryFrom<GameData> for Game {
+    type Error = GameDataError;
+
+    fn try_from(data: GameData) -> Result<Self, Self::Error> {
+        // The sizes are checked against the tiles that were actually read before a board is made
+        // for them, so that made-up sizes can't ask for more memory than the data itself took up
+        if data.tiles.len() != data.rows || data.tiles.iter().any(|row| row.len() != data.cols) {
+            return Err(GameDataError::WrongSize {rows: data.rows, cols: data.cols});
+        }
+        let game = if data.gravity {
+            Game::with_gravity_config(data.rows, data.cols, data.win_length)
+        }
+        else {
+            Game::with_config(data.rows, data.cols, data.win_length)
+        };
+        let mut game = game.map_err(GameDataError::InvalidConfig)?;
+        game.tiles = data.tiles;
+        game.current_piece = data.current_piece;
+        game.hash = zobrist::hash(&game.tiles, game.current_piece);
+
+        // These are the same checks that are used when reading a position string
+        game.check_position().map_err(GameDataError::InvalidPosition)?;
+        let actual = game.find_winner().map_err(GameDataError::InvalidPosition)?;
+        if actual != data.winner {
+            return Err(GameDataError::WinnerMismatch {stored: data.winner, actual});
+        }
+        game.winner = actual;
+
+        check_history(&game, &data.history)?;
+        game.history = data.history;
+
+        // Each undone move is played again on a copy of the game to make sure that redo will work.
+        // The last element is the first one that redo plays.
+        let mut redone = game.clone();
+        for (index, &(row, col)) in data.undone.iter().enumerate().rev() {
+            redone.make_move(row, col).map_err(|_| GameDataError::InvalidUndone {index})?;
+        }
+        game.undone = data.undone;
+
+        Ok(game)
+    }
+}
+
+// Checks that the history could have led to the board by taking the moves back one at a time,
+// starting from the last one. The history doesn't have to go back to the start of the game, since
+// a game read from a position string has no history.
+fn check_history(game: &Game, history: &[(usize, usize)]) -> Result<(), GameDataError> {
+    let mut tiles = game.tiles.clone();
+    let mut piece = game.current_piece;
+
+    for (index, &(row, col)) in history.iter().enumerate().rev() {
+        let invalid = GameDataError::InvalidHistory {index};
+        piece = piece.other();
+
+        // The tile must hold the piece that made this move
+        if row >= game.rows() || col >= game.cols() || tiles[row][col] != Some(piece) {
+            return Err(invalid);
+        }
+        // With gravity, the last move in a column must be the top piece of that column
+        if game.gravity && row > 0 && tiles[row - 1][col].is_some() {
+            return Err(invalid);
+        }
+        tiles[row][col] = None;
+
+        // The game would have ended if there was already a winning line before this move
+        let before = Game {tiles, ..game.clone()};
+        if !before.lines().is_empty() {
+            return Err(invalid);
+        }
+        tiles = before.tiles;
+    }
+    Ok(())
+}
@@ -0,0 +1,507 @@
+//
+// file: solver.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module solves the standard 6x7 Connect Four board: it finds out who wins a position (and
+// how quickly) if both players play perfectly. The general search in the ai module works for any
+// board, but it is far too slow to reach the end of a Connect Four game. This solver follows the
+// approach described by Pascal Pons in his tutorial at http://blog.gamesolver.org:
+//
+// 1. The board is stored as two 64 bit numbers (a "bitboard"), so that making a move or finding
+//    every winning tile takes a handful of bitwise operations instead of loops over a Vec.
+// 2. Negamax with alpha-beta pruning, as in the ai module.
+// 3. A transposition table remembers positions that were already searched. The same position can
+//    be reached by playing the same moves in a different order, so this saves a lot of work.
+// 4. Moves that create the most new threats are tried first, and moves that hand the opponent a
+//    win are never tried at all.
+// 5. Instead of searching for the exact score straight away, the score is narrowed down by asking
+//    "is the score above x?" with a tiny search window. Those searches are much faster, and the
+//    transposition table carries what was learned from one to the next.
+//
+// Scores are from the point of view of the player to move. A positive score means that they win.
+// The earlier a win happens, the higher the score: winning with your last possible stone scores 1,
+// and each stone you have left over when you win adds 1 more. A draw scores 0.
+
+use std::fmt;
+
+use crate::ai::Evaluation;
+use crate::{Game, Piece, CONNECT_FOUR_COLS, CONNECT_FOUR_ROWS, CONNECT_FOUR_WIN_LENGTH};
+
+// The size of the board
+pub const WIDTH: usize = CONNECT_FOUR_COLS;
+pub const HEIGHT: usize = CONNECT_FOUR_ROWS;
+
+// Each column takes up HEIGHT + 1 bits. The extra bit on top of each column is always empty, which
+// stops lines from wrapping around from the top of one column to the bottom of the next.
+const H1: usize = HEIGHT + 1;
+
+// The number of tiles on the board
+const TILES: i32 = (WIDTH * HEIGHT) as i32;
+
+// The lowest and highest possible scores
+pub const MIN_SCORE: i32 = -TILES / 2 + 3;
+pub const MAX_SCORE: i32 = (TILES + 1) / 2 - 3;
+
+// The number of entries in the transposition table. A prime number spreads the positions more
+// evenly over the table. Each entry takes 8 bytes, so this uses 64MB.
+const TABLE_SIZE: usize = 8_388_593;
+
+// Columns closer to the middle are part of more lines, so they are usually better moves
+const COLUMN_ORDER: [usize; WIDTH] = [3, 2, 4, 1, 5, 0, 6];
+
+// These are bitboards with one bit for each tile. Bit `col * H1 + row` is the tile `row` tiles up
+// from the bottom of column `col`.
+const fn bottom_mask() -> u64 {
+    let mut mask = 0;
+    let mut col = 0;
+    while col < WIDTH {
+        mask |= 1 << (col * H1);
+        col += 1;
+    }
+    mask
+}
+const BOTTOM_MASK: u64 = bottom_mask();
+// Multiplying the bottom row by a column of HEIGHT ones fills in every tile on the board
+const BOARD_MASK: u64 = BOTTOM_MASK * ((1 << HEIGHT) - 1);
+
+fn top_mask_col(col: usize) -> u64 {
+    1 << (HEIGHT - 1 + col * H1)
+}
+
+fn bottom_mask_col(col: usize) -> u64 {
+    1 << (col * H1)
+}
+
+fn column_mask(col: usize) -> u64 {
+    ((1 << HEIGHT) - 1) << (col * H1)
+}
+
+// This type represents the possible errors that can occur when setting up a position for the solver
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SolverError {
+    /// A move in a move string wasn't a column number from 1 to 7. `number` starts at 1.
+    InvalidColumn { number: usize, found: char },
+
+    /// A move was played in a column that was already full. `number` starts at 1.
+    ColumnFull { number: usize, col: usize },
+
+    /// A move won the game, so there is nothing left to solve. `number` starts at 1.
+    GameOver { number: usize },
+
+    /// The solver only knows about the standard 6x7 Connect Four board
+    NotConnectFour,
+
+    /// The game is already over, so there is nothing to solve
+    Finished,
+}
+
+impl fmt::Display for SolverError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SolverError::InvalidColumn {number, found} => {
+                write!(f, "Move {} ('{}') is not a column from 1 to {}", number, found, WIDTH)
+            },
+            SolverError::ColumnFull {number, col} => write!(f, "Move {} is in column {}, which is full", number, col + 1),
+            SolverError::GameOver {number} => write!(f, "Move {} wins the game", number),
+            SolverError::NotConnectFour => write!(f, "Only the standard 6x7 Connect Four board can be solved"),
+            SolverError::Finished => write!(f, "The game is already over"),
+        }
+    }
+}
+
+impl std::error::Error for SolverError {}
+
+// A Connect Four position stored as bitboards. `current` has a bit set for every piece of the
+// player to move and `mask` has a bit set for every piece on the board. The opponent's pieces are
+// `current ^ mask`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Position {
+    current: u64,
+    mask: u64,
+    moves: usize,
+}
+
+impl Position {
+    // The empty board
+    pub fn new() -> Self {
+        Self {current: 0, mask: 0, moves: 0}
+    }
+
+    // Plays a string of moves from the empty board. Each move is the number of a column from 1 to
+    // 7, so "4453" plays in the middle column twice, then column 5 and then column 3.
+    pub fn from_moves(moves: &str) -> Result<Self, SolverError> {
+        let mut position = Self::new();
+        for (i, c) in moves.chars().enumerate() {
+            let number = i + 1;
+            let col = match c.to_digit(10) {
+                Some(digit) if digit >= 1 && digit as usize <= WIDTH => digit as usize - 1,
+                _ => return Err(SolverError::InvalidColumn {number, found: c}),
+            };
+            if !position.can_play(col) {
+                return Err(SolverError::ColumnFull {number, col});
+            }
+            if position.is_winning_move(col) {
+                return Err(SolverError::GameOver {number});
+            }
+            position.play_col(col);
+        }
+        Ok(position)
+    }
+
+    // Converts a Connect Four game into a position. The game must be the standard 6x7 board with
+    // gravity and must not be finished.
+    pub fn from_game(game: &Game) -> Result<Self, SolverError> {
+        if game.rows() != HEIGHT || game.cols() != WIDTH || game.win_length() != CONNECT_FOUR_WIN_LENGTH
+            || !game.has_gravity() {
+            return Err(SolverError::NotConnectFour);
+        }
+        if game.is_finished() {
+            return Err(SolverError::Finished);
+        }
+
+        let mut position = Self::new();
+        for (row, tiles) in game.tiles().iter().enumerate() {
+            for (col, tile) in tiles.iter().enumerate() {
+                // Row 0 of a Game is the top of the board, but bit 0 of a column is the bottom
+                let bit = 1 << (col * H1 + HEIGHT - 1 - row);
+                if let Some(piece) = tile {
+                    position.mask |= bit;
+                    position.moves += 1;
+                    if *piece == game.current_piece() {
+                        position.current |= bit;
+                    }
+                }
+            }
+        }
+        Ok(position)
+    }
+
+    // The number of moves played so far
+    pub fn moves(&self) -> usize {
+        self.moves
+    }
+
+    // The piece whose turn it is. X always moves first.
+    pub fn current_piece(&self) -> Piece {
+        if self.moves.is_multiple_of(2) { Piece::X } else { Piece::O }
+    }
+
+    // Returns true if the column isn't full. Columns are numbered from 0.
+    pub fn can_play(&self, col: usize) -> bool {
+        self.mask & top_mask_col(col) == 0
+    }
+
+    // Plays in a column, which must not be full
+    pub fn play_col(&mut self, col: usize) {
+        // Adding the bottom bit of a column to the mask carries over all of the pieces in that
+        // column, leaving the lowest empty tile set. `& column_mask` keeps only that tile.
+        self.play((self.mask + bottom_mask_col(col)) & column_mask(col));
+    }
+
+    // Returns true if playing in the column wins the game straight away
+    pub fn is_winning_move(&self, col: usize) -> bool {
+        self.winning_position() & self.possible() & column_mask(col) != 0
+    }
+
+    // Returns true if the player to move can win with their next move
+    pub fn can_win_next(&self) -> bool {
+        self.winning_position() & self.possible() != 0
+    }
+
+    // A number that is different for every position. The mask plus the current player's pieces
+    // works because the mask shows the height of each column and adding a piece's bit to it
+    // changes it in a different way for each arrangement of the pieces.
+    pub fn key(&self) -> u64 {
+        self.current + self.mask
+    }
+
+    // Plays a move given as a bitboard with only the tile being played set
+    fn play(&mut self, tile: u64) {
+        // The pieces of the player to move become the opponent's pieces
+        self.current ^= self.mask;
+        self.mask |= tile;
+        self.moves += 1;
+    }
+
+    // Every tile that a piece can be played on next
+    fn possible(&self) -> u64 {
+        (self.mask + BOTTOM_MASK) & BOARD_MASK
+    }
+
+    // The playable tiles that don't hand the opponent a win. If the opponent has a winning tile
+    // that we can play on, we have to play there, and if they have two we have already lost.
+    fn possible_non_losing_moves(&self) -> u64 {
+        let mut possible = self.possible();
+        let opponent_win = self.opponent_winning_position();
+        let forced = possible & opponent_win;
+        if forced != 0 {
+            // `forced & (forced - 1)` clears the lowest bit, so it is only 0 if one bit was set
+            if forced & (forced - 1) != 0 {
+                return 0;
+            }
+            possible = forced;
+        }
+        // Playing directly below one of the opponent's winning tiles lets them play there
+        possible & !(opponent_win >> 1)
+    }
+
+    // How good a move looks before searching it: the number of winning tiles it gives us
+    fn move_score(&self, tile: u64) -> u32 {
+        winning_tiles(self.current | tile, self.mask).count_ones()
+    }
+
+    // The empty tiles that would complete a line for the player to move
+    fn winning_position(&self) -> u64 {
+        winning_tiles(self.current, self.mask)
+    }
+
+    // The empty tiles that would complete a line for the opponent
+    fn opponent_winning_position(&self) -> u64 {
+        winning_tiles(self.current ^ self.mask, self.mask)
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Returns every empty tile that would complete a line of four for the pieces in `pieces`. Shifting
+// a bitboard moves every piece in the same direction at once: by 1 for up and down a column, by H1
+// for along a row and by H1 - 1 and H1 + 1 for the two diagonals. A tile completes a line if there
+// are three pieces next to it in one of those directions (or two on one side and one on the other).
+fn winning_tiles(pieces: u64, mask: u64) -> u64 {
+    // Vertical lines can only be completed from the top
+    let mut tiles = (pieces << 1) & (pieces << 2) & (pieces << 3);
+
+    for &shift in &[H1, H1 - 1, H1 + 1] {
+        let pair = (pieces << shift) & (pieces << (2 * shift));
+        tiles |= pair & (pieces << (3 * shift));
+        tiles |= pair & (pieces >> shift);
+        let pair = (pieces >> shift) & (pieces >> (2 * shift));
+        tiles |= pair & (pieces << shift);
+        tiles |= pair & (pieces >> (3 * shift));
+    }
+
+    tiles & (BOARD_MASK ^ mask)
+}
+
+// Remembers a bound on the score of positions that have been searched. Each entry holds the key of
+// the position in the top bits and the stored value in the lowest 8 bits. An entry is simply
+// replaced when another position needs the same spot.
+struct TranspositionTable {
+    entries: Vec<u64>,
+}
+
+impl TranspositionTable {
+    fn new(size: usize) -> Self {
+        Self {entries: vec![0; size.max(1)]}
+    }
+
+    fn put(&mut self, key: u64, value: u8) {
+        let index = (key % self.entries.len() as u64) as usize;
+        self.entries[index] = (key << 8) | value as u64;
+    }
+
+    // Returns the value stored for the position, or None if it isn't in the table
+    fn get(&self, key: u64) -> Option<u8> {
+        let entry = self.entries[(key % self.entries.len() as u64) as usize];
+        if entry != 0 && entry >> 8 == key {
+            Some(entry as u8)
+        }
+        else {
+            None
+        }
+    }
+
+    fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = 0;
+        }
+    }
+}
+
+// The solver keeps its transposition table between calls, so solving positions from the same game
+// one after another gets faster as it goes
+pub struct Solver {
+    table: TranspositionTable,
+    nodes: u64,
+}
+
+impl Solver {
+    // Creates a solver with a 64MB transposition table
+    pub fn new() -> Self {
+        Self::with_table_size(TABLE_SIZE)
+    }
+
+    // Creates a solver with room for `entries` positions in its transposition table. A smaller table
+    // uses less memory but makes the solver slower.
+    pub fn with_table_size(entries: usize) -> Self {
+        Self {table: TranspositionTable::new(entries), nodes: 0}
+    }
+
+    // The number of positions searched since the solver was created or reset
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    // Forgets everything the solver has learned
+    pub fn reset(&mut self) {
+        self.table.clear();
+        self.nodes = 0;
+    }
+
+    // Returns the exact score of the position. See the top of this file for what the score means.
+    pub fn solve(&mut self, position: &Position) -> i32 {
+        self.solve_between(position, -(TILES - position.moves as i32) / 2, (TILES + 1 - position.moves as i32) / 2)
+    }
+
+    // Only finds out whether the position is a win (1), a draw (0) or a loss (-1) for the player to
+    // move. This is much faster than finding the exact score.
+    pub fn solve_weak(&mut self, position: &Position) -> i32 {
+        self.solve_between(position, -1, 1)
+    }
+
+    // Returns the score of each column, or None for columns that are full. Columns are numbered from
+    // 0, so the middle column is 3.
+    pub fn column_scores(&mut self, position: &Position) -> [Option<i32>; WIDTH] {
+        let mut scores = [None; WIDTH];
+        for (col, score) in scores.iter_mut().enumerate() {
+            if !position.can_play(col) {
+                continue;
+            }
+            *score = Some(if position.is_winning_move(col) {
+                (TILES + 1 - position.moves as i32) / 2
+            }
+            else {
+                let mut next = *position;
+                next.play_col(col);
+                -self.solve(&next)
+            });
+        }
+        scores
+    }
+
+    // Narrows the score down using searches with a window of size 1. Each search tells us whether
+    // the score is above or below the middle of the window, a lot like a binary search.
+    fn solve_between(&mut self, position: &Position, mut min: i32, mut max: i32) -> i32 {
+        // The search below assumes that the player to move can't win straight away
+        if position.can_win_next() {
+            return (TILES + 1 - position.moves as i32) / 2;
+        }
+
+        while min < max {
+            let mut middle = min + (max - min) / 2;
+            // Checking close to 0 first finds the result (win, draw or loss) quickly, since
+            // searches for scores near 0 are the cheapest
+            if middle <= 0 && min / 2 < middle {
+                middle = min / 2;
+            }
+            else if middle >= 0 && max / 2 > middle {
+                middle = max / 2;
+            }
+            let score = self.negamax(position, middle, middle + 1);
+            if score <= middle {
+                max = score;
+            }
+            else {
+                min = score;
+            }
+        }
+        min
+    }
+
+    // Returns the score of a position in which the player to move can't win straight away. Like
+    // the negamax in the ai module, only scores between alpha and beta are exact: a score at or
+    // below alpha means "at most alpha" and a score at or above beta means "at least beta".
+    fn negamax(&mut self, position: &Position, mut alpha: i32, mut beta: i32) -> i32 {
+        self.nodes += 1;
+        let moves = position.moves as i32;
+
+        // If every move hands the opponent a win, we lose after their next move
+        let next = position.possible_non_losing_moves();
+        if next == 0 {
+            return -(TILES - moves) / 2;
+        }
+        // With only two tiles left and no win for either player on the next move, it's a draw
+        if moves >= TILES - 2 {
+            return 0;
+        }
+
+        // We can't win straight away, and the opponent can't win on their next move, so the score
+        // is at least the score of losing on the move after that
+        let min = -(TILES - 2 - moves) / 2;
+        if alpha < min {
+            alpha = min;
+            if alpha >= beta {
+                return alpha;
+            }
+        }
+
+        // In the same way, we can't win before our move after next. The transposition table may
+        // know a better limit.
+        let mut max = (TILES - 1 - moves) / 2;
+        if let Some(value) = self.table.get(position.key()) {
+            max = value as i32 + MIN_SCORE - 1;
+        }
+        if beta > max {
+            beta = max;
+            if alpha >= beta {
+                return beta;
+            }
+        }
+
+        // Try the moves that create the most winning tiles first. The sort is stable, so moves with
+        // the same score stay in the center-first order.
+        let mut ordered: Vec<(u64, u32)> = COLUMN_ORDER.iter()
+            .map(|&col| next & column_mask(col))
+            .filter(|&tile| tile != 0)
+            .map(|tile| (tile, position.move_score(tile)))
+            .collect();
+        ordered.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+
+        for (tile, _) in ordered {
+            let mut child = *position;
+            child.play(tile);
+            let score = -self.negamax(&child, -beta, -alpha);
+            if score >= beta {
+                return score;
+            }
+            if score > alpha {
+                alpha = score;
+            }
+        }
+
+        // alpha is now an upper limit on the score of this position. Adding 1 - MIN_SCORE keeps the
+        // stored value above 0, since 0 means an empty entry.
+        self.table.put(position.key(), (alpha - MIN_SCORE + 1) as u8);
+        alpha
+    }
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Turns a score for `position` into the number of moves until the game ends, counting the moves of
+// both players, in the same form that `ai::analyze` uses
+pub fn evaluation(position: &Position, score: i32) -> Evaluation {
+    let moves = position.moves as i32;
+    if score > 0 {
+        // Winning straight away scores (TILES + 1 - moves) / 2, and every two moves that it takes
+        // to win lowers the score by 1
+        Evaluation::Win((2 * ((TILES + 1 - moves) / 2 - score) + 1) as usize)
+    }
+    else if score < 0 {
+        // Losing straight after our move scores -(TILES - moves) / 2
+        Evaluation::Loss((2 * ((TILES - moves) / 2 + score) + 2) as usize)
+    }
+    else {
+        Evaluation::Draw
+    }
+}
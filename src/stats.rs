@@ -0,0 +1,128 @@
+//
+// file: stats.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module visits every position that can come up in a game and counts them. For Tic-Tac-Toe
+// the answers are well known: there are 5,478 different positions (765 if symmetric positions are
+// counted once) and 255,168 different games, so getting the same numbers is a good check that the
+// rules are implemented correctly. On other small boards the numbers are interesting in their own
+// right.
+//
+// Every position is only searched once. The number of games that can still be played from a
+// position is remembered by its hash key (see `Game::hash_key`), so reaching the same position
+// again through a different order of moves just adds the remembered numbers instead of playing all
+// of those games out again.
+// For more information, see: http://www.se16.info/hgb/tictactoe.htm
+
+use std::collections::HashMap;
+
+use crate::{symmetry, Game, Winner};
+
+// The number of games (or positions) that ended each way
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tally {
+    pub x_wins: u64,
+    pub o_wins: u64,
+    pub ties: u64,
+}
+
+impl Tally {
+    // The number of games (or positions) altogether
+    pub fn total(&self) -> u64 {
+        self.x_wins + self.o_wins + self.ties
+    }
+
+    // A tally with one game that ended with this winner
+    fn one(winner: Winner) -> Self {
+        let mut tally = Self::default();
+        match winner {
+            Winner::X => tally.x_wins = 1,
+            Winner::O => tally.o_wins = 1,
+            Winner::Tie => tally.ties = 1,
+        }
+        tally
+    }
+
+    fn add(&mut self, other: Tally) {
+        self.x_wins += other.x_wins;
+        self.o_wins += other.o_wins;
+        self.ties += other.ties;
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of different positions with each number of moves played, starting with the
+    /// position the count started from
+    pub positions_by_depth: Vec<u64>,
+
+    /// The number of different positions where the game is over, by who won
+    pub finished_positions: Tally,
+
+    /// The number of different games that can be played to the end, by who won. Two games are
+    /// different if their moves are different, even if they end in the same position.
+    pub games: Tally,
+}
+
+impl Stats {
+    // The number of different positions altogether
+    pub fn positions(&self) -> u64 {
+        self.positions_by_depth.iter().sum()
+    }
+}
+
+// Counts every position and game that can follow on from `game`. With `symmetric`, positions that
+// are the same after turning or flipping the board (see the symmetry module) are only counted once.
+// The games are always all counted.
+// The number of positions grows very quickly with the size of the board, so this is only practical
+// on small boards.
+pub fn enumerate(game: &Game, symmetric: bool) -> Stats {
+    let mut counter = Counter {symmetric, stats: Stats::default(), games: HashMap::new()};
+    // The depths are counted from `game`, which doesn't have to be an empty board
+    counter.stats.games = counter.visit(&mut game.clone(), 0);
+    counter.stats
+}
+
+// Keeps track of the counts during the search
+struct Counter {
+    symmetric: bool,
+    stats: Stats,
+    // The games that can be played from each position that has been searched already
+    games: HashMap<u64, Tally>,
+}
+
+impl Counter {
+    // Counts the position if it is new, and returns the number of games that can be played from it
+    fn visit(&mut self, game: &mut Game, depth: usize) -> Tally {
+        // Symmetric positions have the same number of games, so they can share the key
+        let key = if self.symmetric { symmetry::canonical(game).0.hash_key() } else { game.hash_key() };
+        if let Some(&games) = self.games.get(&key) {
+            return games;
+        }
+
+        if self.stats.positions_by_depth.len() <= depth {
+            self.stats.positions_by_depth.push(0);
+        }
+        self.stats.positions_by_depth[depth] += 1;
+
+        let games = match game.winner() {
+            Some(winner) => {
+                self.stats.finished_positions.add(Tally::one(winner));
+                Tally::one(winner)
+            },
+            None => {
+                let mut games = Tally::default();
+                for (row, col) in game.legal_moves() {
+                    game.make_move(row, col).expect("legal moves should always be valid");
+                    games.add(self.visit(game, depth + 1));
+                    game.undo();
+                }
+                games
+            },
+        };
+        self.games.insert(key, games);
+        games
+    }
+}
@@ -0,0 +1,169 @@
+//
+// file: symmetry.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module knows about the ways a board can be turned or flipped without changing the game.
+// A Tic-Tac-Toe board can be rotated a quarter turn at a time and mirrored, which gives 8
+// symmetries, and every position has up to 8 variants that play exactly the same. Boards that
+// aren't square only keep the half turn and the mirrors, and boards with gravity can only be
+// mirrored left to right, since turning a Connect Four board upside down would make the pieces
+// float.
+//
+// Picking one of the variants as the "canonical" one lets us treat all of them as the same
+// position, which makes opening books and tablebases much smaller. The canonical variant is the
+// one whose tiles come first when read row by row, with empty tiles before x and x before o.
+// For more information, see: https://en.wikipedia.org/wiki/Dihedral_group
+
+use crate::{zobrist, Game, Piece, Tile};
+
+// One way of turning or flipping the board. "Rows" and "cols" below are the size of the board
+// before it is turned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Symmetry {
+    /// Leaves the board as it is
+    Identity,
+
+    /// Turns the board a quarter turn clockwise, so the top left corner ends up top right
+    Rotate90,
+
+    /// Turns the board upside down
+    Rotate180,
+
+    /// Turns the board a quarter turn anticlockwise, so the top left corner ends up bottom left
+    Rotate270,
+
+    /// Swaps the left and right sides of the board
+    MirrorLeftRight,
+
+    /// Swaps the top and bottom of the board
+    MirrorUpDown,
+
+    /// Flips the board over the diagonal from the top left corner to the bottom right corner
+    MirrorDiagonal,
+
+    /// Flips the board over the diagonal from the top right corner to the bottom left corner
+    MirrorAntiDiagonal,
+}
+
+impl Symmetry {
+    // Every symmetry, starting with the identity
+    pub const ALL: [Symmetry; 8] = [
+        Symmetry::Identity,
+        Symmetry::Rotate90,
+        Symmetry::Rotate180,
+        Symmetry::Rotate270,
+        Symmetry::MirrorLeftRight,
+        Symmetry::MirrorUpDown,
+        Symmetry::MirrorDiagonal,
+        Symmetry::MirrorAntiDiagonal,
+    ];
+
+    // Returns the symmetry that undoes this one. Only the quarter turns need a different one, since
+    // doing any of the others twice puts the board back the way it was.
+    pub fn inverse(self) -> Self {
+        match self {
+            Symmetry::Rotate90 => Symmetry::Rotate270,
+            Symmetry::Rotate270 => Symmetry::Rotate90,
+            other => other,
+        }
+    }
+
+    // Returns true if this symmetry swaps the rows and columns, which changes the shape of a board
+    // that isn't square
+    pub fn swaps_sides(self) -> bool {
+        matches!(
+            self,
+            Symmetry::Rotate90 | Symmetry::Rotate270 | Symmetry::MirrorDiagonal | Symmetry::MirrorAntiDiagonal
+        )
+    }
+
+    // Returns where the tile at (row, col) ends up on a board with `rows` rows and `cols` columns.
+    // This also maps a move in one position to the same move in the turned position.
+    pub fn apply(self, (row, col): (usize, usize), rows: usize, cols: usize) -> (usize, usize) {
+        // The last row and column, since the tiles are numbered from 0
+        let (last_row, last_col) = (rows - 1, cols - 1);
+        match self {
+            Symmetry::Identity => (row, col),
+            Symmetry::Rotate90 => (col, last_row - row),
+            Symmetry::Rotate180 => (last_row - row, last_col - col),
+            Symmetry::Rotate270 => (last_col - col, row),
+            Symmetry::MirrorLeftRight => (row, last_col - col),
+            Symmetry::MirrorUpDown => (last_row - row, col),
+            Symmetry::MirrorDiagonal => (col, row),
+            Symmetry::MirrorAntiDiagonal => (last_col - col, last_row - row),
+        }
+    }
+
+    // Returns the game as it looks after turning the board, with the moves in its history (and the
+    // moves that can be redone) turned too. Returns None if the symmetry doesn't fit the game (see
+    // `symmetries`).
+    pub fn transform(self, game: &Game) -> Option<Game> {
+        if !symmetries(game).contains(&self) {
+            return None;
+        }
+        let (rows, cols) = (game.rows(), game.cols());
+        let apply = |&tile: &(usize, usize)| self.apply(tile, rows, cols);
+
+        let mut turned = game.clone();
+        for (row, tiles) in game.tiles.iter().enumerate() {
+            for (col, &tile) in tiles.iter().enumerate() {
+                let (new_row, new_col) = apply(&(row, col));
+                turned.tiles[new_row][new_col] = tile;
+            }
+        }
+        turned.history = game.history.iter().map(apply).collect();
+        turned.undone = game.undone.iter().map(apply).collect();
+        turned.hash = zobrist::hash(&turned.tiles, turned.current_piece);
+        Some(turned)
+    }
+}
+
+// Returns the symmetries that turn the board into a board where the game plays exactly the same,
+// starting with the identity
+pub fn symmetries(game: &Game) -> Vec<Symmetry> {
+    Symmetry::ALL.iter().copied().filter(|&symmetry| {
+        if game.has_gravity() {
+            // Pieces have to stay at the bottom of their columns
+            matches!(symmetry, Symmetry::Identity | Symmetry::MirrorLeftRight)
+        }
+        else {
+            // Without gravity every tile is the same, so only the shape of the board matters
+            game.rows() == game.cols() || !symmetry.swaps_sides()
+        }
+    }).collect()
+}
+
+// Returns every variant of the game along with the symmetry that makes it. Variants can be the
+// same, e.g. every variant of an empty board is the same empty board.
+pub fn variants(game: &Game) -> Vec<(Symmetry, Game)> {
+    symmetries(game).into_iter().map(|symmetry| {
+        let variant = symmetry.transform(game).expect("symmetries should fit the game");
+        (symmetry, variant)
+    }).collect()
+}
+
+// Returns the canonical variant of the game along with the symmetry that turns the game into it.
+// Every variant of a position has the same canonical variant, so its `hash_key` can be used to
+// look up all of them at once. A move in the canonical variant can be turned back into a move in
+// the original game with `symmetry.inverse().apply(..)`.
+pub fn canonical(game: &Game) -> (Game, Symmetry) {
+    // `min_by_key` keeps the first of several equal variants, which is the identity if it is one
+    // of them
+    let (symmetry, variant) = variants(game).into_iter()
+        .min_by_key(|(_, variant)| order(variant))
+        .expect("the identity is always a symmetry");
+    (variant, symmetry)
+}
+
+// The tiles of a game read row by row, as numbers that can be compared. Piece doesn't implement
+// Ord, because "x is less than o" doesn't mean anything in the game itself.
+fn order(game: &Game) -> Vec<u8> {
+    let value = |tile: &Tile| match tile {
+        None => 0,
+        Some(Piece::X) => 1,
+        Some(Piece::O) => 2,
+    };
+    game.tiles().iter().flatten().map(value).collect()
+}
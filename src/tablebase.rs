@@ -0,0 +1,348 @@
+//
+// file: tablebase.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module works out the result of every position on a small board ahead of time and stores it
+// in a table, called a tablebase. After that, finding the best move is just a matter of looking up
+// the position after each move, with no searching at all.
+//
+// The table is built by "retrograde analysis", which works backwards from the end of the game:
+//
+// 1. Every position that can be reached from the start is visited once and linked to the positions
+//    that come right after it.
+// 2. In a position where someone has just won, the piece to move has lost.
+// 3. Going backwards, a position is a win if one of its moves leads to a position that is lost for
+//    the opponent, and it is a loss if every move leads to a position that is won for the
+//    opponent. The positions are handled closest to the end first, so the first winning move found
+//    is the fastest, and the last losing move found is the one that holds out the longest.
+// 4. Anything that is neither a win nor a loss once there is nothing left to do is a draw.
+//
+// Positions that are the same after turning or flipping the board (see the symmetry module) are
+// stored once, which makes the Tic-Tac-Toe table 765 positions instead of 5,478.
+// For more information, see: https://en.wikipedia.org/wiki/Retrograde_analysis
+//
+// The table is saved as a small binary file: the bytes "CRTB", a version number, the rules string
+// of the game, the number of positions and then each position's hash key and result.
+
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::ai::{self, Evaluation};
+use crate::{symmetry, Game, Winner};
+
+// The first bytes of every tablebase file, so that other files are rejected straight away
+const MAGIC: &[u8; 4] = b"CRTB";
+const VERSION: u8 = 1;
+
+// Each result is stored in one byte. 0 is a draw, a win in n moves is n and a loss in n moves is n
+// with the top bit set. That leaves 7 bits for the number of moves.
+const LOSS_BIT: u8 = 0x80;
+const MAX_TILES: usize = 0x7F;
+
+// This type represents the possible errors that can occur when building, saving or loading a
+// tablebase
+#[derive(Debug)]
+pub enum TablebaseError {
+    /// The file could not be read or written
+    Io(io::Error),
+
+    /// The file isn't a tablebase, or it was cut short
+    BadFormat,
+
+    /// The board has too many tiles for the number of moves to fit in the file
+    TooLarge { tiles: usize },
+}
+
+impl fmt::Display for TablebaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TablebaseError::Io(error) => write!(f, "{}", error),
+            TablebaseError::BadFormat => write!(f, "The file is not a tablebase"),
+            TablebaseError::TooLarge {tiles} => {
+                write!(f, "A board with {} tiles is too big for a tablebase, the most is {}", tiles, MAX_TILES)
+            },
+        }
+    }
+}
+
+impl Error for TablebaseError {}
+
+// `From` lets the `?` operator turn an io::Error into a TablebaseError for us
+impl From<io::Error> for TablebaseError {
+    fn from(error: io::Error) -> Self {
+        TablebaseError::Io(error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tablebase {
+    // The rules string of the game the table was made for (see `Game::rules_string`)
+    rules: String,
+    // The result of each position for the piece to move, by the hash key of its canonical variant.
+    // A win or loss in 0 moves means the game is already over.
+    results: HashMap<u64, Evaluation>,
+}
+
+// One position while the table is being built
+struct Node {
+    // The positions right after each move. Moves that lead to symmetric positions only count once.
+    children: Vec<usize>,
+    parents: Vec<usize>,
+    // Who won, if the game is over. Finished games have no children.
+    winner: Option<Winner>,
+    result: Option<Evaluation>,
+    // The number of children that aren't known to be wins for the opponent yet. The position is
+    // lost once this gets to 0.
+    unresolved: usize,
+}
+
+impl Tablebase {
+    // Works out the result of every position that can come up after `game`, which is usually an
+    // empty board. This visits every one of those positions, so it is only practical on small
+    // boards.
+    pub fn generate(game: &Game) -> Result<Self, TablebaseError> {
+        let tiles = game.rows() * game.cols();
+        if tiles > MAX_TILES {
+            return Err(TablebaseError::TooLarge {tiles});
+        }
+
+        // Step 1: find every position and link it to the positions after it
+        let mut nodes = Vec::new();
+        let mut keys = HashMap::new();
+        explore(&mut game.clone(), &mut nodes, &mut keys);
+        for index in 0..nodes.len() {
+            for child in nodes[index].children.clone() {
+                nodes[child].parents.push(index);
+            }
+        }
+
+        // Step 2: the finished games. Ties are draws, and they never make their parents a win or a
+        // loss, so they don't have to go in the queue.
+        let mut queue = VecDeque::new();
+        for (index, node) in nodes.iter_mut().enumerate() {
+            match node.winner {
+                Some(Winner::Tie) => node.result = Some(Evaluation::Draw),
+                Some(_) => {
+                    node.result = Some(Evaluation::Loss(0));
+                    queue.push_back(index);
+                },
+                None => {},
+            }
+        }
+
+        // Step 3: work backwards. The queue is always in order of the number of moves to the end.
+        while let Some(index) = queue.pop_front() {
+            let result = nodes[index].result.expect("positions in the queue have a result");
+            for parent in nodes[index].parents.clone() {
+                let node = &mut nodes[parent];
+                if node.result.is_some() {
+                    continue;
+                }
+                match result {
+                    Evaluation::Loss(moves) => {
+                        node.result = Some(Evaluation::Win(moves + 1));
+                        queue.push_back(parent);
+                    },
+                    Evaluation::Win(moves) => {
+                        node.unresolved -= 1;
+                        if node.unresolved == 0 {
+                            node.result = Some(Evaluation::Loss(moves + 1));
+                            queue.push_back(parent);
+                        }
+                    },
+                    _ => {},
+                }
+            }
+        }
+
+        // Step 4: everything else is a draw
+        let results = keys.into_iter()
+            .map(|(key, index)| (key, nodes[index].result.unwrap_or(Evaluation::Draw)))
+            .collect();
+        Ok(Self {rules: game.rules_string(), results})
+    }
+
+    // The rules string of the game the table was made for
+    pub fn rules(&self) -> &str {
+        &self.rules
+    }
+
+    // The number of positions in the table. Symmetric positions only count once.
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+
+    // Returns the result of the position for the piece to move, or None if the position isn't in
+    // the table, e.g. because the table was made for other rules. A finished game is a draw, or a
+    // loss in 0 moves if the other piece won.
+    pub fn probe(&self, game: &Game) -> Option<Evaluation> {
+        if game.rules_string() != self.rules {
+            return None;
+        }
+        self.results.get(&symmetry::canonical(game).0.hash_key()).copied()
+    }
+
+    // Scores every legal move for the current piece, best move first, just like `ai::analyze`.
+    // Returns None if any of the positions after the moves aren't in the table.
+    pub fn analyze(&self, game: &Game) -> Option<Vec<((usize, usize), Evaluation)>> {
+        if game.is_finished() {
+            return Some(Vec::new());
+        }
+        let mut game = game.clone();
+        let mut scored = Vec::new();
+        for (row, col) in ai::ordered_moves(&game) {
+            game.make_move(row, col).expect("legal moves should always be valid");
+            // The result after the move is for the opponent, so a loss for them is a win for us.
+            // The move itself counts as one more move.
+            let evaluation = match self.probe(&game)? {
+                Evaluation::Loss(moves) => Evaluation::Win(moves + 1),
+                Evaluation::Win(moves) => Evaluation::Loss(moves + 1),
+                other => other,
+            };
+            game.undo();
+            scored.push(((row, col), evaluation));
+        }
+
+        // Quick wins come first and slow losses come before quick ones. The sort is stable, so
+        // moves that are just as good stay in the center-first order.
+        scored.sort_by_key(|&(_, evaluation)| match evaluation {
+            Evaluation::Win(moves) => (0, moves as isize),
+            Evaluation::Loss(moves) => (2, -(moves as isize)),
+            _ => (1, 0),
+        });
+        Some(scored)
+    }
+
+    // Returns the best move for the current piece, or None if the game is over or the position
+    // isn't in the table
+    pub fn best_move(&self, game: &Game) -> Option<(usize, usize)> {
+        self.analyze(game)?.first().map(|&(mv, _)| mv)
+    }
+
+    // Writes the table in the binary format described at the top of this module. The positions are
+    // sorted so that the same table always gives the same bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.push(self.rules.len() as u8);
+        bytes.extend_from_slice(self.rules.as_bytes());
+        bytes.extend_from_slice(&(self.results.len() as u32).to_le_bytes());
+
+        let mut results: Vec<_> = self.results.iter().collect();
+        results.sort_by_key(|&(&key, _)| key);
+        for (key, evaluation) in results {
+            bytes.extend_from_slice(&key.to_le_bytes());
+            bytes.push(match *evaluation {
+                Evaluation::Win(moves) => moves as u8,
+                Evaluation::Loss(moves) => LOSS_BIT | moves as u8,
+                _ => 0,
+            });
+        }
+        bytes
+    }
+
+    // Reads a table written by `to_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, TablebaseError> {
+        let mut reader = Reader {bytes};
+        if reader.take(MAGIC.len())? != MAGIC || reader.take(1)?[0] != VERSION {
+            return Err(TablebaseError::BadFormat);
+        }
+        let length = reader.take(1)?[0] as usize;
+        let rules = String::from_utf8(reader.take(length)?.to_vec()).map_err(|_| TablebaseError::BadFormat)?;
+        // The rules have to describe a game that can actually be played, and that a table could
+        // have been made for. `from_rules_string` turns down boards too big to make before any
+        // memory is set aside for their tiles.
+        let game = Game::from_rules_string(&rules).map_err(|_| TablebaseError::BadFormat)?;
+        if game.rows() * game.cols() > MAX_TILES {
+            return Err(TablebaseError::BadFormat);
+        }
+
+        // Each result takes up 9 bytes. The count comes from the file, so it is checked against
+        // what is left before any room is set aside for it.
+        let count = u32::from_le_bytes(reader.array()?) as usize;
+        if count.checked_mul(9) != Some(reader.bytes.len()) {
+            return Err(TablebaseError::BadFormat);
+        }
+        let mut results = HashMap::with_capacity(count);
+        for _ in 0..count {
+            let key = u64::from_le_bytes(reader.array()?);
+            let value = reader.take(1)?[0];
+            let evaluation = match value {
+                0 => Evaluation::Draw,
+                _ if value & LOSS_BIT != 0 => Evaluation::Loss((value & !LOSS_BIT) as usize),
+                _ => Evaluation::Win(value as usize),
+            };
+            results.insert(key, evaluation);
+        }
+        Ok(Self {rules, results})
+    }
+
+    // Saves the table to a file, replacing the file if it already exists
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), TablebaseError> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    // Loads a table saved with `save`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, TablebaseError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+// Visits the position and every position after it that hasn't been visited yet, and returns the
+// index of its node
+fn explore(game: &mut Game, nodes: &mut Vec<Node>, keys: &mut HashMap<u64, usize>) -> usize {
+    let key = symmetry::canonical(game).0.hash_key();
+    if let Some(&index) = keys.get(&key) {
+        return index;
+    }
+    let index = nodes.len();
+    nodes.push(Node {children: Vec::new(), parents: Vec::new(), winner: game.winner(), result: None, unresolved: 0});
+    keys.insert(key, index);
+
+    let mut children = Vec::new();
+    for (row, col) in game.legal_moves() {
+        game.make_move(row, col).expect("legal moves should always be valid");
+        let child = explore(game, nodes, keys);
+        game.undo();
+        if !children.contains(&child) {
+            children.push(child);
+        }
+    }
+    nodes[index].unresolved = children.len();
+    nodes[index].children = children;
+    index
+}
+
+// Reads the binary format a few bytes at a time. Running out of bytes means the file was cut short.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], TablebaseError> {
+        if self.bytes.len() < count {
+            return Err(TablebaseError::BadFormat);
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    // Takes exactly N bytes as an array, which is what `from_le_bytes` needs
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], TablebaseError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+}
@@ -0,0 +1,172 @@
+//
+// file: theme.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module decides what the board looks like when it is printed. A Theme picks the characters
+// used for the pieces and empty tiles, and optionally a color for each piece. The colors are
+// ANSI escape codes, which most terminals understand. For more information, see:
+// https://en.wikipedia.org/wiki/ANSI_escape_code#SGR_(Select_Graphic_Rendition)_parameters
+//
+// Colours are only wanted when a person is looking at a terminal, so `Theme::detect` turns them off
+// when the output goes to a file or another program, or when the NO_COLOR environment variable is
+// set (see https://no-color.org).
+
+use std::env;
+use std::io::{self, IsTerminal};
+
+use crate::{Piece, Tile};
+
+// The names that can be given to `Theme::named`, e.g. from the --theme command-line option
+pub const THEME_NAMES: [&str; 5] = ["plain", "unicode", "color", "discs", "high-contrast"];
+
+// The ANSI codes that turn color on for each piece. These are the numbers that go between `\x1b[`
+// and `m`, e.g. "91" is bright red.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Colors {
+    pub x: &'static str,
+    pub o: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Theme {
+    /// The character used for each piece and for empty tiles
+    pub x: char,
+    pub o: char,
+    pub empty: char,
+
+    /// The characters used for the pieces in the winning line
+    pub x_winning: char,
+    pub o_winning: char,
+
+    /// The color of each piece, or None to print without color
+    pub colors: Option<Colors>,
+}
+
+// The ANSI code that swaps the text and background colors. The winning line is shown this way
+// when there are colors, so that it stands out even on terminals that can't show bold text.
+const REVERSE: &str = "7";
+
+impl Theme {
+    // Only ASCII characters, for terminals that can't show anything else. This is how the board
+    // looks on a "dumb" terminal.
+    pub fn plain() -> Self {
+        Self {x: 'x', o: 'o', empty: '.', x_winning: 'X', o_winning: 'O', colors: None}
+    }
+
+    // The original look of the board without any color. This is what a Console uses unless it is
+    // given another theme.
+    pub fn unicode() -> Self {
+        Self {x: 'x', o: 'o', empty: '\u{25A2}', x_winning: 'X', o_winning: 'O', colors: None}
+    }
+
+    // The original look of the board with x in red and o in blue
+    pub fn color() -> Self {
+        Self {colors: Some(Colors {x: "91", o: "94"}), ..Self::unicode()}
+    }
+
+    // Round discs like the real Connect Four game, red for x and yellow for o. The discs are
+    // filled in for x and hollow for o so that they can be told apart without color too.
+    pub fn discs() -> Self {
+        Self {
+            x: '\u{25CF}',
+            o: '\u{25CB}',
+            empty: '\u{00B7}',
+            x_winning: '\u{25C9}',
+            o_winning: '\u{25CE}',
+            colors: Some(Colors {x: "91", o: "93"}),
+        }
+    }
+
+    // Bold blue and orange, which can be told apart by people with the common kinds of color
+    // blindness. These come from the palette at https://jfly.uni-koeln.de/color/
+    pub fn high_contrast() -> Self {
+        Self {colors: Some(Colors {x: "1;38;5;33", o: "1;38;5;214"}), ..Self::unicode()}
+    }
+
+    // Returns the theme with the given name (see THEME_NAMES), or None if there isn't one
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "plain" => Some(Self::plain()),
+            "unicode" => Some(Self::unicode()),
+            "color" | "colour" => Some(Self::color()),
+            "discs" => Some(Self::discs()),
+            "high-contrast" => Some(Self::high_contrast()),
+            _ => None,
+        }
+    }
+
+    // Picks a theme for printing to stdout. Dumb terminals get the plain theme and everything else
+    // gets the color theme, with the colors turned off when they aren't wanted.
+    pub fn detect() -> Self {
+        // `as_deref` turns the String inside the Result into a &str so that we can compare it
+        let dumb = env::var("TERM").as_deref() == Ok("dumb");
+        let theme = if dumb { Self::plain() } else { Self::color() };
+        theme.for_stdout()
+    }
+
+    // Turns the colors off if NO_COLOR is set or stdout isn't a terminal, e.g. because the output
+    // is being written to a file. The characters are left as they are.
+    pub fn for_stdout(self) -> Self {
+        if colors_wanted(io::stdout().is_terminal()) {
+            self
+        }
+        else {
+            self.without_colors()
+        }
+    }
+
+    // Returns the same theme without any colors
+    pub fn without_colors(self) -> Self {
+        Self {colors: None, ..self}
+    }
+
+    // Returns the character for a tile without any color. This and `tile_color` are for drawing the
+    // board some other way than printing `tile`, like the tui module does.
+    pub fn tile_symbol(&self, tile: Tile, winning: bool) -> char {
+        match (tile, winning) {
+            (Some(Piece::X), false) => self.x,
+            (Some(Piece::X), true) => self.x_winning,
+            (Some(Piece::O), false) => self.o,
+            (Some(Piece::O), true) => self.o_winning,
+            (None, _) => self.empty,
+        }
+    }
+
+    // Returns the ANSI code for the color of a tile, or None if it isn't shown in color
+    pub fn tile_color(&self, tile: Tile) -> Option<&'static str> {
+        match tile {
+            Some(Piece::X) => self.colors.map(|colors| colors.x),
+            Some(Piece::O) => self.colors.map(|colors| colors.o),
+            None => None,
+        }
+    }
+
+    // Returns the text to print for a tile. `winning` is true if the tile is part of the winning
+    // line.
+    pub fn tile(&self, tile: Tile, winning: bool) -> String {
+        let symbol = self.tile_symbol(tile, winning);
+
+        // `\x1b[...m` turns the color on and `\x1b[0m` turns it off again
+        match self.tile_color(tile) {
+            Some(color) if winning => format!("\x1b[{};{}m{}\x1b[0m", color, REVERSE, symbol),
+            Some(color) => format!("\x1b[{}m{}\x1b[0m", color, symbol),
+            None => symbol.to_string(),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::unicode()
+    }
+}
+
+// Returns true if colors should be used for output that is (or isn't) going to a terminal. The
+// NO_COLOR convention says that colors are turned off whenever it is set to anything but an empty
+// string.
+fn colors_wanted(is_terminal: bool) -> bool {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    is_terminal && !no_color
+}
@@ -0,0 +1,514 @@
+//
+// file: tournament.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module runs matches between bots to find out which one plays better. Every pairing plays
+// its games two at a time from the same random opening, once with each player going first, since
+// going first is a big advantage in most of these games. The games are spread over several threads
+// so that long matches finish sooner.
+//
+// The results are turned into an Elo difference, which is the usual way of saying how much
+// stronger one player is than another: a player 100 Elo stronger scores about 64%. Since the
+// games are random, the difference comes with an error bar that covers the real difference 95% of
+// the time.
+//
+// To check that a change is an improvement without playing more games than needed, a match
+// between two players can use a sequential probability ratio test (SPRT). It stops as soon as the
+// results make it clear enough whether the new player is at least `elo1` stronger or at most
+// `elo0` stronger.
+// For more information, see: https://www.chessprogramming.org/Match_Statistics
+
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use crate::console::Console;
+use crate::player::Player;
+use crate::rng::Rng;
+use crate::{run_console, Forfeit, Game, MoveError, Outcome, Piece, Winner};
+
+// How many standard errors the error bars reach on either side, which covers 95% of the results
+const CONFIDENCE: f64 = 1.96;
+
+// Who plays whom
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Every player plays every other player
+    RoundRobin,
+
+    /// The first player plays every other player, which is how a new version is compared with
+    /// several old ones
+    Gauntlet,
+}
+
+// How a tournament is run. `Default` gives a round robin with 10 games per pairing on one thread.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub format: Format,
+
+    /// The number of games each pairing plays. The games are played in pairs with the colors
+    /// swapped, so this should be even.
+    pub games: usize,
+
+    /// The number of random moves played at the start of each pair of games
+    pub opening_plies: usize,
+
+    /// The number of games played at the same time
+    pub threads: usize,
+
+    /// The seed for the openings and the players, so the same settings play the same games
+    pub seed: u64,
+
+    /// Stops the match early once the test is decided. Only the first two players are tested, so
+    /// this is meant for matches between two players.
+    pub sprt: Option<Sprt>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {format: Format::RoundRobin, games: 10, opening_plies: 2, threads: 1, seed: 0, sprt: None}
+    }
+}
+
+// The games between two players from the point of view of one of them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Score {
+    pub wins: u64,
+    pub draws: u64,
+    pub losses: u64,
+}
+
+impl Score {
+    pub fn games(&self) -> u64 {
+        self.wins + self.draws + self.losses
+    }
+
+    // A win is worth a point and a draw half a point
+    pub fn points(&self) -> f64 {
+        self.wins as f64 + self.draws as f64 / 2.0
+    }
+
+    // The same games from the other player's point of view
+    pub fn reversed(&self) -> Self {
+        Self {wins: self.losses, draws: self.draws, losses: self.wins}
+    }
+
+    fn add(&mut self, other: Score) {
+        self.wins += other.wins;
+        self.draws += other.draws;
+        self.losses += other.losses;
+    }
+
+    // The share of the points that were won, and how much that share varies from game to game
+    fn mean_and_variance(&self) -> Option<(f64, f64)> {
+        let games = self.games() as f64;
+        if games == 0.0 {
+            return None;
+        }
+        let mean = self.points() / games;
+        let variance = (self.wins as f64 * (1.0 - mean).powi(2)
+            + self.draws as f64 * (0.5 - mean).powi(2)
+            + self.losses as f64 * mean.powi(2)) / games;
+        Some((mean, variance))
+    }
+
+    // Returns the Elo difference that these results point to, along with the error bar. There is
+    // no difference to give when nothing was played or one player won every point, since that
+    // could mean any difference at all.
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let (mean, variance) = self.mean_and_variance()?;
+        if mean <= 0.0 || mean >= 1.0 {
+            return None;
+        }
+        // The error bar is worked out for the score and then turned into Elo. The ends are kept
+        // inside (0, 1), since Elo can't be worked out for them.
+        let margin = CONFIDENCE * (variance / self.games() as f64).sqrt();
+        let low = elo_difference((mean - margin).max(f64::EPSILON));
+        let high = elo_difference((mean + margin).min(1.0 - f64::EPSILON));
+        Some((elo_difference(mean), (high - low) / 2.0))
+    }
+}
+
+// The Elo difference that makes a player expect to score `score` (from 0 to 1) against another
+pub fn elo_difference(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+// The score a player is expected to get against a player `elo` weaker than them
+pub fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+// A sequential probability ratio test of whether the first player is at most `elo0` stronger than
+// the second (H0) or at least `elo1` stronger (H1). `alpha` is the chance of accepting H1 when H0
+// is true and `beta` is the chance of the opposite.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+// What the test has found out so far
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SprtResult {
+    /// The first player isn't more than `elo0` stronger
+    H0,
+
+    /// The first player is at least `elo1` stronger
+    H1,
+
+    /// More games are needed to tell
+    Undecided,
+}
+
+impl Sprt {
+    // A test with the usual 5% chance of getting it wrong either way
+    pub fn new(elo0: f64, elo1: f64) -> Self {
+        Self {elo0, elo1, alpha: 0.05, beta: 0.05}
+    }
+
+    // The log-likelihood ratio of the results, which grows as the results favor H1 and shrinks as
+    // they favor H0. This uses the normal approximation to the results, which is accurate once
+    // more than a few games have been played.
+    pub fn llr(&self, score: &Score) -> f64 {
+        let (mean, variance) = match score.mean_and_variance() {
+            Some((mean, variance)) if variance > 0.0 => (mean, variance),
+            // Until the results differ there is nothing to go on
+            _ => return 0.0,
+        };
+        let (score0, score1) = (expected_score(self.elo0), expected_score(self.elo1));
+        score.games() as f64 * (score1 - score0) * (2.0 * mean - score0 - score1) / (2.0 * variance)
+    }
+
+    // The LLRs where the test stops and accepts H0 or H1
+    pub fn bounds(&self) -> (f64, f64) {
+        ((self.beta / (1.0 - self.alpha)).ln(), ((1.0 - self.beta) / self.alpha).ln())
+    }
+
+    pub fn result(&self, score: &Score) -> SprtResult {
+        let llr = self.llr(score);
+        let (lower, upper) = self.bounds();
+        if llr <= lower {
+            SprtResult::H0
+        }
+        else if llr >= upper {
+            SprtResult::H1
+        }
+        else {
+            SprtResult::Undecided
+        }
+    }
+}
+
+// The results of a tournament
+#[derive(Debug, Clone, PartialEq)]
+pub struct Results {
+    names: Vec<String>,
+    // scores[a][b] is how player a did against player b
+    scores: Vec<Vec<Score>>,
+    forfeits: Vec<Forfeited>,
+    sprt: Option<(Sprt, SprtResult)>,
+}
+
+// A game that a player lost by breaking the rules rather than over the board. These usually mean
+// that a bot has a bug, so they are listed along with the results.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Forfeited {
+    /// The player that lost the game and its opponent, both counted from 0
+    pub player: usize,
+    pub opponent: usize,
+
+    /// The piece the player was playing
+    pub piece: Piece,
+
+    /// Why the player lost the game
+    pub reason: ForfeitReason,
+}
+
+// The ways a player can lose a game without it being played out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ForfeitReason {
+    /// The player made a move that isn't allowed, or didn't make one in time
+    InvalidMove(MoveError),
+
+    /// The player stopped playing altogether, e.g. because its engine crashed
+    Stopped,
+
+    /// The player couldn't be created for the game, e.g. because its engine wouldn't start
+    NotStarted(String),
+}
+
+impl fmt::Display for ForfeitReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ForfeitReason::InvalidMove(error) => write!(f, "{}", error),
+            ForfeitReason::Stopped => write!(f, "stopped playing"),
+            ForfeitReason::NotStarted(error) => write!(f, "could not start: {}", error),
+        }
+    }
+}
+
+impl Results {
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    // How player `a` did against player `b`, both counted from 0
+    pub fn score(&self, a: usize, b: usize) -> Score {
+        self.scores[a][b]
+    }
+
+    // How a player did against everyone else put together
+    pub fn total(&self, player: usize) -> Score {
+        let mut total = Score::default();
+        for &score in &self.scores[player] {
+            total.add(score);
+        }
+        total
+    }
+
+    // The number of games played altogether
+    pub fn games(&self) -> u64 {
+        (0..self.names.len()).map(|player| self.total(player).games()).sum::<u64>() / 2
+    }
+
+    // The games that were lost by forfeit, in the order they finished
+    pub fn forfeits(&self) -> &[Forfeited] {
+        &self.forfeits
+    }
+
+    // The test and what it found, if the tournament had one
+    pub fn sprt(&self) -> Option<(Sprt, SprtResult)> {
+        self.sprt
+    }
+
+    fn record(&mut self, (a, b): (usize, usize), score: Score) {
+        self.scores[a][b].add(score);
+        self.scores[b][a].add(score.reversed());
+    }
+}
+
+// Writes a table for each pairing and the standings, for example:
+//
+//     Player     Opponent   Games  Wins Draws Losses  Points  Elo
+//     mcts       random        20    17     2      1    18.0  +382 ± 226
+//
+//     Rank  Player   Games  Wins Draws Losses  Points  Elo
+//        1  mcts        20    17     2      1    18.0  +382 ± 226
+//
+// The Elo in the standings is against the other players put together. Any games lost by forfeit
+// are listed after the standings, with the reason.
+impl fmt::Display for Results {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self.names.iter().map(|name| name.len()).max().unwrap_or(0).max("Opponent".len());
+        writeln!(f, "{:<width$}  {:<width$}  {}", "Player", "Opponent", HEADINGS, width = width)?;
+        for a in 0..self.names.len() {
+            for b in a + 1..self.names.len() {
+                let score = self.score(a, b);
+                if score.games() > 0 {
+                    writeln!(f, "{:<width$}  {:<width$}  {}", self.names[a], self.names[b], Row(score), width = width)?;
+                }
+            }
+        }
+
+        // `sort_by` keeps players with the same points in the order they were given
+        let mut ranking: Vec<usize> = (0..self.names.len()).collect();
+        ranking.sort_by(|&a, &b| self.total(b).points().total_cmp(&self.total(a).points()));
+        writeln!(f)?;
+        writeln!(f, "Rank  {:<width$}  {}", "Player", HEADINGS, width = width)?;
+        for (rank, &player) in ranking.iter().enumerate() {
+            writeln!(f, "{:>4}  {:<width$}  {}", rank + 1, self.names[player], Row(self.total(player)), width = width)?;
+        }
+
+        if !self.forfeits.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Forfeits")?;
+            for forfeit in &self.forfeits {
+                let (player, opponent) = (&self.names[forfeit.player], &self.names[forfeit.opponent]);
+                writeln!(f, "{} ({}) against {}: {}", player, forfeit.piece.name(), opponent, forfeit.reason)?;
+            }
+        }
+
+        if let Some((sprt, result)) = self.sprt {
+            let (lower, upper) = sprt.bounds();
+            let llr = sprt.llr(&self.score(0, 1));
+            let verdict = match result {
+                SprtResult::H0 => format!("H0 accepted, {} is at most {} Elo stronger", self.names[0], sprt.elo0),
+                SprtResult::H1 => format!("H1 accepted, {} is at least {} Elo stronger", self.names[0], sprt.elo1),
+                SprtResult::Undecided => "undecided, more games are needed".to_string(),
+            };
+            writeln!(f)?;
+            writeln!(f, "SPRT [{}, {}]: LLR {:.2} ({:.2}, {:.2}), {}", sprt.elo0, sprt.elo1, llr, lower, upper, verdict)?;
+        }
+        Ok(())
+    }
+}
+
+const HEADINGS: &str = "Games  Wins Draws Losses  Points  Elo";
+
+// The numbers of a score lined up under HEADINGS
+struct Row(Score);
+
+impl fmt::Display for Row {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Row(score) = self;
+        write!(f, "{:>5} {:>5} {:>5} {:>6} {:>7.1}  ", score.games(), score.wins, score.draws, score.losses, score.points())?;
+        match score.elo() {
+            Some((elo, margin)) => write!(f, "{:+.0} ± {:.0}", elo, margin),
+            None => write!(f, "-"),
+        }
+    }
+}
+
+// One game of the tournament
+struct Job {
+    // The players, with the one playing x first
+    x: usize,
+    o: usize,
+    // Both games of a pair have the same opening seed, so they get the same opening
+    opening: u64,
+    // The seed for the players
+    seed: u64,
+}
+
+// Runs a tournament between players on the board of `game`, which is usually empty. `names` are
+// the players' names and `new_player` creates a player for every game from its index in `names`
+// and a seed. Creating the players in the threads that play the games means that players don't
+// have to be shareable between threads. A player that can't be created forfeits the game, and the
+// error it gave is listed with the forfeits.
+pub fn run<F>(game: &Game, names: &[String], settings: &Settings, new_player: F) -> Results
+where F: Fn(usize, u64) -> Result<Box<dyn Player>, String> + Sync
+{
+    let mut results = Results {
+        names: names.to_vec(),
+        scores: vec![vec![Score::default(); names.len()]; names.len()],
+        forfeits: Vec::new(),
+        sprt: settings.sprt.map(|sprt| (sprt, SprtResult::Undecided)),
+    };
+
+    let pairings: Vec<(usize, usize)> = match settings.format {
+        Format::RoundRobin => (0..names.len()).flat_map(|a| (a + 1..names.len()).map(move |b| (a, b))).collect(),
+        Format::Gauntlet => (1..names.len()).map(|b| (0, b)).collect(),
+    };
+    // The pairings take turns, so that a match stopped early by the test has played about as many
+    // games with each color. Every pairing plays the same openings.
+    let mut seeds = Rng::new(settings.seed);
+    let (mut jobs, mut opening_seed) = (Vec::new(), 0);
+    for game in 0..settings.games {
+        if game % 2 == 0 {
+            opening_seed = seeds.next_u64();
+        }
+        for &(a, b) in &pairings {
+            let (x, o) = if game % 2 == 0 { (a, b) } else { (b, a) };
+            jobs.push(Job {x, o, opening: opening_seed, seed: seeds.next_u64()});
+        }
+    }
+
+    // Each thread takes the next game that nobody has started, until there are none left or the
+    // test is decided. The results come back over a channel, so only this thread touches them.
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..settings.threads.clamp(1, jobs.len().max(1)) {
+            let sender = sender.clone();
+            let (jobs, next, stop, new_player) = (&jobs, &next, &stop, &new_player);
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let job = match jobs.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        Some(job) => job,
+                        None => break,
+                    };
+                    // o isn't created when x can't be, since that game is already over
+                    let (winner, forfeit) = match new_player(job.x, job.seed) {
+                        Err(error) => (winner_against(Piece::X), Some((Piece::X, ForfeitReason::NotStarted(error)))),
+                        Ok(x) => match new_player(job.o, job.seed.wrapping_add(1)) {
+                            Err(error) => (winner_against(Piece::O), Some((Piece::O, ForfeitReason::NotStarted(error)))),
+                            Ok(o) => play_game(opening(game, settings.opening_plies, job.opening), x, o),
+                        },
+                    };
+                    if sender.send((job.x, job.o, winner, forfeit)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        // Otherwise the loop below would wait forever for this copy of the sender
+        drop(sender);
+
+        for (x, o, winner, forfeit) in receiver {
+            // Games that were already being played when the test was decided don't count
+            if stop.load(Ordering::Relaxed) {
+                continue;
+            }
+            let score = match winner {
+                Winner::X => Score {wins: 1, ..Score::default()},
+                Winner::O => Score {losses: 1, ..Score::default()},
+                Winner::Tie => Score {draws: 1, ..Score::default()},
+            };
+            results.record((x, o), score);
+            if let Some((piece, reason)) = forfeit {
+                let (player, opponent) = if piece == Piece::X { (x, o) } else { (o, x) };
+                results.forfeits.push(Forfeited {player, opponent, piece, reason});
+            }
+            if let Some((sprt, result)) = &mut results.sprt {
+                *result = sprt.result(&results.scores[0][1]);
+                if *result != SprtResult::Undecided {
+                    stop.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    });
+    results
+}
+
+// Plays `plies` random moves, leaving out moves that would end the game
+fn opening(game: &Game, plies: usize, seed: u64) -> Game {
+    let mut rng = Rng::new(seed);
+    let mut game = game.clone();
+    for _ in 0..plies {
+        let moves: Vec<(usize, usize)> = game.legal_moves().into_iter().filter(|&(row, col)| {
+            let mut next = game.clone();
+            next.make_move(row, col).is_ok() && !next.is_finished()
+        }).collect();
+        if moves.is_empty() {
+            break;
+        }
+        let (row, col) = moves[rng.below(moves.len())];
+        game.make_move(row, col).expect("legal moves should always be valid");
+    }
+    game
+}
+
+// Plays a game between two bots with the same game loop as `program::run`, and returns the winner.
+// A bot that makes an invalid move or stops answering loses the game, and then the piece it was
+// playing and the reason are returned too.
+fn play_game(mut game: Game, x: Box<dyn Player>, o: Box<dyn Player>) -> (Winner, Option<(Piece, ForfeitReason)>) {
+    // Nothing is ever read, and what the game loop and the players write is thrown away
+    let (mut input, mut output) = (io::empty(), io::sink());
+    let mut console = Console::new(&mut input, &mut output);
+    // Reading nothing and writing to a sink can't fail, so an error came from one of the players.
+    // It stops the game just like input being closed.
+    let outcome = run_console(&mut game, &mut [x, o], &mut console).unwrap_or(Outcome::InputClosed);
+    let (piece, reason) = match outcome {
+        Outcome::Finished(winner) => return (winner, None),
+        Outcome::Forfeit(Forfeit {piece, error}) => (piece, ForfeitReason::InvalidMove(error)),
+        // The game stops on the turn of the player that stopped answering
+        Outcome::InputClosed => (game.current_piece(), ForfeitReason::Stopped),
+    };
+    (winner_against(piece), Some((piece, reason)))
+}
+
+// The winner of a game that `piece` forfeited
+fn winner_against(piece: Piece) -> Winner {
+    match piece {
+        Piece::X => Winner::O,
+        Piece::O => Winner::X,
+    }
+}
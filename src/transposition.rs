@@ -0,0 +1,119 @@
+//
+// file: transposition.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module contains a transposition table, which is a cache that a search can use to remember
+// what it found out about positions it has already searched. The same position can usually be
+// reached by playing the same moves in a different order (a "transposition"), so without the table
+// a search would do the same work over and over again.
+//
+// The table has a fixed number of slots, so it never uses more memory than it was created with.
+// A position is stored in the slot picked by its hash key (see `Game::hash_key`), and when two
+// positions want the same slot, one of them has to go. Each slot has room for two entries:
+//
+// - The first entry keeps whichever position was searched the deepest, since that result took the
+//   most work to find and is useful for the longest.
+// - The second entry always takes the newest position, so that recent results are kept even when
+//   the first entry is holding on to an old deep one.
+//
+// For more information, see: https://www.chessprogramming.org/Transposition_Table
+
+// Something a search remembers about one position. `value` can be anything the search wants, e.g.
+// a score and whether it is exact or only a bound.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry<T> {
+    /// The full hash key of the position, which tells apart the positions that share a slot
+    pub key: u64,
+
+    /// How many moves ahead the search looked from this position
+    pub depth: usize,
+
+    pub value: T,
+}
+
+#[derive(Debug, Clone)]
+pub struct TranspositionTable<T> {
+    // Each slot has the depth-preferred entry first and the always-replaced entry second
+    slots: Vec<[Option<Entry<T>>; 2]>,
+    // The number of entries that are filled in
+    len: usize,
+}
+
+impl<T> TranspositionTable<T> {
+    // Creates an empty table with room for `slots` slots of two entries each. There is always at
+    // least one slot.
+    pub fn new(slots: usize) -> Self {
+        // `resize_with` is used instead of `vec!` because `vec!` needs to clone the empty slot, and
+        // T doesn't have to implement Clone
+        let mut table = Vec::new();
+        table.resize_with(slots.max(1), || [None, None]);
+        Self {slots: table, len: 0}
+    }
+
+    // The number of entries that can be stored at once
+    pub fn capacity(&self) -> usize {
+        self.slots.len() * 2
+    }
+
+    // The number of entries that are stored right now
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Removes every entry
+    pub fn clear(&mut self) {
+        for slot in &mut self.slots {
+            *slot = [None, None];
+        }
+        self.len = 0;
+    }
+
+    // Returns what was stored for the position with this key, or None if there is nothing stored
+    // for it (or if it has been replaced since)
+    pub fn get(&self, key: u64) -> Option<&Entry<T>> {
+        self.slots[self.index(key)].iter().flatten().find(|entry| entry.key == key)
+    }
+
+    // Stores a value for the position with this key. A value that is already stored for the same
+    // position is always replaced.
+    pub fn insert(&mut self, key: u64, depth: usize, value: T) {
+        let index = self.index(key);
+        let slot = &mut self.slots[index];
+        let before = slot.iter().flatten().count();
+        let [deep, recent] = slot;
+        let entry = Entry {key, depth, value};
+
+        // The same position is updated wherever it already is
+        if deep.as_ref().is_some_and(|old| old.key == key) {
+            *deep = Some(entry);
+        }
+        else if recent.as_ref().is_some_and(|old| old.key == key) {
+            *recent = Some(entry);
+        }
+        // A deeper search pushes the old deep entry down into the recent entry, where it gets
+        // another chance before it is thrown away
+        else if deep.as_ref().is_none_or(|old| depth >= old.depth) {
+            if let Some(old) = deep.replace(entry) {
+                *recent = Some(old);
+            }
+        }
+        else {
+            *recent = Some(entry);
+        }
+
+        // The number of entries only changes when an empty entry is filled in
+        self.len += slot.iter().flatten().count() - before;
+    }
+
+    // Picks the slot for a key. Zobrist keys are already random, so the remainder is as good as
+    // any other way of spreading them out.
+    fn index(&self, key: u64) -> usize {
+        (key % self.slots.len() as u64) as usize
+    }
+}
@@ -0,0 +1,341 @@
+//
+// file: tui.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module is only compiled with the `tui` feature. It plays a game full screen in the terminal
+// instead of printing a new board after every move. The board is drawn in place, people pick their
+// move by moving a cursor around the board, and a panel next to the board shows the moves played so
+// far and whose turn it is:
+//
+//        A B C      Moves
+//      1 x ▢ ▢      1. x 1A
+//      2 ▢ o ▢      2. o 2B
+//      3 ▢ ▢ ▢
+//                   x to move
+//
+// The terminal is controlled with the crossterm crate, which works on Linux, macOS and Windows.
+// For more information, see: https://docs.rs/crossterm
+
+use std::io::{self, Write};
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, ResetColor, SetAttribute};
+use crossterm::{cursor, execute, queue, terminal};
+
+use crate::console::Console;
+use crate::player::{Action, Player};
+use crate::theme::Theme;
+use crate::{format_move, hint, take_turn, Forfeit, Game, Outcome, Turn, Winner};
+
+// How many of the most recent moves are shown in the panel next to the board
+const HISTORY_LINES: usize = 10;
+
+// The number of columns the board takes up on the screen for each column of tiles
+const TILE_WIDTH: u16 = 2;
+
+// The things that a person can do with the keyboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Place,
+    Undo,
+    Redo,
+    Hint,
+    Quit,
+}
+
+// Everything on the screen that isn't part of the game itself
+struct Screen {
+    // The tile that the cursor is on as (row, col)
+    cursor: (usize, usize),
+    // A message shown under the status line, e.g. when a move couldn't be made
+    message: String,
+}
+
+// Plays a game full screen in the terminal. People choose their moves with the arrow keys (or h, j,
+// k and l) and place a piece with Enter or the space bar. u undoes a move, r redoes it, ? moves the
+// cursor to the best move and q quits.
+// Like `program::play`, any type of Player can be used for `x` and `o`. Once the game is over the
+// final board is printed to the terminal as usual, so the result is still there after the program
+// exits.
+pub fn play(game: Game, x: Box<dyn Player>, o: Box<dyn Player>) -> io::Result<Outcome> {
+    play_with_theme(game, x, o, Theme::detect())
+}
+
+// The same as `play`, but the board is drawn with the given theme instead of the one picked by
+// `Theme::detect`
+pub fn play_with_theme(mut game: Game, x: Box<dyn Player>, o: Box<dyn Player>, theme: Theme) -> io::Result<Outcome> {
+    let mut stdout = io::stdout();
+
+    let mut players = [x, o];
+    let mut screen = Screen {cursor: start_cursor(&game), message: String::new()};
+    let full_screen = FullScreen::enter(&mut stdout)?;
+    let outcome = run(&mut game, &mut players, &theme, &mut screen, &mut stdout);
+
+    // The terminal has to be put back even if something went wrong while playing, so we wait until
+    // now to look at the outcome
+    drop(full_screen);
+    let outcome = outcome?;
+
+    // Leave the final board behind in the normal terminal
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut console = Console::with_theme(&mut input, &mut stdout, theme);
+    console.print_game(&game)?;
+    match &outcome {
+        Outcome::Finished(winner) => writeln!(console, "{}", result_message(*winner))?,
+        Outcome::Forfeit(Forfeit {piece, error}) => writeln!(
+            console,
+            "{} made an invalid move and forfeits! {}.",
+            players[piece.index()].name(),
+            error,
+        )?,
+        Outcome::InputClosed => {
+            // A bot that stops, like an engine that crashed, leaves a message saying why
+            if !screen.message.is_empty() {
+                writeln!(console, "{}", screen.message)?;
+            }
+            writeln!(console, "The game was stopped before it was finished.")?
+        },
+    }
+    Ok(outcome)
+}
+
+// Raw mode sends us every key as soon as it is pressed instead of waiting for Enter. The alternate
+// screen is a separate screen that goes away again afterwards, leaving the user's terminal the way
+// it was. Both are turned on when a FullScreen is created and off again when it is dropped. `Drop`
+// also runs when a panic unwinds the stack, so the terminal is put back even if a player panics.
+struct FullScreen;
+
+impl FullScreen {
+    fn enter(out: &mut impl Write) -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        // From here on, dropping the FullScreen turns raw mode off again
+        let full_screen = FullScreen;
+        execute!(out, terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(full_screen)
+    }
+}
+
+impl Drop for FullScreen {
+    fn drop(&mut self) {
+        // Drop can't return an error, and there is nothing more we could do about one anyway
+        let _ = execute!(io::stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+// This is the game loop. It works like `program::run`, except that people are asked for keys
+// instead of lines of text. What the keys (and the bots' actions) do to the game is worked out by
+// `take_turn`, just like on the console.
+fn run(
+    game: &mut Game,
+    players: &mut [Box<dyn Player>; 2],
+    theme: &Theme,
+    screen: &mut Screen,
+    out: &mut impl Write,
+) -> io::Result<Outcome> {
+    loop {
+        draw(out, game, players, theme, screen)?;
+
+        // The board stays on the screen until a key is pressed so that the user can see how the game
+        // ended
+        if let Some(winner) = game.winner() {
+            read_key()?;
+            return Ok(Outcome::Finished(winner));
+        }
+
+        let player = &mut players[game.current_piece().index()];
+        let action = if player.is_interactive() {
+            let key = match read_key()? {
+                Some(key) => key,
+                None => continue,
+            };
+            screen.message.clear();
+            match key {
+                Key::Quit => None,
+                // Moving the cursor doesn't change the game, so there is no turn to take
+                Key::Up | Key::Down | Key::Left | Key::Right => {
+                    screen.cursor = move_cursor(game, screen.cursor, key);
+                    continue;
+                },
+                Key::Place => Some(Action::Move(screen.cursor.0, screen.cursor.1)),
+                Key::Undo => Some(Action::Undo),
+                Key::Redo => Some(Action::Redo),
+                Key::Hint => Some(Action::Hint),
+            }
+        }
+        else {
+            // Players that aren't people are asked for an action just like on the console. Nothing
+            // is read from their console, and anything they write to it (like an engine saying that
+            // it stopped working) becomes the message.
+            let mut output = Vec::new();
+            let action = player.choose_action(game, &mut Console::new(&mut io::empty(), &mut output))?;
+            screen.message = String::from_utf8_lossy(&output).trim().to_string();
+            action
+        };
+
+        match take_turn(game, players, action) {
+            Turn::Played => {},
+            Turn::Retry(message) => screen.message = message,
+            Turn::Over(outcome) => return Ok(outcome),
+            // The cursor jumps to the best move so that it can be played straight away
+            Turn::Other(Action::Hint) => {
+                if let Some(&((row, col), evaluation)) = hint(game, players).first() {
+                    screen.message = format!("Hint: {} {}", format_move(row, col), evaluation);
+                    screen.cursor = (row, col);
+                }
+            },
+            // There is no way to type in a file name here, so a game can't be saved
+            Turn::Other(_) => {},
+        }
+
+        // Placing a piece or undoing a move changes where the next piece in a column would land
+        screen.cursor = fit_cursor(game, screen.cursor);
+    }
+}
+
+// The cursor starts in the middle of the board, which is usually a good first move
+fn start_cursor(game: &Game) -> (usize, usize) {
+    fit_cursor(game, (game.rows() / 2, game.cols() / 2))
+}
+
+// Returns where the cursor ends up after pressing an arrow key. The cursor stops at the edges of
+// the board.
+fn move_cursor(game: &Game, (row, col): (usize, usize), key: Key) -> (usize, usize) {
+    let cursor = match key {
+        Key::Up => (row.saturating_sub(1), col),
+        Key::Down => ((row + 1).min(game.rows() - 1), col),
+        Key::Left => (row, col.saturating_sub(1)),
+        Key::Right => (row, (col + 1).min(game.cols() - 1)),
+        _ => (row, col),
+    };
+    fit_cursor(game, cursor)
+}
+
+// With gravity, only the column can be chosen, so the cursor is moved to the tile in its column
+// that the next piece would land on
+fn fit_cursor(game: &Game, (row, col): (usize, usize)) -> (usize, usize) {
+    if game.has_gravity() {
+        // A full column has no landing row, so the cursor stays at the top of it
+        (game.landing_row(col).unwrap_or(0), col)
+    }
+    else {
+        (row, col)
+    }
+}
+
+// Waits for the next key press and returns what it means, or None for keys that don't do anything
+fn read_key() -> io::Result<Option<Key>> {
+    // Besides keys, the terminal also tells us about things like the window being resized. Those
+    // are ignored, and so are keys being released.
+    let (code, modifiers) = match event::read()? {
+        Event::Key(KeyEvent {code, modifiers, kind: KeyEventKind::Press, ..}) => (code, modifiers),
+        _ => return Ok(None),
+    };
+
+    // In raw mode, Ctrl-C is just another key, so we have to handle it ourselves
+    if modifiers.contains(KeyModifiers::CONTROL) && code == KeyCode::Char('c') {
+        return Ok(Some(Key::Quit));
+    }
+
+    Ok(match code {
+        KeyCode::Up | KeyCode::Char('k') => Some(Key::Up),
+        KeyCode::Down | KeyCode::Char('j') => Some(Key::Down),
+        KeyCode::Left | KeyCode::Char('h') => Some(Key::Left),
+        KeyCode::Right | KeyCode::Char('l') => Some(Key::Right),
+        KeyCode::Enter | KeyCode::Char(' ') => Some(Key::Place),
+        KeyCode::Char('u') => Some(Key::Undo),
+        KeyCode::Char('r') => Some(Key::Redo),
+        KeyCode::Char('?') => Some(Key::Hint),
+        KeyCode::Char('q') | KeyCode::Esc => Some(Key::Quit),
+        _ => None,
+    })
+}
+
+// Draws the whole screen. `queue!` only collects the commands, and they are sent to the terminal
+// all at once when we flush at the end. That stops the screen from flickering.
+fn draw(out: &mut impl Write, game: &Game, players: &[Box<dyn Player>; 2], theme: &Theme, screen: &Screen) -> io::Result<()> {
+    queue!(out, terminal::Clear(terminal::ClearType::All))?;
+
+    // The width of the row numbers, just like in `Console::print_tiles`
+    let width = game.rows().to_string().len() + 1;
+    let last_move = game.history().last().copied();
+    // The tiles of any winning line are shown in bold
+    let winning = game.winning_line().unwrap_or_default();
+
+    // The column letters go along the top
+    queue!(out, cursor::MoveTo(0, 0), Print(format!("{:width$}", "", width = width)))?;
+    for col in 0..game.cols() {
+        queue!(out, Print(format!(" {}", (b'A' + col as u8) as char)))?;
+    }
+
+    for (row, tiles) in game.tiles().iter().enumerate() {
+        queue!(out, cursor::MoveTo(0, row as u16 + 1), Print(format!("{:>width$} ", row + 1, width = width)))?;
+        for (col, &tile) in tiles.iter().enumerate() {
+            // The theme picks the character and color, just like on the console. Its color is
+            // an ANSI code, so it is printed as is instead of going through crossterm.
+            let is_winning = winning.contains(&(row, col));
+            if let Some(color) = theme.tile_color(tile) {
+                queue!(out, Print(format!("\x1b[{}m", color)))?;
+            }
+            // Reverse video swaps the text and background colors, which makes the cursor easy to
+            // spot. The last move is underlined.
+            if is_winning {
+                queue!(out, SetAttribute(Attribute::Bold))?;
+            }
+            if last_move == Some((row, col)) {
+                queue!(out, SetAttribute(Attribute::Underlined))?;
+            }
+            if screen.cursor == (row, col) && !game.is_finished() {
+                queue!(out, SetAttribute(Attribute::Reverse))?;
+            }
+            queue!(out, Print(theme.tile_symbol(tile, is_winning)), SetAttribute(Attribute::Reset), ResetColor, Print(" "))?;
+        }
+    }
+
+    // The panel goes to the right of the board
+    let panel = (width as u16 + 1) + TILE_WIDTH * game.cols() as u16 + 4;
+    queue!(out, cursor::MoveTo(panel, 0), Print("Moves"))?;
+    let history = game.history();
+    let first = history.len().saturating_sub(HISTORY_LINES);
+    for (line, (i, &(row, col))) in history.iter().enumerate().skip(first).enumerate() {
+        // X always moves first, so every even move was made by X
+        let piece = if i % 2 == 0 { "x" } else { "o" };
+        queue!(out, cursor::MoveTo(panel, line as u16 + 1), Print(format!("{}. {} {}", i + 1, piece, format_move(row, col))))?;
+    }
+
+    // The status goes below the board and the panel
+    let bottom = (game.rows().max(HISTORY_LINES) + 2) as u16;
+    let status = match game.winner() {
+        Some(winner) => format!("{} Press any key to exit.", result_message(winner)),
+        None => {
+            let piece = game.current_piece();
+            format!("{} to move ({})", piece.name(), players[piece.index()].name())
+        },
+    };
+    queue!(
+        out,
+        cursor::MoveTo(0, bottom),
+        Print(status),
+        cursor::MoveTo(0, bottom + 1),
+        Print(&screen.message),
+        cursor::MoveTo(0, bottom + 3),
+        Print("arrows/hjkl: move   enter/space: place   u: undo   r: redo   ?: hint   q: quit"),
+    )?;
+    out.flush()
+}
+
+// The message shown at the end of the game
+fn result_message(winner: Winner) -> &'static str {
+    match winner {
+        Winner::X => "x wins!",
+        Winner::O => "o wins!",
+        Winner::Tie => "Tie!",
+    }
+}
@@ -0,0 +1,51 @@
+//
+// file: zobrist.rs
+// author: Michael Brockus
+// gmail: <michaelbrockus@gmail.com>
+//
+
+// This module gives every position a 64-bit number called its Zobrist hash. Every tile has a
+// random number for each piece, and the hash of a position is all of the numbers for the pieces on
+// the board combined with XOR, plus one more number when it is O's turn. XOR undoes itself, so
+// making or taking back a move only needs to XOR in the number for that one tile instead of looking
+// at the whole board again. `Game` keeps its hash up to date this way, see `Game::hash_key`.
+// For more information, see: https://en.wikipedia.org/wiki/Zobrist_hashing
+//
+// Two different positions can end up with the same hash, but with 64 bits that is so unlikely that
+// searches usually just accept the risk.
+
+use crate::rng::Rng;
+use crate::{Piece, Tiles, MAX_COLS};
+
+// The random numbers don't have to be stored in a table. Each one is made from the tile and piece
+// by the same SplitMix64 generator that the random players use, which always gives the same number
+// for the same seed. This seed is just an arbitrary constant.
+const SEED: u64 = 0x5EED_C0DE_2B1D_F00D;
+
+// Returns the random number for `piece` on the tile at (row, col)
+pub fn tile_key(row: usize, col: usize, piece: Piece) -> u64 {
+    // Every tile and piece gets its own seed. Boards are never wider than MAX_COLS, so no two tiles
+    // share an index.
+    let index = (row * MAX_COLS + col) * 2 + piece.index();
+    Rng::new(SEED ^ index as u64).next_u64()
+}
+
+// The random number that is added to the hash when it is O's turn, so that the same pieces with a
+// different piece to move get a different hash
+pub fn side_key() -> u64 {
+    Rng::new(!SEED).next_u64()
+}
+
+// Works out the hash of a position from scratch. `Game` only needs this when its board is filled in
+// all at once, e.g. when reading a position string.
+pub fn hash(tiles: &Tiles, current_piece: Piece) -> u64 {
+    let mut hash = if current_piece == Piece::O { side_key() } else { 0 };
+    for (row, tiles) in tiles.iter().enumerate() {
+        for (col, tile) in tiles.iter().enumerate() {
+            if let Some(piece) = *tile {
+                hash ^= tile_key(row, col, piece);
+            }
+        }
+    }
+    hash
+}
@@ -95,4 +95,1321 @@ mod tests {
         game.make_move(1, 1).unwrap();
         assert_eq!(game.winner().unwrap(), program::Winner::Tie);
     }
+
+    #[test]
+    fn connect_four_vertical_x_wins() {
+        let mut game = program::Game::connect_four();
+        // Pieces fall to the bottom row (row 5) and stack upwards from there
+        assert_eq!(game.drop_piece(0).unwrap(), 5);
+        assert_eq!(game.drop_piece(1).unwrap(), 5);
+        assert_eq!(game.drop_piece(0).unwrap(), 4);
+        game.drop_piece(1).unwrap();
+        game.drop_piece(0).unwrap();
+        game.drop_piece(1).unwrap();
+        assert!(game.winner().is_none());
+        game.drop_piece(0).unwrap();
+        assert_eq!(game.winner().unwrap(), program::Winner::X);
+    }
+
+    #[test]
+    fn connect_four_diagonal_x_wins() {
+        let mut game = program::Game::connect_four();
+        // Builds a staircase so that X gets the diagonal 6A, 5B, 4C, 3D
+        for &col in &[0, 1, 1, 2, 3, 2, 2, 3, 4, 3] {
+            game.drop_piece(col).unwrap();
+        }
+        assert!(game.winner().is_none());
+        assert_eq!(game.drop_piece(3).unwrap(), 2);
+        assert_eq!(game.winner().unwrap(), program::Winner::X);
+    }
+
+    #[test]
+    fn connect_four_column_full() {
+        let mut game = program::Game::connect_four();
+        // Alternating pieces in one column never makes a line, so we can fill it completely
+        for _ in 0..6 {
+            game.drop_piece(3).unwrap();
+        }
+        assert_eq!(game.landing_row(3), None);
+        match game.drop_piece(3) {
+            Err(program::MoveError::ColumnFull {col: 3}) => {},
+            other => panic!("expected a full column, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn connect_four_piece_cannot_float() {
+        let mut game = program::Game::connect_four();
+        // The bottom row is 5, so row 2 would leave the piece floating in the air
+        assert!(game.make_move(2, 3).is_err());
+        game.make_move(5, 3).unwrap();
+        assert_eq!(game.current_piece(), program::Piece::O);
+    }
+
+    #[test]
+    fn gomoku_five_in_a_row() {
+        let mut game = program::Game::with_config(15, 15, 5).unwrap();
+        assert_eq!((game.rows(), game.cols(), game.win_length()), (15, 15, 5));
+        // X plays down the anti-diagonal in the bottom right corner while O plays along the top
+        for i in 0..4 {
+            game.make_move(10 + i, 14 - i).unwrap();
+            game.make_move(0, i).unwrap();
+        }
+        // Four in a row is not enough here
+        assert!(game.winner().is_none());
+        game.make_move(14, 10).unwrap();
+        assert_eq!(game.winner().unwrap(), program::Winner::X);
+    }
+
+    #[test]
+    fn four_by_four_row_o_wins() {
+        let mut game = program::Game::with_config(4, 4, 4).unwrap();
+        for col in 0..3 {
+            game.make_move(0, col).unwrap();
+            game.make_move(3, col).unwrap();
+        }
+        game.make_move(1, 3).unwrap();
+        assert!(game.winner().is_none());
+        game.make_move(3, 3).unwrap();
+        assert_eq!(game.winner().unwrap(), program::Winner::O);
+    }
+
+    #[test]
+    fn invalid_configs() {
+        use program::{ConfigError, Game};
+        assert_eq!(Game::with_config(0, 3, 3).unwrap_err(), ConfigError::EmptyBoard {rows: 0, cols: 3});
+        assert_eq!(Game::with_config(3, 27, 3).unwrap_err(), ConfigError::TooManyColumns {cols: 27});
+        // Huge boards are rejected before any memory is allocated for their tiles
+        assert_eq!(Game::with_gravity_config(100000000000, 26, 3).unwrap_err(),
+            ConfigError::TooManyRows {rows: 100000000000});
+        assert_eq!(Game::with_config(program::MAX_ROWS, 26, 3).unwrap().rows(), program::MAX_ROWS);
+        assert_eq!(Game::with_config(3, 4, 5).unwrap_err(),
+            ConfigError::InvalidWinLength {win_length: 5, rows: 3, cols: 4});
+        assert_eq!(Game::with_config(3, 3, 0).unwrap_err(),
+            ConfigError::InvalidWinLength {win_length: 0, rows: 3, cols: 3});
+    }
+
+    #[test]
+    fn gravity_config_matches_connect_four() {
+        let game = program::Game::with_gravity_config(6, 7, 4).unwrap();
+        let connect_four = program::Game::connect_four();
+        assert!(game.has_gravity());
+        assert_eq!(game.tiles(), connect_four.tiles());
+        assert_eq!(game.win_length(), connect_four.win_length());
+    }
+
+    #[test]
+    fn undo_winning_move() {
+        let mut game = program::Game::new();
+        game.make_move(0, 0).unwrap();
+        game.make_move(1, 0).unwrap();
+        game.make_move(0, 1).unwrap();
+        game.make_move(1, 1).unwrap();
+        game.make_move(0, 2).unwrap();
+        assert_eq!(game.winner().unwrap(), program::Winner::X);
+
+        // Taking back the winning move reopens the game with X to move again
+        assert_eq!(game.undo(), Some((0, 2)));
+        assert!(game.winner().is_none());
+        assert_eq!(game.current_piece(), program::Piece::X);
+        assert!(game.tiles()[0][2].is_none());
+        assert_eq!(game.history(), &[(0, 0), (1, 0), (0, 1), (1, 1)]);
+
+        // Redoing it brings the win back
+        assert_eq!(game.redo(), Some((0, 2)));
+        assert_eq!(game.winner().unwrap(), program::Winner::X);
+        assert_eq!(game.redo(), None);
+    }
+
+    #[test]
+    fn new_move_clears_redo() {
+        let mut game = program::Game::connect_four();
+        game.drop_piece(3).unwrap();
+        game.drop_piece(3).unwrap();
+        game.drop_piece(4).unwrap();
+        assert_eq!(game.undo(), Some((5, 4)));
+        assert_eq!(game.undo(), Some((4, 3)));
+        assert_eq!(game.current_piece(), program::Piece::O);
+        assert!(game.can_redo());
+
+        // Redo only one of the two undone moves, then play something else
+        assert_eq!(game.redo(), Some((4, 3)));
+        assert!(game.can_redo());
+        game.drop_piece(0).unwrap();
+        assert!(!game.can_redo());
+        assert_eq!(game.history(), &[(5, 3), (4, 3), (5, 0)]);
+
+        // Undo all the way back to the start
+        while game.undo().is_some() {}
+        assert!(!game.can_undo());
+        assert_eq!(game.tiles(), program::Game::connect_four().tiles());
+        assert_eq!(game.current_piece(), program::Piece::X);
+    }
+
+    // Plays every possible sequence of opponent moves against the computer and checks that the
+    // computer never loses
+    fn computer_never_loses(game: &mut program::Game, computer: program::Piece) {
+        if let Some(winner) = game.winner() {
+            let loser = match computer {
+                program::Piece::X => program::Winner::O,
+                program::Piece::O => program::Winner::X,
+            };
+            assert_ne!(winner, loser, "computer lost after {:?}", game.history());
+            return;
+        }
+
+        if game.current_piece() == computer {
+            let (row, col) = program::ai::best_move(game).unwrap();
+            game.make_move(row, col).unwrap();
+            computer_never_loses(game, computer);
+            game.undo();
+        }
+        else {
+            for (row, col) in game.legal_moves() {
+                game.make_move(row, col).unwrap();
+                computer_never_loses(game, computer);
+                game.undo();
+            }
+        }
+    }
+
+    #[test]
+    fn computer_never_loses_as_x() {
+        computer_never_loses(&mut program::Game::new(), program::Piece::X);
+    }
+
+    #[test]
+    fn computer_never_loses_as_o() {
+        computer_never_loses(&mut program::Game::new(), program::Piece::O);
+    }
+
+    #[test]
+    fn computer_blocks_and_wins() {
+        let mut game = program::Game::new();
+        // X threatens the top row, so O has to block at 1C
+        game.make_move(0, 0).unwrap();
+        game.make_move(1, 1).unwrap();
+        game.make_move(0, 1).unwrap();
+        assert_eq!(program::ai::best_move(&game), Some((0, 2)));
+
+        // After O blocks, X can't win the top row but O threatens the anti-diagonal
+        game.make_move(0, 2).unwrap();
+        game.make_move(2, 2).unwrap();
+        // O wins immediately on 3A
+        assert_eq!(program::ai::best_move(&game), Some((2, 0)));
+    }
+
+    #[test]
+    fn connect_four_depth_limited_takes_win() {
+        let mut game = program::Game::connect_four();
+        for &col in &[0, 6, 1, 6, 2, 5] {
+            game.drop_piece(col).unwrap();
+        }
+        assert_eq!(program::ai::best_move_with_depth(&game, 4), Some((5, 3)));
+    }
+
+    // Runs a game without any input and returns how it ended
+    fn run_bots(x: Box<dyn program::player::Player>, o: Box<dyn program::player::Player>) -> program::Outcome {
+        program::run(program::Game::new(), x, o, &b""[..], Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn random_players_never_beat_computer() {
+        use program::player::{ComputerPlayer, RandomPlayer};
+        use program::{Outcome, Winner};
+        for seed in 0..10 {
+            match run_bots(Box::new(RandomPlayer::with_seed(seed)), Box::new(ComputerPlayer::new())) {
+                Outcome::Finished(winner) => assert_ne!(winner, Winner::X),
+                other => panic!("unexpected outcome {:?}", other),
+            }
+            match run_bots(Box::new(ComputerPlayer::new()), Box::new(RandomPlayer::with_seed(seed))) {
+                Outcome::Finished(winner) => assert_ne!(winner, Winner::O),
+                other => panic!("unexpected outcome {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn difficulty_levels() {
+        use program::player::{ComputerPlayer, Difficulty, DIFFICULTY_NAMES};
+        use program::{Outcome, Winner};
+        for name in &DIFFICULTY_NAMES {
+            assert!(Difficulty::named(name).is_some());
+        }
+        assert_eq!(Difficulty::named("impossible"), None);
+
+        // The easier the level, the more often the perfect player beats it
+        let losses = |difficulty| (0..20).filter(|&seed| {
+            let x = ComputerPlayer::with_difficulty(difficulty, seed);
+            match run_bots(Box::new(x), Box::new(ComputerPlayer::new())) {
+                Outcome::Finished(winner) => winner == Winner::O,
+                other => panic!("unexpected outcome {:?}", other),
+            }
+        }).count();
+        assert!(losses(Difficulty::Easy) > losses(Difficulty::Hard));
+        assert_eq!(losses(Difficulty::Perfect), 0);
+    }
+
+    #[test]
+    fn custom_bot_forfeits_invalid_move() {
+        use program::player::{BotFn, RandomPlayer};
+        // This bot always tries the top left corner, which is only free on its first move
+        let corner = BotFn::new("Corner", |_: &program::Game| (0, 0));
+        let forfeit = match run_bots(Box::new(corner), Box::new(RandomPlayer::with_seed(7))) {
+            program::Outcome::Forfeit(forfeit) => forfeit,
+            other => panic!("expected a forfeit, got {:?}", other),
+        };
+        assert_eq!(forfeit.piece, program::Piece::X);
+        match forfeit.error {
+            program::MoveError::TileNotEmpty {row: 0, col: 0, ..} => {},
+            other => panic!("expected the corner to be taken, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn scripted_human_game() {
+        use program::player::HumanPlayer;
+        // The second line is not a valid move and 2B is played twice, so both should be
+        // reported and asked for again. o then takes back x's 2A so x gets to play 1B instead.
+        let input = "1A\n4D\n2B\n2B\n2A\nu\n1B\n3C\n1C\n";
+        let mut output = Vec::new();
+        let outcome = program::run(program::Game::new(), Box::new(HumanPlayer), Box::new(HumanPlayer),
+            input.as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        match outcome {
+            program::Outcome::Finished(program::Winner::X) => {},
+            other => panic!("expected x to win, got {:?}", other),
+        }
+        assert!(output.contains("Invalid move: '4D'. The rows are numbered from 1 to 3. Please try again."));
+        assert!(output.contains("The tile at position 2B already has piece o in it!"));
+        assert!(output.ends_with("   A B C\n 1 X X X\n 2 ▢ o ▢\n 3 ▢ ▢ o\n\nx wins!\n"));
+    }
+
+    #[test]
+    fn scripted_gravity_game() {
+        use program::player::HumanPlayer;
+        use program::{Game, Outcome, Winner};
+        // With gravity a column is enough, as a letter or a number, and the full form still works
+        let input = "D\n4\ne\n1\n6F\n1\nC\n";
+        let mut output = Vec::new();
+        let outcome = program::run(Game::connect_four(), Box::new(HumanPlayer), Box::new(HumanPlayer),
+            input.as_bytes(), &mut output).unwrap();
+        assert!(matches!(outcome, Outcome::Finished(Winner::X)));
+        assert!(String::from_utf8(output).unwrap().contains("Enter column (e.g. D or 4)"));
+
+        let game = Game::from_rules_string("2x3k3g").unwrap();
+        let mut output = Vec::new();
+        let outcome = program::run(game, Box::new(HumanPlayer), Box::new(HumanPlayer), "A\n1\na\n".as_bytes(), &mut output).unwrap();
+        assert!(matches!(outcome, Outcome::InputClosed));
+        assert!(String::from_utf8(output).unwrap().contains("Column A is already full. Please try again."));
+    }
+
+    #[test]
+    fn winning_line() {
+        use program::Game;
+        let mut game = Game::new();
+        assert_eq!(game.winning_line(), None);
+        // x's last move in the middle completes both diagonals at once
+        for &(row, col) in &[(0, 0), (0, 1), (0, 2), (1, 0), (2, 0), (1, 2), (2, 2), (2, 1), (1, 1)] {
+            game.make_move(row, col).unwrap();
+        }
+        assert_eq!(game.winning_line(), Some(vec![(0, 0), (0, 2), (1, 1), (2, 0), (2, 2)]));
+        assert_eq!(game.winning_lines(), vec![vec![(0, 0), (1, 1), (2, 2)], vec![(0, 2), (1, 1), (2, 0)]]);
+        game.undo();
+        assert_eq!(game.winning_line(), None);
+
+        let mut game = Game::connect_four();
+        for &col in &[0, 1, 0, 1, 0, 1, 0] {
+            game.drop_piece(col).unwrap();
+        }
+        assert_eq!(game.winning_line(), Some(vec![(2, 0), (3, 0), (4, 0), (5, 0)]));
+
+        let mut output = Vec::new();
+        program::console::Console::new(&mut "".as_bytes(), &mut output).print_game(&game).unwrap();
+        assert!(String::from_utf8(output).unwrap().starts_with("   A B C D E F G
+ 1 ▢ ▢"));
+    }
+
+    #[test]
+    fn board_themes() {
+        use program::console::Console;
+        use program::theme::{Theme, THEME_NAMES};
+        use program::{Game, Piece};
+        for name in &THEME_NAMES {
+            assert!(Theme::named(name).is_some());
+        }
+        assert_eq!(Theme::named("sparkly"), None);
+
+        let theme = Theme::color();
+        assert_eq!(theme.tile(Some(Piece::X), false), "\x1b[91mx\x1b[0m");
+        assert_eq!(theme.tile(Some(Piece::O), true), "\x1b[94;7mO\x1b[0m");
+        assert_eq!(theme.tile(None, false), "\u{25A2}");
+        assert_eq!(theme.without_colors(), Theme::unicode());
+        // The tui module draws the same characters and colors itself
+        assert_eq!(Theme::discs().tile_symbol(Some(Piece::O), true), '\u{25CE}');
+        assert_eq!(Theme::discs().tile_color(Some(Piece::O)), Some("93"));
+        assert_eq!(Theme::discs().tile_color(None), None);
+
+        let game = Game::from_position_string("xxx/oo1/3 o").unwrap();
+        let mut output = Vec::new();
+        Console::with_theme(&mut "".as_bytes(), &mut output, Theme::plain()).print_game(&game).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "   A B C\n 1 X X X\n 2 o o .\n 3 . . .\n\n");
+    }
+
+    #[test]
+    fn analyze_scores_every_move() {
+        use program::ai::{self, Evaluation};
+        use program::Game;
+        // Every first move on the 3x3 board draws with perfect play
+        let analysis = ai::analyze(&Game::new());
+        assert_eq!(analysis.len(), 9);
+        assert!(analysis.iter().all(|&(_, evaluation)| evaluation == Evaluation::Draw));
+
+        // o can win straight away at 2C and blocking x at 1C draws. Anything else lets x win on
+        // the next move.
+        let game = Game::from_position_string("xx1/oo1/x2 o").unwrap();
+        let analysis = ai::analyze(&game);
+        assert_eq!(analysis[0], ((1, 2), Evaluation::Win(1)));
+        assert_eq!(analysis[1], ((0, 2), Evaluation::Draw));
+        assert_eq!(analysis[2].1, Evaluation::Loss(2));
+        assert_eq!(analysis[0].1.to_string(), "wins in 1");
+
+        // Connect Four is too big to search to the end, so the depth is reported
+        let analysis = ai::analyze_with_depth(&Game::connect_four(), 2);
+        assert_eq!(analysis.len(), 7);
+        match analysis[0] {
+            ((5, 3), Evaluation::Heuristic {depth: 2, ..}) => {},
+            other => panic!("expected the middle column at depth 2, got {:?}", other),
+        }
+
+        // On the largest board, x's open lines add up to more than a win is worth. The heuristic
+        // is capped, so o's moves still aren't reported as losses. x fills the left half of the
+        // columns apart from a few gaps and o fills the right half, where the x pieces in rows 50
+        // and 100 break up o's lines. Nobody has 50 in a line, and o is to move.
+        let mut game = Game::with_config(program::MAX_ROWS, program::MAX_COLS, 50).unwrap();
+        let (mut xs, mut os) = (Vec::new(), Vec::new());
+        for col in 0..program::MAX_COLS {
+            for row in 0..program::MAX_ROWS {
+                let gap = row == 49 || row == 99;
+                if (col < 13 && !gap && row != 0 && row != 98) || (col >= 13 && gap) {
+                    xs.push((row, col));
+                }
+                else if col >= 13 {
+                    os.push((row, col));
+                }
+            }
+        }
+        os.pop();
+        for (i, &(row, col)) in xs.iter().enumerate() {
+            game.make_move(row, col).unwrap();
+            if let Some(&(row, col)) = os.get(i) {
+                game.make_move(row, col).unwrap();
+            }
+        }
+        assert!(!game.is_finished());
+        assert_eq!(game.current_piece(), program::Piece::O);
+        for (_, evaluation) in ai::analyze_with_depth(&game, 1) {
+            assert!(matches!(evaluation, Evaluation::Heuristic {..}), "{:?}", evaluation);
+        }
+    }
+
+    #[test]
+    fn hint_command() {
+        use program::player::HumanPlayer;
+        let game = program::Game::from_position_string("xx1/oo1/3 x").unwrap();
+        let mut output = Vec::new();
+        program::run(game, Box::new(HumanPlayer), Box::new(HumanPlayer), "h\n".as_bytes(), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Hint: 1C wins in 1, 2C draws, 3B loses in 2, 3A loses in 2, 3C loses in 2\n"));
+    }
+
+    #[test]
+    fn connect_four_solver_positions() {
+        use program::solver::{Position, Solver, SolverError};
+        // This position comes from the test set at http://blog.gamesolver.org
+        let position = Position::from_moves("2252576253462244111563365343671351441").unwrap();
+        let mut solver = Solver::with_table_size(1 << 16);
+        assert_eq!(solver.solve(&position), -1);
+        assert_eq!(solver.solve_weak(&position), -1);
+
+        // A game and a move string for the same position give the same position
+        let mut game = program::Game::connect_four();
+        for c in "4453".chars() {
+            game.drop_piece(c.to_digit(10).unwrap() as usize - 1).unwrap();
+        }
+        assert_eq!(Position::from_game(&game), Position::from_moves("4453"));
+
+        assert_eq!(Position::from_moves("448"), Err(SolverError::InvalidColumn {number: 3, found: '8'}));
+        assert_eq!(Position::from_moves("1111111"), Err(SolverError::ColumnFull {number: 7, col: 0}));
+        assert_eq!(Position::from_moves("1212121"), Err(SolverError::GameOver {number: 7}));
+        assert_eq!(Position::from_game(&program::Game::new()), Err(SolverError::NotConnectFour));
+    }
+
+    #[test]
+    fn connect_four_solver_matches_full_search() {
+        use program::ai;
+        use program::player::{Player, RandomPlayer};
+        use program::solver::{self, Position, Solver};
+        let mut solver = Solver::with_table_size(1 << 16);
+        let mut random = RandomPlayer::with_seed(4);
+        let mut checked = 0;
+        // Random games are played until there are only a few empty tiles left, and then the
+        // solver has to agree with the ai module searching to the end of the game
+        while checked < 10 {
+            let mut game = program::Game::connect_four();
+            while !game.is_finished() && game.history().len() < 33 {
+                let (row, col) = random.choose_move(&game).unwrap();
+                game.make_move(row, col).unwrap();
+            }
+            if game.is_finished() {
+                continue;
+            }
+            let position = Position::from_game(&game).unwrap();
+            let expected = ai::analyze_with_depth(&game, 42)[0].1;
+            assert_eq!(solver::evaluation(&position, solver.solve(&position)), expected,
+                "position {}", game.to_position_string());
+            checked += 1;
+        }
+    }
+
+    #[test]
+    fn mcts_blocks_and_wins() {
+        use program::mcts::{Budget, Mcts, Playout};
+        for &playout in &[Playout::Random, Playout::Heuristic] {
+            let mut mcts = Mcts::new(Budget::Iterations(2000), 1).with_playout(playout);
+            // x has to block o at 2C
+            let game = program::Game::from_position_string("x2/oo1/x2 x").unwrap();
+            assert_eq!(mcts.best_move(&game), Some((1, 2)));
+            // x can win straight away at 1C
+            let game = program::Game::from_position_string("xx1/oo1/3 x").unwrap();
+            assert_eq!(mcts.best_move(&game), Some((0, 2)));
+            assert_eq!(mcts.iterations(), 2000);
+        }
+    }
+
+    #[test]
+    fn mcts_is_repeatable_and_reuses_its_tree() {
+        use program::mcts::Budget;
+        use program::player::{MctsPlayer, Player, RandomPlayer};
+        // The same seeds give exactly the same game, even though each player keeps its tree from
+        // one move to the next
+        let play = || {
+            let mut game = program::Game::connect_four();
+            let mut players: [Box<dyn Player>; 2] =
+                [Box::new(MctsPlayer::new(Budget::Iterations(300), 3)), Box::new(RandomPlayer::with_seed(3))];
+            while !game.is_finished() {
+                let (row, col) = players[game.history().len() % 2].choose_move(&game).unwrap();
+                game.make_move(row, col).unwrap();
+            }
+            game
+        };
+        let game = play();
+        assert_eq!(game.history(), play().history());
+        assert_eq!(game.winner(), Some(program::Winner::X));
+    }
+
+    #[test]
+    fn zobrist_hash_keys() {
+        let mut game = program::Game::new();
+        let empty = game.hash_key();
+        for &(row, col) in &[(0, 0), (1, 1), (0, 2)] {
+            game.make_move(row, col).unwrap();
+        }
+        // The same moves in a different order reach the same position
+        let mut other = program::Game::new();
+        for &(row, col) in &[(0, 2), (1, 1), (0, 0)] {
+            other.make_move(row, col).unwrap();
+        }
+        assert_eq!(game.hash_key(), other.hash_key());
+        // A position read from a string has the same key as the one that was played
+        let position = program::Game::from_position_string(&game.to_position_string()).unwrap();
+        assert_eq!(position.hash_key(), game.hash_key());
+
+        // Moving a piece or taking back a move changes the key, and undo and redo restore it
+        let moved = program::Game::from_position_string("xx1/1o1/3 o").unwrap();
+        assert_ne!(moved.hash_key(), game.hash_key());
+        let before = game.hash_key();
+        game.undo();
+        assert_ne!(game.hash_key(), before);
+        game.redo();
+        assert_eq!(game.hash_key(), before);
+        while game.undo().is_some() {}
+        assert_eq!(game.hash_key(), empty);
+    }
+
+    #[test]
+    fn transposition_table_replacement() {
+        use program::transposition::TranspositionTable;
+        // With one slot every key competes for the same two entries
+        let mut table = TranspositionTable::new(1);
+        assert_eq!(table.capacity(), 2);
+        table.insert(1, 5, "deep");
+        table.insert(2, 1, "shallow");
+        assert_eq!(table.len(), 2);
+        // The deep entry stays and the newest shallow entry replaces the older one
+        table.insert(3, 2, "newer");
+        assert_eq!(table.get(1).map(|entry| entry.value), Some("deep"));
+        assert_eq!(table.get(2), None);
+        assert_eq!(table.get(3).map(|entry| entry.depth), Some(2));
+        // A deeper search takes over the deep entry and the old one moves down
+        table.insert(4, 9, "deeper");
+        assert_eq!(table.get(4).map(|entry| entry.value), Some("deeper"));
+        assert_eq!(table.get(1).map(|entry| entry.value), Some("deep"));
+        assert_eq!(table.get(3), None);
+        // The same key is always updated in place
+        table.insert(1, 0, "updated");
+        assert_eq!(table.get(1).map(|entry| entry.value), Some("updated"));
+        assert_eq!(table.len(), 2);
+        table.clear();
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn symmetric_positions() {
+        use program::symmetry::{self, Symmetry};
+        use program::Game;
+        // x in the corner and o in the middle of an edge
+        let game = Game::from_position_string("x2/2o/3 x").unwrap();
+        let variants = symmetry::variants(&game);
+        assert_eq!(variants.len(), 8);
+        let (canonical, _) = symmetry::canonical(&game);
+        for (symmetry, variant) in &variants {
+            // Every variant has the same canonical form, and moves can be mapped back and forth
+            assert_eq!(symmetry::canonical(variant).0.hash_key(), canonical.hash_key());
+            let mv = symmetry.apply((0, 1), 3, 3);
+            assert_eq!(symmetry.inverse().apply(mv, 3, 3), (0, 1));
+        }
+        assert_eq!(Symmetry::Rotate90.transform(&game).unwrap().to_position_string(), "2x/3/1o1 x 3x3k3");
+
+        // Connect Four can only be mirrored, and the moves in the history are mirrored too
+        let mut connect_four = Game::connect_four();
+        connect_four.drop_piece(0).unwrap();
+        assert_eq!(symmetry::symmetries(&connect_four), vec![Symmetry::Identity, Symmetry::MirrorLeftRight]);
+        assert!(Symmetry::Rotate180.transform(&connect_four).is_none());
+        let (mirrored, symmetry) = symmetry::canonical(&connect_four);
+        assert_eq!(symmetry, Symmetry::MirrorLeftRight);
+        assert_eq!(mirrored.history(), &[(5, 6)]);
+    }
+
+    #[test]
+    fn tic_tac_toe_positions_up_to_symmetry() {
+        use std::collections::HashSet;
+        // Visits every position that can come up in a game. There are 5,478 of them, and 765 once
+        // the symmetric ones are counted as the same position.
+        fn visit(game: &mut program::Game, seen: &mut HashSet<u64>, canonical: &mut HashSet<u64>) {
+            if !seen.insert(game.hash_key()) {
+                return;
+            }
+            canonical.insert(program::symmetry::canonical(game).0.hash_key());
+            for (row, col) in game.legal_moves() {
+                game.make_move(row, col).unwrap();
+                visit(game, seen, canonical);
+                game.undo();
+            }
+        }
+        let (mut seen, mut canonical) = (HashSet::new(), HashSet::new());
+        visit(&mut program::Game::new(), &mut seen, &mut canonical);
+        assert_eq!(seen.len(), 5478);
+        assert_eq!(canonical.len(), 765);
+    }
+
+    #[test]
+    fn game_tree_statistics() {
+        use program::stats::{self, Tally};
+        let stats = stats::enumerate(&program::Game::new(), false);
+        assert_eq!(stats.positions(), 5478);
+        assert_eq!(stats.positions_by_depth, vec![1, 9, 72, 252, 756, 1260, 1520, 1140, 390, 78]);
+        assert_eq!(stats.finished_positions, Tally {x_wins: 626, o_wins: 316, ties: 16});
+        assert_eq!(stats.games, Tally {x_wins: 131184, o_wins: 77904, ties: 46080});
+        assert_eq!(stats.games.total(), 255168);
+
+        // Folding the symmetric positions together doesn't change the number of games
+        let symmetric = stats::enumerate(&program::Game::new(), true);
+        assert_eq!(symmetric.positions(), 765);
+        assert_eq!(symmetric.finished_positions.total(), 138);
+        assert_eq!(symmetric.games, stats.games);
+
+        let (code, output) = run_binary(&["stats", "--size", "2x2", "--win", "2"]);
+        assert_eq!(code, 0);
+        assert!(output.contains("positions: 29\nfinished positions: 12 (x wins 12, o wins 0, ties 0)\n"));
+        assert_eq!(run_binary(&["stats", "--ai", "x"]).0, 2);
+    }
+
+    #[test]
+    fn tablebase_matches_full_search() {
+        use program::tablebase::Tablebase;
+        use std::collections::HashSet;
+        let tablebase = Tablebase::generate(&program::Game::new()).unwrap();
+        assert_eq!(tablebase.len(), 765);
+        assert_eq!(tablebase.probe(&program::Game::new()), Some(program::ai::Evaluation::Draw));
+
+        // Every move in every position gets the same result as searching to the end of the game
+        fn visit(game: &mut program::Game, tablebase: &Tablebase, seen: &mut HashSet<u64>) {
+            if game.is_finished() || !seen.insert(game.hash_key()) {
+                return;
+            }
+            assert_eq!(tablebase.analyze(game), Some(program::ai::analyze_with_depth(game, 9)),
+                "position {}", game.to_position_string());
+            for (row, col) in game.legal_moves() {
+                game.make_move(row, col).unwrap();
+                visit(game, tablebase, seen);
+                game.undo();
+            }
+        }
+        visit(&mut program::Game::new(), &tablebase, &mut HashSet::new());
+    }
+
+    #[test]
+    fn tablebase_files() {
+        use program::player::{HumanPlayer, RandomPlayer, TablebasePlayer};
+        use program::tablebase::{Tablebase, TablebaseError};
+        use std::sync::Arc;
+        let tablebase = Tablebase::generate(&program::Game::with_config(3, 3, 2).unwrap()).unwrap();
+        let bytes = tablebase.to_bytes();
+        assert_eq!(Tablebase::from_bytes(&bytes).unwrap(), tablebase);
+        match Tablebase::from_bytes(&bytes[..bytes.len() - 1]) {
+            Err(TablebaseError::BadFormat) => {},
+            other => panic!("expected a bad format, got {:?}", other),
+        }
+        // A file that claims to hold billions of results but doesn't
+        match Tablebase::from_bytes(b"CRTB\x01\x053x3k3\xFF\xFF\xFF\xFF") {
+            Err(TablebaseError::BadFormat) => {},
+            other => panic!("expected a bad format, got {:?}", other),
+        }
+        // Rules for a board far too big for a table are turned down before a board is made
+        for bytes in [&b"CRTB\x01\x10100000000000x1k1\x00\x00\x00\x00"[..], b"CRTB\x01\x0712x12k3\x00\x00\x00\x00"] {
+            match Tablebase::from_bytes(bytes) {
+                Err(TablebaseError::BadFormat) => {},
+                other => panic!("expected a bad format, got {:?}", other),
+            }
+        }
+        // A table for other rules doesn't know any Tic-Tac-Toe positions
+        assert_eq!(tablebase.probe(&program::Game::new()), None);
+
+        let path = std::env::temp_dir().join("connect_rusty_tablebase.tb");
+        Tablebase::generate(&program::Game::new()).unwrap().save(&path).unwrap();
+        let tablebase = Arc::new(Tablebase::load(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        for seed in 0..10 {
+            let player = TablebasePlayer::new(Arc::clone(&tablebase));
+            match run_bots(Box::new(RandomPlayer::with_seed(seed)), Box::new(player)) {
+                program::Outcome::Finished(winner) => assert_ne!(winner, program::Winner::X),
+                other => panic!("unexpected outcome {:?}", other),
+            }
+        }
+
+        // Hints are looked up in the tablebase of the computer player
+        let mut output = Vec::new();
+        let player = TablebasePlayer::new(Arc::clone(&tablebase));
+        program::run(program::Game::new(), Box::new(HumanPlayer), Box::new(player), "h\n".as_bytes(), &mut output).unwrap();
+        let hints: Vec<String> = tablebase.analyze(&program::Game::new()).unwrap().into_iter()
+            .map(|((row, col), evaluation)| format!("{} {}", program::format_move(row, col), evaluation))
+            .collect();
+        assert!(String::from_utf8(output).unwrap().contains(&format!("Hint: {}\n", hints.join(", "))));
+    }
+
+    #[test]
+    fn opening_book_from_analysis() {
+        use program::ai::{self, Evaluation};
+        use program::book::OpeningBook;
+        let book = OpeningBook::from_analysis(&program::Game::new(), 2, ai::analyze);
+        assert_eq!(OpeningBook::parse(&book.to_string()).unwrap(), book);
+
+        // Every book move keeps the draw, in every variant of the position
+        let mut game = program::Game::new();
+        game.make_move(0, 0).unwrap();
+        let moves = book.moves(&game);
+        assert_eq!(moves, vec![((1, 1), 1)]);
+        for (row, col) in game.legal_moves() {
+            game.make_move(row, col).unwrap();
+            for ((row, col), _) in book.moves(&game) {
+                let evaluation = ai::analyze(&game).into_iter().find(|&(mv, _)| mv == (row, col)).unwrap().1;
+                assert_eq!(evaluation, Evaluation::Draw);
+            }
+            game.undo();
+        }
+        // Positions after more than two moves aren't in the book
+        game.make_move(1, 1).unwrap();
+        game.make_move(2, 2).unwrap();
+        assert_eq!(book.pick(&game, 0), None);
+    }
+
+    #[test]
+    fn opening_book_from_games() {
+        use program::book::{BookError, OpeningBook};
+        use program::player::{BookPlayer, HumanPlayer, Player, RandomPlayer};
+        use std::sync::Arc;
+        // x wins, so only x's moves are added, and they are worth 2 each
+        let mut game = program::Game::new();
+        for &(row, col) in &[(0, 0), (1, 0), (0, 1), (1, 1), (0, 2)] {
+            game.make_move(row, col).unwrap();
+        }
+        let mut book = OpeningBook::new(&game);
+        book.add_game(&game, 3);
+        book.add_game(&program::Game::new(), 3);
+        assert_eq!(book.len(), 2);
+        assert_eq!(book.moves(&program::Game::new()), vec![((0, 0), 2)]);
+
+        // A hand-written book works for every variant of its positions
+        let book = OpeningBook::parse("[Variant \"3x3k3\"]\n\n# The corner\n3/3/3 x: 1A 3, 2B 1\n").unwrap();
+        let mut players = [
+            BookPlayer::new(Arc::new(book), Box::new(RandomPlayer::with_seed(1)), 5),
+            BookPlayer::new(Arc::new(OpeningBook::new(&game)), Box::new(RandomPlayer::with_seed(1)), 5),
+        ];
+        let first = players[0].choose_move(&program::Game::new()).unwrap();
+        assert!(first == (0, 0) || first == (1, 1));
+        assert_eq!(players[1].name(), "Random");
+
+        // A person behind the book is told about the book's moves and still types in their own,
+        // so closing the input ends the game instead of waiting for a move
+        let book = OpeningBook::parse("[Variant \"3x3k3\"]\n3/3/3 x: 2B 1\n").unwrap();
+        let person = BookPlayer::new(Arc::new(book), Box::new(HumanPlayer), 5);
+        assert!(person.is_interactive());
+        let mut output = Vec::new();
+        let outcome = program::run(program::Game::new(), Box::new(person), Box::new(HumanPlayer), "1A\n".as_bytes(), &mut output).unwrap();
+        assert!(matches!(outcome, program::Outcome::InputClosed));
+        assert!(String::from_utf8(output).unwrap().contains("The book plays: 2B"));
+
+        match OpeningBook::parse("[Variant \"3x3k3\"]\n3/3/3 x: 4A 1\n") {
+            Err(BookError::Syntax {line: 2, ..}) => {},
+            other => panic!("expected a syntax error, got {:?}", other),
+        }
+        // Weights for the same move are added up without overflowing
+        let book = OpeningBook::parse("[Variant \"3x3k3\"]\n3/3/3 x: 1A 4294967295, 1A 1\n").unwrap();
+        assert_eq!(book.moves(&program::Game::new()), vec![((0, 0), u32::MAX)]);
+    }
+
+    #[test]
+    fn parse_move_errors() {
+        use program::{format_move, parse_move, InvalidMove};
+        assert_eq!(parse_move("12c", 12, 3), Ok((11, 2)));
+        assert_eq!(format_move(11, 2), "12C");
+        assert_eq!(parse_move("A", 3, 3), Err(InvalidMove::WrongLength {input: "A".to_string()}));
+        assert_eq!(parse_move("0A", 3, 3), Err(InvalidMove::BadRow {input: "0A".to_string(), rows: 3}));
+        assert_eq!(parse_move("+1A", 3, 3), Err(InvalidMove::BadRow {input: "+1A".to_string(), rows: 3}));
+        assert_eq!(parse_move("1D", 3, 3), Err(InvalidMove::BadColumn {input: "1D".to_string(), cols: 3}));
+        assert_eq!(parse_move("1é", 3, 3), Err(InvalidMove::BadColumn {input: "1é".to_string(), cols: 3}));
+        assert_eq!(parse_move("1D", 3, 3).unwrap_err().to_string(),
+            "Invalid move: '1D'. The columns are lettered from A to C");
+        assert_eq!(parse_move("1A", 3, 0).unwrap_err().to_string(), "Invalid move: '1A'. The board has no columns");
+    }
+
+    #[test]
+    fn moves_off_the_board_display() {
+        use program::{format_move, Game, MoveError};
+        // Columns past Z have no letter, so they are written as numbers
+        assert_eq!(format_move(0, 25), "1Z");
+        assert_eq!(format_move(0, 26), "row 1, column 27");
+        assert_eq!(format_move(usize::MAX, usize::MAX), format!("row {}, column {}", usize::MAX as u128 + 1, usize::MAX as u128 + 1));
+        assert_eq!(Game::connect_four().drop_piece(200).unwrap_err().to_string(), "There is no column 201 on the board");
+        assert_eq!(Game::connect_four().drop_piece(7).unwrap_err(), MoveError::InvalidColumn {col: 7});
+        assert_eq!(Game::connect_four().drop_piece(7).unwrap_err().to_string(), "There is no column H on the board");
+    }
+
+    #[test]
+    fn move_errors_display() {
+        use program::{Game, MoveError};
+        let mut game = Game::new();
+        game.make_move(0, 0).unwrap();
+        // The `?` operator works with any error type that implements std::error::Error
+        let error: Box<dyn std::error::Error> = game.make_move(0, 0).unwrap_err().into();
+        assert_eq!(error.to_string(), "The tile at position 1A already has piece x in it");
+        assert_eq!(MoveError::ColumnFull {col: 6}.to_string(), "Column G is already full");
+        assert_eq!(MoveError::GameAlreadyOver.to_string(), "The game is already over");
+    }
+
+    #[test]
+    fn input_closed_before_game_finished() {
+        use program::player::{ComputerPlayer, HumanPlayer};
+        let mut output = Vec::new();
+        let outcome = program::run(program::Game::new(), Box::new(HumanPlayer), Box::new(ComputerPlayer::new()),
+            &b"2B\n"[..], &mut output).unwrap();
+        match outcome {
+            program::Outcome::InputClosed => {},
+            other => panic!("expected the input to be closed, got {:?}", other),
+        }
+        assert!(String::from_utf8(output).unwrap().contains("Computer plays: "));
+    }
+
+    #[test]
+    fn position_string_round_trip() {
+        let mut game = program::Game::new();
+        game.make_move(0, 0).unwrap();
+        game.make_move(1, 1).unwrap();
+        game.make_move(2, 1).unwrap();
+        assert_eq!(game.to_position_string(), "x2/1o1/1x1 o 3x3k3");
+
+        let copy = program::Game::from_position_string(&game.to_position_string()).unwrap();
+        assert_eq!(copy.tiles(), game.tiles());
+        assert_eq!(copy.current_piece(), program::Piece::O);
+        assert!(copy.winner().is_none());
+        assert!(copy.history().is_empty());
+
+        let mut game = program::Game::with_gravity_config(8, 12, 4).unwrap();
+        game.drop_piece(11).unwrap();
+        assert_eq!(game.to_position_string(), "12/12/12/12/12/12/12/11x o 8x12k4g");
+        let copy = program::Game::from_position_string(&game.to_position_string()).unwrap();
+        assert_eq!(copy.tiles(), game.tiles());
+        assert!(copy.has_gravity());
+    }
+
+    #[test]
+    fn position_string_recomputes_winner() {
+        use program::{Game, Winner};
+        // The rules field can be left out for Connect Four
+        let mut game = Game::from_position_string("7/7/7/7/o1o4/xxx3o x").unwrap();
+        assert!(game.has_gravity());
+        assert!(game.winner().is_none());
+        game.drop_piece(3).unwrap();
+        assert_eq!(game.winner(), Some(Winner::X));
+
+        let game = Game::from_position_string("xxx/oo1/3 o").unwrap();
+        assert_eq!(game.winner(), Some(Winner::X));
+        let game = Game::from_position_string("xox/xox/oxo o").unwrap();
+        assert_eq!(game.winner(), Some(Winner::Tie));
+    }
+
+    #[test]
+    fn invalid_position_strings() {
+        use program::{ConfigError, Game, PositionError};
+        assert_eq!(Game::from_position_string("3/3/3").unwrap_err(), PositionError::WrongFieldCount {fields: 1});
+        assert_eq!(Game::from_position_string("3/3/2 x").unwrap_err(), PositionError::UnevenRows);
+        assert_eq!(Game::from_position_string("3/3/3 z").unwrap_err(),
+            PositionError::InvalidSide {found: "z".to_string()});
+        assert_eq!(Game::from_position_string("4/4/4/4 x").unwrap_err(), PositionError::MissingRules {rows: 4, cols: 4});
+        assert_eq!(Game::from_position_string("3/3/3 x 4x4k3").unwrap_err(),
+            PositionError::SizeMismatch {rows: 4, cols: 4});
+        assert_eq!(Game::from_position_string("xx1/3/3 o").unwrap_err(), PositionError::PieceCount {x: 2, o: 0});
+        assert_eq!(Game::from_position_string("x2/3/3 x").unwrap_err(), PositionError::WrongSideToMove);
+        assert_eq!(Game::from_position_string("7/7/7/7/x6/o6 x k4g").unwrap().tiles(),
+            Game::from_position_string("7/7/7/7/x6/o6 x").unwrap().tiles());
+        assert_eq!(Game::from_position_string("7/7/7/x6/7/o6 x").unwrap_err(),
+            PositionError::FloatingPiece {row: 3, col: 0});
+        // Both players can't have won
+        assert_eq!(Game::from_position_string("xxx/ooo/3 x").unwrap_err(), PositionError::ImpossibleWin);
+        // X can't have made two separate lines with one move
+        assert_eq!(Game::from_position_string("xxx2/oo3/3o1/oo3/xxx2 o 5x5k3").unwrap_err(), PositionError::ImpossibleWin);
+        // Huge rows are rejected before any tiles are made
+        assert_eq!(Game::from_position_string("99999999999999999 x").unwrap_err(),
+            PositionError::InvalidConfig(ConfigError::TooManyColumns {cols: 99999999999999999}));
+        assert_eq!(Game::from_position_string("x26 o").unwrap_err(),
+            PositionError::InvalidConfig(ConfigError::TooManyColumns {cols: 27}));
+        assert_eq!(Game::from_rules_string("100000000000x26k3").unwrap_err(),
+            PositionError::InvalidConfig(ConfigError::TooManyRows {rows: 100000000000}));
+        assert_eq!(Game::from_position_string(&format!("{} x 101x1k1", vec!["1"; 101].join("/"))).unwrap_err(),
+            PositionError::InvalidConfig(ConfigError::TooManyRows {rows: 101}));
+    }
+
+    #[test]
+    fn game_record_round_trip() {
+        use program::record::{self, GameRecord};
+        let mut game = program::Game::connect_four();
+        for &col in &[3, 3, 4, 4, 5, 5, 6] {
+            game.drop_piece(col).unwrap();
+        }
+        let mut record = GameRecord::new(&game);
+        // Names from engines can have anything in them, including the characters that end a header
+        record.set_header("X", "Alice \"The Bot\" [v2] \\");
+        record.set_header("O", "Bob");
+        record.moves[1].comment = Some("should have blocked {or not}".to_string());
+        assert_eq!(record.header("Variant"), Some("6x7k4g"));
+        assert_eq!(record.header("Result"), Some("x"));
+
+        let path = std::env::temp_dir().join("connect_rusty_record_round_trip.txt");
+        record::save(&record, &path).unwrap();
+        let loaded = record::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, record);
+
+        let replayed = loaded.to_game().unwrap();
+        assert_eq!(replayed.history(), game.history());
+        assert_eq!(replayed.winner(), Some(program::Winner::X));
+
+        // A game started from a position is replayed from that position
+        let mut game = program::Game::from_position_string("x2/1o1/3 x").unwrap();
+        game.make_move(0, 1).unwrap();
+        let record = GameRecord::new(&game);
+        assert_eq!(record.header("Position"), Some("x2/1o1/3 x"));
+        let replayed = GameRecord::parse(&record.to_string()).unwrap().to_game().unwrap();
+        assert_eq!(replayed.tiles(), game.tiles());
+        assert_eq!(replayed.history(), game.history());
+    }
+
+    #[test]
+    fn game_record_parse() {
+        use program::record::{GameRecord, RecordError};
+        let text = "[Variant \"3x3k3\"]\n[X \"Human\"]\n[Result \"*\"]\n\n2b 1A {a corner} {is fine}\n3c\n";
+        let record = GameRecord::parse(text).unwrap();
+        assert_eq!(record.header("X"), Some("Human"));
+        assert_eq!(record.moves.len(), 3);
+        assert_eq!((record.moves[0].row, record.moves[0].col), (1, 1));
+        assert_eq!(record.moves[1].comment.as_deref(), Some("a corner is fine"));
+        let game = record.to_game().unwrap();
+        assert_eq!(game.current_piece(), program::Piece::O);
+
+        match GameRecord::parse("\n1A 4A\n") {
+            Err(RecordError::InvalidMove {number: 2, text}) => assert_eq!(text, "4A"),
+            other => panic!("expected an invalid move, got {:?}", other),
+        }
+        match GameRecord::parse("1A 1A").unwrap().to_game() {
+            Err(RecordError::IllegalMove {number: 2, ..}) => {},
+            other => panic!("expected an illegal move, got {:?}", other),
+        }
+        match GameRecord::parse("[Result \"o\"]\n\n1A 2A 1B 2B 1C").unwrap().to_game() {
+            Err(RecordError::ResultMismatch {recorded, actual}) => assert_eq!((recorded.as_str(), actual.as_str()), ("o", "x")),
+            other => panic!("expected a result mismatch, got {:?}", other),
+        }
+        // The line is counted from the start of the file, not from the start of the moves
+        match GameRecord::parse("[X \"Human\"]\n\n1A {unfinished 2B\n3C") {
+            Err(error @ RecordError::Syntax {line: 3, ..}) => {
+                assert_eq!(error.to_string(), "Line 3: comment is missing a closing }");
+            },
+            other => panic!("expected a syntax error, got {:?}", other),
+        }
+        // A huge variant is turned down before a board is made for it
+        match GameRecord::parse("[Variant \"100000000000x26k3\"]\n\n1A") {
+            Err(RecordError::InvalidVariant(_)) => {},
+            other => panic!("expected an invalid variant, got {:?}", other),
+        }
+        match GameRecord::parse("[Position \"xx1/3/3 o\"]\n\n1C") {
+            Err(RecordError::InvalidPosition(_)) => {},
+            other => panic!("expected an invalid position, got {:?}", other),
+        }
+        let error = program::record::load("/nonexistent/connect_rusty.txt").unwrap_err();
+        assert!(!error.to_string().contains("Os {"));
+        assert_eq!(program::Game::from_position_string("3/3/3 z").unwrap_err().to_string(),
+            "The piece to move must be x or o, not 'z'");
+    }
+
+    // These tests only run with `cargo test --features serde`
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_json_round_trip() {
+        use program::{Game, MoveError, Piece, Winner};
+        let mut game = Game::new();
+        for &(row, col) in &[(0, 0), (1, 1), (0, 1), (2, 2)] {
+            game.make_move(row, col).unwrap();
+        }
+        game.undo();
+
+        let json = serde_json::to_value(&game).unwrap();
+        assert_eq!(json, serde_json::json!({
+            "rows": 3, "cols": 3, "win_length": 3, "gravity": false,
+            "tiles": [["x", "x", null], [null, "o", null], [null, null, null]],
+            "current_piece": "o",
+            "winner": null,
+            "history": [[0, 0], [1, 1], [0, 1]],
+            "undone": [[2, 2]],
+        }));
+
+        let mut loaded: Game = serde_json::from_value(json).unwrap();
+        assert_eq!(loaded.tiles(), game.tiles());
+        assert_eq!(loaded.history(), game.history());
+        assert_eq!(loaded.redo(), Some((2, 2)));
+
+        assert_eq!(serde_json::to_string(&Winner::Tie).unwrap(), "\"tie\"");
+        assert_eq!(serde_json::from_str::<Piece>("\"x\"").unwrap(), Piece::X);
+        let error = MoveError::ColumnFull {col: 3};
+        assert_eq!(serde_json::to_string(&error).unwrap(), r#"{"kind":"column_full","col":3}"#);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_rejects_inconsistent_games() {
+        use program::Game;
+        let game = |tiles: serde_json::Value, current: &str, winner: serde_json::Value, history: serde_json::Value| {
+            serde_json::from_value::<Game>(serde_json::json!({
+                "rows": 3, "cols": 3, "win_length": 3, "gravity": false,
+                "tiles": tiles, "current_piece": current, "winner": winner,
+                "history": history, "undone": [],
+            }))
+        };
+        let board = serde_json::json!([["x", "x", "x"], ["o", "o", null], [null, null, null]]);
+        assert!(game(board.clone(), "o", serde_json::json!("x"), serde_json::json!([])).is_ok());
+        // The winner has to match the board
+        assert!(game(board.clone(), "o", serde_json::json!(null), serde_json::json!([])).is_err());
+        // It isn't X's turn after X has played three times
+        assert!(game(board.clone(), "x", serde_json::json!("x"), serde_json::json!([])).is_err());
+        // The history has to lead to the board
+        assert!(game(board.clone(), "o", serde_json::json!("x"), serde_json::json!([[0, 0], [2, 2]])).is_err());
+        // The last move was made by X, so it can't be one of the o tiles
+        assert!(game(board, "o", serde_json::json!("x"), serde_json::json!([[1, 1], [0, 1], [1, 0]])).is_err());
+        // The tiles have to fit the board size
+        assert!(game(serde_json::json!([["x"]]), "o", serde_json::json!(null), serde_json::json!([])).is_err());
+        // Sizes that don't match the tiles are turned down before a board is made for them
+        let error = serde_json::from_value::<Game>(serde_json::json!({
+            "rows": 100000000000u64, "cols": 26, "win_length": 3, "gravity": true,
+            "tiles": [], "current_piece": "x", "winner": null, "history": [], "undone": [],
+        })).unwrap_err();
+        assert!(error.to_string().contains("the tiles don't make a board with 100000000000 rows"), "{}", error);
+    }
+
+    // Runs the connect-rusty program with the given arguments and returns its exit code and output.
+    // Cargo tells integration tests where it put the program using this environment variable.
+    fn run_binary(args: &[&str]) -> (i32, String) {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_connect-rusty"))
+            .args(args)
+            .stdin(std::process::Stdio::null())
+            .output()
+            .unwrap();
+        (output.status.code().unwrap(), String::from_utf8(output.stdout).unwrap())
+    }
+
+    #[test]
+    fn command_line_analyze_and_version() {
+        use program::tablebase::Tablebase;
+        let (code, output) = run_binary(&["analyze", "xx1/oo1/3 x"]);
+        assert_eq!(code, 0);
+        assert_eq!(output, "1C wins in 1\n2C draws\n3B loses in 2\n3A loses in 2\n3C loses in 2\n");
+
+        let (code, output) = run_binary(&["--version"]);
+        assert_eq!(code, 0);
+        assert_eq!(output, format!("connect-rusty {}\n", env!("CARGO_PKG_VERSION")));
+
+        assert_eq!(run_binary(&["analyze", "xx1/oo1/3 x", "--depth"]).0, 2);
+
+        // The options of analyze can come in either order. The tablebase knows the position, so
+        // --depth isn't needed for it.
+        let path = std::env::temp_dir().join("connect_rusty_command_line_analyze.tb");
+        Tablebase::generate(&program::Game::new()).unwrap().save(&path).unwrap();
+        let path = path.to_str().unwrap();
+        for args in [["--tablebase", path, "--depth", "1"], ["--depth", "1", "--tablebase", path]] {
+            let (code, output) = run_binary(&[&["analyze", "xx1/oo1/3 x"][..], &args].concat());
+            assert_eq!(code, 0);
+            assert_eq!(output, "1C wins in 1\n2C draws\n3B loses in 2\n3A loses in 2\n3C loses in 2\n");
+        }
+        // A position that isn't in the table is searched to --depth, and is an error without it
+        let searched = run_binary(&["analyze", "4/4/4/4 x", "--depth", "1"]);
+        assert_eq!(run_binary(&["analyze", "4/4/4/4 x", "--tablebase", path, "--depth", "1"]), searched);
+        assert_eq!(run_binary(&["analyze", "4/4/4/4 x", "--depth", "1", "--tablebase", path]), searched);
+        assert_eq!(run_binary(&["analyze", "4/4/4/4 x", "--tablebase", path]).0, 1);
+        // A computer player with a tablebase searches to --depth once the game leaves the table
+        let (code, output) = run_binary(&["play", "-x", "computer", "-o", "computer", "--variant", "4x4k3",
+            "--tablebase", path, "--depth", "2"]);
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(code, 0);
+        assert!(output.ends_with("wins!\n") || output.ends_with("Tie!\n"));
+        assert_eq!(run_binary(&["play", "-x", "nobody"]).0, 2);
+        assert_eq!(run_binary(&["fly"]).0, 2);
+    }
+
+    #[test]
+    fn command_line_play_and_replay() {
+        // Two computers play each other, so no input is needed
+        let (code, output) = run_binary(&["play", "-x", "computer", "-o", "computer", "--variant", "4x4k3"]);
+        assert_eq!(code, 0);
+        assert!(output.ends_with("x wins!\n"));
+        // A full search of Connect Four would never finish, so the computer searches less deeply
+        let (code, output) = run_binary(&["play", "-x", "computer", "-o", "computer", "--variant", "connect-four"]);
+        assert_eq!(code, 0);
+        assert!(output.ends_with("wins!\n") || output.ends_with("Tie!\n"));
+
+        let mut game = program::Game::new();
+        game.make_move(0, 0).unwrap();
+        let path = std::env::temp_dir().join("connect_rusty_command_line_replay.txt");
+        program::record::save(&program::record::GameRecord::new(&game), &path).unwrap();
+        let (code, output) = run_binary(&["replay", path.to_str().unwrap()]);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(code, 0);
+        assert!(output.contains("1. x plays 1A\n   A B C\n 1 x ▢ ▢\n"));
+    }
+
+    #[test]
+    fn engine_protocol_replies() {
+        use program::player::ComputerPlayer;
+        let input = "hello\nnewgame 4x4k3\nposition start moves 1A 4D\nbogus\nposition 4/4/4/4 o\nisready\nquit\ngo\n";
+        let mut output = Vec::new();
+        program::engine::serve(&mut ComputerPlayer::new(), "Perfect", input.as_bytes(), &mut output).unwrap();
+        // The position with o to move on an empty board is rejected and nothing after quit is read
+        assert_eq!(String::from_utf8(output).unwrap(), concat!(
+            "hello Perfect\n",
+            "error unknown command 'bogus'\n",
+            "error invalid position: The piece to move doesn\'t match the pieces on the board\n",
+            "readyok\n",
+        ));
+
+        // o has to block x in the top row
+        let input = "newgame\nposition x2/1o1/3 x moves 1B\ngo\nposition start moves 1A 1A\n";
+        let mut output = Vec::new();
+        program::engine::serve(&mut ComputerPlayer::new(), "Perfect", input.as_bytes(), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), concat!(
+            "bestmove 1C\n",
+            "error 1A can't be played: The tile at position 1A already has piece x in it\n",
+        ));
+
+        // Rules for a board too big to make are turned down, and the engine keeps going
+        let input = "newgame 100000000000x26k3\nisready\n";
+        let mut output = Vec::new();
+        program::engine::serve(&mut ComputerPlayer::new(), "Perfect", input.as_bytes(), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), concat!(
+            "error invalid rules '100000000000x26k3': A board can have at most 100 rows, not 100000000000\n",
+            "readyok\n",
+        ));
+    }
+
+    #[test]
+    fn engine_players_are_refereed() {
+        use program::engine::{EngineError, EnginePlayer};
+        use program::player::{BotFn, Player, RandomPlayer};
+        use program::{MoveError, Outcome, Piece, Winner};
+        let engine = |args: &[&str]| {
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            EnginePlayer::spawn(env!("CARGO_BIN_EXE_connect-rusty"), &args).unwrap()
+        };
+
+        // The built-in engine never loses
+        for seed in 0..3 {
+            let outcome = run_bots(Box::new(RandomPlayer::with_seed(seed)), Box::new(engine(&["engine"])));
+            assert!(matches!(outcome, Outcome::Finished(Winner::O) | Outcome::Finished(Winner::Tie)));
+        }
+
+        // An engine that always answers 1A forfeits as soon as that tile is taken
+        let script = "while read command rest; do case $command in
+            hello) echo hello Stubborn;; go) echo bestmove 1A;; quit) exit;; esac; done";
+        let stubborn = EnginePlayer::spawn("sh", &["-c".to_string(), script.to_string()]).unwrap();
+        assert_eq!(stubborn.name(), "Stubborn");
+        let first = BotFn::new("First", |game: &program::Game| game.legal_moves()[0]);
+        let outcome = run_bots(Box::new(first), Box::new(stubborn));
+        match outcome {
+            Outcome::Forfeit(forfeit) => {
+                assert_eq!(forfeit.piece, Piece::O);
+                assert!(matches!(forfeit.error, MoveError::TileNotEmpty {other_piece: Piece::X, row: 0, col: 0}));
+            },
+            other => panic!("unexpected outcome {:?}", other),
+        }
+
+        // A reply that isn't a move at all forfeits too
+        let script = "while read command rest; do case $command in
+            hello) echo hello;; go) echo bestmove ??;; quit) exit;; esac; done";
+        let confused = EnginePlayer::spawn("sh", &["-c".to_string(), script.to_string()]).unwrap();
+        let outcome = run_bots(Box::new(confused), Box::new(RandomPlayer::with_seed(1)));
+        match outcome {
+            Outcome::Forfeit(forfeit) => {
+                assert_eq!(forfeit.piece, Piece::X);
+                assert_eq!(forfeit.error, MoveError::Unreadable {text: "??".to_string()});
+                assert_eq!(forfeit.error.to_string(), "'??' is not a move");
+            },
+            other => panic!("unexpected outcome {:?}", other),
+        }
+
+        // An engine that never answers `go` is given up on once its time is up
+        let script = "while read command rest; do case $command in
+            hello) echo hello Sleepy;; quit) exit;; esac; done";
+        let timeout = std::time::Duration::from_millis(200);
+        let mut sleepy = EnginePlayer::spawn_with_timeout("sh", &["-c".to_string(), script.to_string()], timeout).unwrap();
+        match sleepy.request_move(&program::Game::new()) {
+            Err(error @ EngineError::Timeout(_)) => assert_eq!(error.to_string(), "the engine didn't reply within 200ms"),
+            other => panic!("expected a timeout, got {:?}", other),
+        }
+        match run_bots(Box::new(sleepy), Box::new(RandomPlayer::with_seed(1))) {
+            Outcome::Forfeit(forfeit) => {
+                assert_eq!(forfeit.error, MoveError::TimedOut {millis: 200});
+                assert_eq!(forfeit.error.to_string(), "No move was made within 200ms");
+            },
+            other => panic!("expected a forfeit, got {:?}", other),
+        }
+
+        let script = "while read command rest; do case $command in
+            hello) echo hello;; go) echo bestmove ??;; quit) exit;; esac; done";
+        // The engine command passes on what went wrong with the engine it wraps instead of stopping
+        let path = std::env::temp_dir().join("connect_rusty_confused_engine.sh");
+        std::fs::write(&path, script).unwrap();
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_connect-rusty"))
+            .args(["engine", "--player", &format!("engine:sh {}", path.display())])
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .and_then(|mut child| {
+                use std::io::Write;
+                child.stdin.take().unwrap().write_all(b"go\nisready\n")?;
+                child.wait_with_output()
+            })
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "error '??' is not a move\nreadyok\n");
+    }
+
+    #[test]
+    fn tournament_between_bots() {
+        use program::player::{ComputerPlayer, Player, RandomPlayer};
+        use program::tournament::{self, ForfeitReason, Format, Score, Settings, Sprt, SprtResult};
+        let names = vec!["computer".to_string(), "random".to_string(), "random 2".to_string()];
+        let new_player = |index: usize, seed: u64| -> Result<Box<dyn Player>, String> {
+            match index {
+                0 => Ok(Box::new(ComputerPlayer::new())),
+                _ => Ok(Box::new(RandomPlayer::with_seed(seed))),
+            }
+        };
+        // A random opening could leave the computer in a lost position, so the games start on an
+        // empty board
+        let settings = Settings {format: Format::Gauntlet, games: 10, opening_plies: 0, threads: 3, seed: 5, ..Settings::default()};
+        let results = tournament::run(&program::Game::new(), &names, &settings, new_player);
+        assert_eq!(results.games(), 20);
+        for opponent in 1..3 {
+            let score = results.score(0, opponent);
+            assert_eq!((score.games(), score.losses), (10, 0));
+            assert_eq!(results.score(opponent, 0), score.reversed());
+        }
+        // The random players only play the computer in a gauntlet
+        assert_eq!(results.score(1, 2), Score::default());
+        // Every game comes from the seed, so the threads don't change the results
+        let one_thread = Settings {threads: 1, ..settings.clone()};
+        assert_eq!(tournament::run(&program::Game::new(), &names, &one_thread, new_player), results);
+
+        // The test stops as soon as the computer is clearly stronger
+        let sprt = Settings {games: 1000, sprt: Some(Sprt::new(0.0, 50.0)), ..settings};
+        let results = tournament::run(&program::Game::new(), &names[..2], &sprt, new_player);
+        assert_eq!(results.sprt().unwrap().1, SprtResult::H1);
+        assert!(results.games() < 1000);
+
+        // A bot that always plays the same square forfeits its games, and they are listed
+        struct Stuck;
+        impl Player for Stuck {
+            fn choose_move(&mut self, _game: &program::Game) -> Option<(usize, usize)> {
+                Some((0, 0))
+            }
+        }
+        let new_player = |index: usize, seed: u64| -> Result<Box<dyn Player>, String> {
+            match index {
+                0 => Ok(Box::new(Stuck)),
+                _ => Ok(Box::new(RandomPlayer::with_seed(seed))),
+            }
+        };
+        let settings = Settings {games: 2, threads: 1, ..Settings::default()};
+        let results = tournament::run(&program::Game::new(), &names[..2], &settings, new_player);
+        assert_eq!(results.score(0, 1).losses, 2);
+        assert_eq!(results.forfeits().len(), 2);
+        assert!(results.forfeits().iter().all(|forfeit| forfeit.player == 0 && matches!(forfeit.reason, ForfeitReason::InvalidMove(_))));
+        assert!(results.to_string().contains("Forfeits\ncomputer (x) against random: The tile at position 1A"), "{}", results);
+
+        // A player that can't be created forfeits instead of stopping the tournament
+        let new_player = |index: usize, seed: u64| -> Result<Box<dyn Player>, String> {
+            match index {
+                0 => Ok(Box::new(RandomPlayer::with_seed(seed))),
+                _ => Err("the engine is gone".to_string()),
+            }
+        };
+        let results = tournament::run(&program::Game::new(), &names[..2], &settings, new_player);
+        assert_eq!(results.score(1, 0).losses, 2);
+        assert_eq!(results.forfeits()[0].reason, ForfeitReason::NotStarted("the engine is gone".to_string()));
+        assert!(results.to_string().contains("random (o) against computer: could not start: the engine is gone"), "{}", results);
+    }
+
+    #[test]
+    fn elo_and_sprt() {
+        use program::tournament::{elo_difference, expected_score, Score, Sprt, SprtResult};
+        assert!((elo_difference(0.75) - 190.85).abs() < 0.01);
+        assert!((expected_score(elo_difference(0.3)) - 0.3).abs() < 1e-9);
+
+        let (elo, margin) = Score {wins: 60, draws: 20, losses: 20}.elo().unwrap();
+        assert!((elo - 147.19).abs() < 0.01);
+        assert!(margin > 50.0 && margin < 100.0);
+        assert_eq!(Score {wins: 3, draws: 0, losses: 0}.elo(), None);
+
+        let sprt = Sprt::new(0.0, 50.0);
+        assert_eq!(sprt.result(&Score {wins: 60, draws: 20, losses: 20}), SprtResult::H1);
+        assert_eq!(sprt.result(&Score {wins: 400, draws: 200, losses: 400}), SprtResult::H0);
+        assert_eq!(sprt.result(&Score {wins: 11, draws: 10, losses: 10}), SprtResult::Undecided);
+    }
 }